@@ -3,13 +3,15 @@ extern crate sdl2;
 extern crate lodepng;
 extern crate cgmath;
 extern crate serialize;
+extern crate time;
 
 use sdl2::video::{Window, PosCentered, OPENGL};
 use std::sync::Arc;
 use std::comm;
+use std::default::Default;
 use cgmath::*;
 use image_types::{ScreenPoint, Pixel, Rect, ImageIter, Color};
-use scene::Scene;
+use scene::{Scene, TraversalStats};
 
 mod image_types;
 mod scene;
@@ -59,6 +61,7 @@ fn main() {
     }
 
     let mut counter = 0u;
+    let mut stats = TraversalStats::default();
     let mut jobs = ImageIter::for_image_dimensions(W, H);
         for _ in range(0, num_threads) {
         let job = jobs.next();
@@ -71,7 +74,8 @@ fn main() {
         }
     }
     loop {
-        let (rect, pixels) = rx.recv();
+        let (rect, pixels, worker_stats) = rx.recv();
+        stats = stats.add(&worker_stats);
         for (point, pixel) in rect.iter().zip(pixels.iter()) {
             let index : uint = ((point.x + point.y * W) * 3) as uint;
             data[index + 0] = pixel.r;
@@ -88,6 +92,7 @@ fn main() {
         }
         if counter == 0 { break };
     }
+    scene::print_traversal_stats(&stats);
         
     let path = &Path::new("test.png");
     match lodepng::encode_file(path, data.as_slice(), W, H, lodepng::LCT_RGB, 8) {
@@ -98,7 +103,7 @@ fn main() {
     sdl2::quit();
 }
 
-fn new_worker(tx: &Sender<(Rect, Vec<Pixel>)>, rect: Rect, scene: Arc<Scene>) {
+fn new_worker(tx: &Sender<(Rect, Vec<Pixel>, TraversalStats)>, rect: Rect, scene: Arc<Scene>) {
     let proc_tx = tx.clone();
     spawn(proc() {
         let num_pixels = rect.width as uint * rect.height as uint;
@@ -106,7 +111,7 @@ fn new_worker(tx: &Sender<(Rect, Vec<Pixel>)>, rect: Rect, scene: Arc<Scene>) {
         for point in rect.iter() {
             pixels.push(generate_pixel(point, &scene));
         }
-        proc_tx.send((rect, pixels));
+        proc_tx.send((rect, pixels, scene::take_traversal_stats()));
     });
 }
 
@@ -120,7 +125,6 @@ fn pixel_mapping(point: ScreenPoint) -> (f32, f32) {
     (x, -y)
 }
 
-const CAMERA_POS: Point3<f32> = Point3 {x: 0.0, y: -2.0, z: 0.0};
 const N_SAMPLES: uint = 9;
 fn generate_pixel(point: ScreenPoint, scene: &Arc<Scene>) -> Pixel {
     let mut c = Color { r: 0.0, g: 0.0, b: 0.0 };
@@ -129,8 +133,7 @@ fn generate_pixel(point: ScreenPoint, scene: &Arc<Scene>) -> Pixel {
 
     for point in samples.iter() {
         let (x, y) = *point;
-        let view_direction = Vector3::new(x, 1.0f32, y).normalize();
-        let view_ray = Ray::new(CAMERA_POS, view_direction);
+        let view_ray = scene.camera.ray(x, y);
         c = c.add_c(&scene.trace_ray(&view_ray, 0));
     }
     c = c.mul_s(1.0/N_SAMPLES as f32).saturate();
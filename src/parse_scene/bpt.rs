@@ -0,0 +1,46 @@
+use std::io::File;
+use cgmath::Point3;
+
+/// Reads Bézier patches in the `.bpt` format the Utah teapot is usually
+/// shared in: the number of patches, then for each patch its degree in u
+/// and v, followed by the x, y and z of its control points row by row.
+/// Only bicubic patches, of degree 3 3, are supported.
+pub fn read_bpt(path: &Path) -> Vec<[Point3<f32>, ..16]> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(contents) => contents,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let numbers: Vec<f32> = contents.as_slice().words().map(|word| {
+        match from_str::<f32>(word) {
+            Some(number) => number,
+            None => fail!("{}: '{}' isn't a number", path.display(), word)
+        }
+    }).collect();
+    if numbers.is_empty() {
+        fail!("{}: File doesn't have the number of patches", path.display());
+    }
+
+    let count = numbers[0];
+    if count < 0.0 || count.fract() != 0.0 {
+        fail!("{}: {} isn't a number of patches", path.display(), count);
+    }
+    let count = count as uint;
+    // Each patch is its two degrees and 16 points of x, y and z
+    if numbers.len() != 1 + count * 50 {
+        fail!("{}: File has {} numbers, but {} patches need {}",
+              path.display(), numbers.len(), count, 1 + count * 50);
+    }
+    let mut patches = Vec::with_capacity(count);
+    for (i, patch) in numbers.slice_from(1).chunks(50).enumerate() {
+        if patch[0] != 3.0 || patch[1] != 3.0 {
+            fail!("{}: Patch {} is degree {} by {}, only bicubic patches are supported",
+                  path.display(), i, patch[0], patch[1]);
+        }
+        let mut points = [Point3::new(0.0f32, 0.0, 0.0), ..16];
+        for (point, p) in points.iter_mut().zip(patch.slice_from(2).chunks(3)) {
+            *point = Point3::new(p[0], p[1], p[2]);
+        }
+        patches.push(points);
+    }
+    patches
+}
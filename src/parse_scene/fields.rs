@@ -0,0 +1,109 @@
+use scene::{DistanceField, SphereField, RoundedBoxField, TorusField, CapsuleField, Mandelbulb};
+use scene::{SmoothUnion, SmoothSubtraction, SmoothIntersection, Repetition};
+use serialize::json::{Json, JsonObject};
+use cgmath::Vector3;
+use parse_scene::objects::{find_number, find_vector, vector_from_json};
+
+/// Reads the distance field of an "sdf" object.
+pub fn field_from_json(json: &Json) -> Box<DistanceField+Send+Sync> {
+    let field = json.as_object()
+                    .expect("Distance field isn't a JSON object");
+    let field_type = field.find(&"type".to_string())
+                          .expect("Distance field doesn't have a type")
+                          .as_string()
+                          .expect("Distance field type isn't a string");
+    match field_type {
+        "sphere"              => sphere_from_json(field),
+        "rounded box"         => rounded_box_from_json(field),
+        "torus"               => torus_from_json(field),
+        "capsule"             => capsule_from_json(field),
+        "smooth union"        => blend_from_json(field, field_type),
+        "smooth subtraction"  => blend_from_json(field, field_type),
+        "smooth intersection" => blend_from_json(field, field_type),
+        "repetition"          => repetition_from_json(field),
+        "mandelbulb"          => mandelbulb_from_json(field),
+        x                     => fail!("Unsupported distance field type '{}'", x)
+    }
+}
+
+fn sphere_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let pos = find_vector(field, "position", "Sphere field");
+    let radius = find_number(field, "radius", "Sphere field");
+    box SphereField::new((pos.x, pos.y, pos.z), radius)
+}
+
+fn rounded_box_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let pos = find_vector(field, "position", "Rounded box field");
+    let size = find_vector(field, "size", "Rounded box field");
+    let radius = find_number(field, "radius", "Rounded box field");
+    box RoundedBoxField::new((pos.x, pos.y, pos.z), (size.x, size.y, size.z), radius)
+}
+
+fn torus_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let pos = find_vector(field, "position", "Torus field");
+    let axis = match field.find(&"axis".to_string()) {
+        Some(json) => vector_from_json(json, "Torus field axis"),
+        None => Vector3::unit_z()
+    };
+    let major_radius = find_number(field, "major radius", "Torus field");
+    let minor_radius = find_number(field, "minor radius", "Torus field");
+    box TorusField::new((pos.x, pos.y, pos.z), (axis.x, axis.y, axis.z), major_radius, minor_radius)
+}
+
+fn capsule_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let start = find_vector(field, "start", "Capsule field");
+    let end = find_vector(field, "end", "Capsule field");
+    let radius = find_number(field, "radius", "Capsule field");
+    box CapsuleField::new((start.x, start.y, start.z), (end.x, end.y, end.z), radius)
+}
+
+/// Blends the list of "fields" together in turn. Subtraction carves all
+/// the others out of the first one.
+fn blend_from_json(field: &JsonObject, blend: &str) -> Box<DistanceField+Send+Sync> {
+    let smoothness = find_number(field, "smoothness", "Smooth blend");
+    let fields = field.find(&"fields".to_string())
+                      .expect("Smooth blend doesn't have fields")
+                      .as_list()
+                      .expect("Smooth blend fields aren't a list");
+    if fields.is_empty() {
+        fail!("Smooth blend doesn't have any fields");
+    }
+    let mut fields = fields.iter().map(|json| field_from_json(json));
+    let first = fields.next().unwrap();
+    fields.fold(first, |a, b| {
+        match blend {
+            "smooth union" => box SmoothUnion::new(a, b, smoothness) as Box<DistanceField+Send+Sync>,
+            "smooth subtraction" => box SmoothSubtraction::new(a, b, smoothness) as Box<DistanceField+Send+Sync>,
+            _ => box SmoothIntersection::new(a, b, smoothness) as Box<DistanceField+Send+Sync>
+        }
+    })
+}
+
+fn repetition_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let repeated = field_from_json(field.find(&"field".to_string())
+                                        .expect("Repetition doesn't have a field"));
+    let period = find_vector(field, "period", "Repetition");
+    // Without a limit the copies go on forever
+    let limit = field.find(&"limit".to_string()).map(|json| {
+        let limit = vector_from_json(json, "Repetition limit");
+        (limit.x, limit.y, limit.z)
+    });
+    box Repetition::new(repeated, (period.x, period.y, period.z), limit)
+}
+
+fn mandelbulb_from_json(field: &JsonObject) -> Box<DistanceField+Send+Sync> {
+    let pos = find_vector(field, "position", "Mandelbulb");
+    let scale = match field.find(&"scale".to_string()) {
+        Some(json) => json.as_f64().expect("Mandelbulb scale isn't a number") as f32,
+        None => 1.0
+    };
+    let power = match field.find(&"power".to_string()) {
+        Some(json) => json.as_f64().expect("Mandelbulb power isn't a number") as f32,
+        None => 8.0
+    };
+    let iterations = match field.find(&"iterations".to_string()) {
+        Some(json) => json.as_u64().expect("Mandelbulb iterations isn't a number") as uint,
+        None => 10
+    };
+    box Mandelbulb::new((pos.x, pos.y, pos.z), scale, power, iterations)
+}
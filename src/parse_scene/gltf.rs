@@ -0,0 +1,692 @@
+use std::io::File;
+use std::mem::transmute;
+use std::str;
+use std::sync::Arc;
+use serialize::json;
+use serialize::json::{Json, JsonObject, JsonList};
+use serialize::base64::FromBase64;
+use cgmath::{EuclideanVector, Matrix, Vector};
+use cgmath::{Matrix4, Vector3, Vector4, Point3};
+use image_types::Color;
+use scene::{SceneObject, SceneLight, PointLight, DirectionalLight, Illuminator};
+use scene::{Material, Texture, Camera, TriangleMesh, BvhParams};
+use parse_scene::images::decode_color;
+use parse_scene::objects::transform_from_json;
+
+/// Everything brought in from the glTF files listed in "assets".
+pub struct GltfAsset {
+    pub objects: Vec<SceneObject>,
+    pub lights: Vec<SceneLight>,
+    pub cameras: Vec<Camera>
+}
+
+/// A loaded glTF file, along with the materials built from it.
+struct Document<'a> {
+    json: &'a JsonObject,
+    path: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Arc<Material>>,
+    /// Which set of texture coordinates each material's texture uses
+    texture_sets: Vec<uint>,
+    default_material: Arc<Material>
+}
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_JSON_CHUNK: u32 = 0x4E4F534A;
+const GLB_BIN_CHUNK: u32 = 0x004E4942;
+
+/// Loads every asset in the "assets" list. Each one is a .gltf or .glb
+/// "file", placed in the scene by its optional "transform".
+pub fn parse_assets(assets_json: &Json, base_dir: &Path, bvh_params: &BvhParams) -> GltfAsset {
+    let assets = assets_json.as_list()
+                            .expect("Assets isn't a list");
+    let mut all = GltfAsset { objects: Vec::new(), lights: Vec::new(), cameras: Vec::new() };
+    for asset_json in assets.iter() {
+        let asset = asset_json.as_object()
+                              .expect("Asset isn't a JSON object");
+        let file = asset.find(&"file".to_string())
+                        .expect("Asset doesn't have a file")
+                        .as_string()
+                        .expect("Asset file isn't a string");
+        let to_world = match asset.find(&"transform".to_string()) {
+            Some(transform_json) => transform_from_json(transform_json),
+            None => Matrix4::identity()
+        };
+        let GltfAsset { objects, lights, cameras } = read_gltf(&base_dir.join(file), &to_world,
+                                                               bvh_params);
+        all.objects.extend(objects.into_iter());
+        all.lights.extend(lights.into_iter());
+        all.cameras.extend(cameras.into_iter());
+    }
+    all
+}
+
+/// Loads the default scene of a glTF file, with meshes baked into world
+/// space. glTF is y up, so everything is turned to be z up first.
+pub fn read_gltf(path: &Path, to_world: &Matrix4<f32>, bvh_params: &BvhParams) -> GltfAsset {
+    let contents = match File::open(path).read_to_end() {
+        Ok(contents) => contents,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let (text, binary) = if contents.len() >= 4 && read_u32(contents.as_slice(), 0) == GLB_MAGIC {
+        split_glb(contents.as_slice(), path)
+    } else {
+        (contents, None)
+    };
+    let text = str::from_utf8(text.as_slice())
+                   .expect(format!("{}: glTF JSON isn't UTF-8", path.display()).as_slice());
+    let json = match json::from_str(text) {
+        Ok(json) => json,
+        Err(err) => fail!("{}: {}", path.display(), err)
+    };
+    let root = json.as_object()
+                   .expect(format!("{}: Top level isn't a JSON object", path.display()).as_slice());
+
+    let mut document = Document { json: root,
+                                  path: path,
+                                  buffers: Vec::new(),
+                                  materials: Vec::new(),
+                                  texture_sets: Vec::new(),
+                                  default_material: Arc::new(default_material()) };
+    document.buffers = load_buffers(root, path, binary);
+    document.load_materials();
+
+    let y_up = Matrix4::new(1.0, 0.0, 0.0, 0.0,
+                            0.0, 0.0, 1.0, 0.0,
+                            0.0, -1.0, 0.0, 0.0,
+                            0.0, 0.0, 0.0, 1.0);
+    let root_matrix = to_world.mul_m(&y_up);
+    let mut asset = GltfAsset { objects: Vec::new(), lights: Vec::new(), cameras: Vec::new() };
+    for node in document.root_nodes().into_iter() {
+        document.visit(node, &root_matrix, &mut asset, bvh_params);
+    }
+    asset
+}
+
+fn read_u32(data: &[u8], offset: uint) -> u32 {
+    // glTF binary data is always little endian
+    range(0, 4).rev().fold(0u32, |bits, i| (bits << 8) | data[offset + i] as u32)
+}
+
+/// Splits a .glb file into its JSON and its binary buffer, if it has one.
+fn split_glb(data: &[u8], path: &Path) -> (Vec<u8>, Option<Vec<u8>>) {
+    if data.len() < 20 || read_u32(data, 4) != 2 {
+        fail!("{}: Only version 2 binary glTF files are supported", path.display());
+    }
+    let mut offset = 12;
+    let mut text = None;
+    let mut binary = None;
+    while offset + 8 <= data.len() {
+        let length = read_u32(data, offset) as uint;
+        let chunk_type = read_u32(data, offset + 4);
+        let start = offset + 8;
+        if start + length > data.len() {
+            fail!("{}: Chunk runs past the end of the file", path.display());
+        }
+        let chunk = data.slice(start, start + length).to_vec();
+        match chunk_type {
+            GLB_JSON_CHUNK if text.is_none() => text = Some(chunk),
+            GLB_BIN_CHUNK if binary.is_none() => binary = Some(chunk),
+            // Extensions can add chunks of their own
+            _ => ()
+        }
+        offset = start + length;
+    }
+    match text {
+        Some(text) => (text, binary),
+        None => fail!("{}: Binary glTF file doesn't have a JSON chunk", path.display())
+    }
+}
+
+/// The contents of a data URI, or of a file relative to the glTF file.
+fn load_uri(uri: &str, path: &Path) -> Vec<u8> {
+    if uri.starts_with("data:") {
+        let data = match uri.find_str(";base64,") {
+            Some(start) => uri.slice_from(start + 8),
+            None => fail!("{}: Only base64 data URIs are supported", path.display())
+        };
+        match data.from_base64() {
+            Ok(bytes) => bytes,
+            Err(err) => fail!("{}: Bad base64 data: {}", path.display(), err)
+        }
+    } else {
+        let file = path.dir_path().join(uri);
+        match File::open(&file).read_to_end() {
+            Ok(bytes) => bytes,
+            Err(err) => fail!("Error reading {}: {}", file.display(), err)
+        }
+    }
+}
+
+fn load_buffers(root: &JsonObject, path: &Path, binary: Option<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut binary = binary;
+    list(root, "buffers").iter().map(|buffer_json| {
+        let buffer = object(buffer_json, "Buffer");
+        match buffer.find(&"uri".to_string()) {
+            Some(uri) => load_uri(uri.as_string().expect("Buffer uri isn't a string"), path),
+            // Only the first buffer of a .glb file can be its binary chunk
+            None => binary.take()
+                          .expect(format!("{}: Buffer doesn't have a uri", path.display()).as_slice())
+        }
+    }).collect()
+}
+
+fn default_material() -> Material {
+    Material { color: Color { r: 1.0, g: 1.0, b: 1.0 },
+               metallic: 1.0,
+               roughness: 1.0,
+               texture: None }
+}
+
+impl<'a> Document<'a> {
+    fn load_materials(&mut self) {
+        let mut textures: Vec<Option<Option<Arc<Texture>>>> =
+            Vec::from_elem(list(self.json, "textures").len(), None);
+        let materials = list(self.json, "materials");
+        for material_json in materials.iter() {
+            let material = object(material_json, "Material");
+            let mut result = default_material();
+            let mut texture_set = 0;
+            match material.find(&"pbrMetallicRoughness".to_string()) {
+                Some(pbr_json) => {
+                    let pbr = object(pbr_json, "Material pbrMetallicRoughness");
+                    match pbr.find(&"baseColorFactor".to_string()) {
+                        Some(factor) => {
+                            let factor = numbers(factor, "Material baseColorFactor");
+                            if factor.len() < 3 {
+                                fail!("{}: Material baseColorFactor has {} values", self.path.display(),
+                                      factor.len());
+                            }
+                            result.color = Color { r: factor[0], g: factor[1], b: factor[2] };
+                        },
+                        None => ()
+                    }
+                    result.metallic = number_or(pbr, "metallicFactor", 1.0);
+                    result.roughness = number_or(pbr, "roughnessFactor", 1.0);
+                    match pbr.find(&"baseColorTexture".to_string()) {
+                        Some(info_json) => {
+                            let info = object(info_json, "Material baseColorTexture");
+                            let index = index(info, "index", "Material baseColorTexture");
+                            texture_set = number_or(info, "texCoord", 0.0) as uint;
+                            if index >= textures.len() {
+                                fail!("{}: Material refers to missing texture {}",
+                                      self.path.display(), index);
+                            }
+                            if textures[index].is_none() {
+                                textures[index] = Some(self.load_texture(index));
+                            }
+                            result.texture = textures[index].as_ref().unwrap().clone();
+                        },
+                        None => ()
+                    }
+                },
+                None => ()
+            }
+            self.materials.push(Arc::new(result));
+            self.texture_sets.push(texture_set);
+        }
+    }
+
+    /// Loads a texture's image, or nothing with a warning if it's in a
+    /// format we can't read.
+    fn load_texture(&self, texture: uint) -> Option<Arc<Texture>> {
+        let texture = object(&list(self.json, "textures")[texture], "Texture");
+        let source = match texture.find(&"source".to_string()) {
+            Some(_) => index(texture, "source", "Texture"),
+            None => {
+                println!("Warning: {}: Texture doesn't have a source image, ignoring it",
+                         self.path.display());
+                return None;
+            }
+        };
+        let images = list(self.json, "images");
+        if source >= images.len() {
+            fail!("{}: Texture refers to missing image {}", self.path.display(), source);
+        }
+        let image = object(&images[source], "Image");
+        let data = match image.find(&"uri".to_string()) {
+            Some(uri) => load_uri(uri.as_string().expect("Image uri isn't a string"), self.path),
+            None => self.buffer_view(index(image, "bufferView", "Image")).to_vec()
+        };
+        match decode_color(data.as_slice()) {
+            Ok(texture) => Some(Arc::new(texture)),
+            Err(err) => {
+                println!("Warning: {}: Couldn't read image {} ({}), only PNG is supported",
+                         self.path.display(), source, err);
+                None
+            }
+        }
+    }
+
+    fn buffer_view(&self, view: uint) -> &[u8] {
+        let views = list(self.json, "bufferViews");
+        if view >= views.len() {
+            fail!("{}: Missing buffer view {}", self.path.display(), view);
+        }
+        let view = object(&views[view], "Buffer view");
+        let buffer = index(view, "buffer", "Buffer view");
+        if buffer >= self.buffers.len() {
+            fail!("{}: Buffer view refers to missing buffer {}", self.path.display(), buffer);
+        }
+        let start = number_or(view, "byteOffset", 0.0) as uint;
+        let length = index(view, "byteLength", "Buffer view");
+        if start + length > self.buffers[buffer].len() {
+            fail!("{}: Buffer view runs past the end of its buffer", self.path.display());
+        }
+        self.buffers[buffer].slice(start, start + length)
+    }
+
+    /// Reads an accessor as a flat list of values, along with how many
+    /// values each element has. Normalized integers are scaled to 0 to 1,
+    /// or -1 to 1 when signed.
+    fn accessor(&self, accessor: uint) -> (Vec<f64>, uint) {
+        let accessors = list(self.json, "accessors");
+        if accessor >= accessors.len() {
+            fail!("{}: Missing accessor {}", self.path.display(), accessor);
+        }
+        let accessor = object(&accessors[accessor], "Accessor");
+        if accessor.find(&"sparse".to_string()).is_some() {
+            fail!("{}: Sparse accessors aren't supported", self.path.display());
+        }
+        let count = index(accessor, "count", "Accessor");
+        let components = match string(accessor, "type", "Accessor") {
+            "SCALAR" => 1u,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT2" => 4,
+            "MAT3" => 9,
+            "MAT4" => 16,
+            x => fail!("{}: Unsupported accessor type '{}'", self.path.display(), x)
+        };
+        let component_type = index(accessor, "componentType", "Accessor");
+        let size = match component_type {
+            5120 | 5121 => 1u,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            x => fail!("{}: Unsupported accessor component type {}", self.path.display(), x)
+        };
+        let normalized = match accessor.find(&"normalized".to_string()) {
+            Some(json) => json.as_boolean().expect("Accessor normalized isn't true or false"),
+            None => false
+        };
+
+        // Accessors without a buffer view are all zeros
+        let view_index = match accessor.find(&"bufferView".to_string()) {
+            Some(_) => index(accessor, "bufferView", "Accessor"),
+            None => return (Vec::from_elem(count * components, 0.0), components)
+        };
+        let data = self.buffer_view(view_index);
+        let stride = {
+            let view = object(&list(self.json, "bufferViews")[view_index], "Buffer view");
+            match view.find(&"byteStride".to_string()) {
+                Some(_) => index(view, "byteStride", "Buffer view"),
+                None => components * size
+            }
+        };
+        let offset = number_or(accessor, "byteOffset", 0.0) as uint;
+        if count > 0 && offset + (count - 1) * stride + components * size > data.len() {
+            fail!("{}: Accessor runs past the end of its buffer view", self.path.display());
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in range(0, count) {
+            for c in range(0, components) {
+                let at = offset + i * stride + c * size;
+                let bits = range(0, size).rev().fold(0u32, |bits, b| (bits << 8) | data[at + b] as u32);
+                let value = match component_type {
+                    5120 => bits as u8 as i8 as f64,
+                    5121 => bits as f64,
+                    5122 => bits as u16 as i16 as f64,
+                    5123 | 5125 => bits as f64,
+                    _ => unsafe { transmute::<u32, f32>(bits) as f64 }
+                };
+                let value = if normalized {
+                    match component_type {
+                        5120 => (value / 127.0).max(-1.0),
+                        5121 => value / 255.0,
+                        5122 => (value / 32767.0).max(-1.0),
+                        5123 => value / 65535.0,
+                        _ => value
+                    }
+                } else {
+                    value
+                };
+                values.push(value);
+            }
+        }
+        (values, components)
+    }
+
+    /// The nodes at the top of the default scene, or of the first scene,
+    /// or failing that every node that isn't another's child.
+    fn root_nodes(&self) -> Vec<uint> {
+        let scenes = list(self.json, "scenes");
+        if !scenes.is_empty() {
+            let scene = number_or(self.json, "scene", 0.0) as uint;
+            if scene >= scenes.len() {
+                fail!("{}: Default scene {} doesn't exist", self.path.display(), scene);
+            }
+            let scene = object(&scenes[scene], "Scene");
+            return list(scene, "nodes").iter().map(|node| as_index(node, "Scene node")).collect();
+        }
+        let nodes = list(self.json, "nodes");
+        let mut is_child = Vec::from_elem(nodes.len(), false);
+        for node in nodes.iter() {
+            for child in list(object(node, "Node"), "children").iter() {
+                let child = as_index(child, "Node child");
+                if child < is_child.len() {
+                    is_child[child] = true;
+                }
+            }
+        }
+        range(0, nodes.len()).filter(|&node| !is_child[node]).collect()
+    }
+
+    fn visit(&self, node_index: uint, parent: &Matrix4<f32>, asset: &mut GltfAsset,
+             bvh_params: &BvhParams) {
+        let nodes = list(self.json, "nodes");
+        if node_index >= nodes.len() {
+            fail!("{}: Missing node {}", self.path.display(), node_index);
+        }
+        let node = object(&nodes[node_index], "Node");
+        let to_world = parent.mul_m(&node_matrix(node));
+
+        match node.find(&"mesh".to_string()) {
+            Some(mesh) => self.add_mesh(as_index(mesh, "Node mesh"), &to_world, asset, bvh_params),
+            None => ()
+        }
+        match node.find(&"camera".to_string()) {
+            Some(camera) => match self.camera(as_index(camera, "Node camera"), &to_world) {
+                Some(camera) => asset.cameras.push(camera),
+                None => ()
+            },
+            None => ()
+        }
+        let light = node.find(&"extensions".to_string())
+                        .and_then(|extensions| extensions.find(&"KHR_lights_punctual".to_string()))
+                        .and_then(|lights| lights.find(&"light".to_string()));
+        match light {
+            Some(light) => {
+                let light = self.light(as_index(light, "Node light"), &to_world);
+                asset.lights.push(SceneLight { illuminator: light });
+            },
+            None => ()
+        }
+
+        for child in list(node, "children").iter() {
+            self.visit(as_index(child, "Node child"), &to_world, asset, bvh_params);
+        }
+    }
+
+    fn add_mesh(&self, mesh: uint, to_world: &Matrix4<f32>, asset: &mut GltfAsset,
+                bvh_params: &BvhParams) {
+        let meshes = list(self.json, "meshes");
+        if mesh >= meshes.len() {
+            fail!("{}: Missing mesh {}", self.path.display(), mesh);
+        }
+        let mesh = object(&meshes[mesh], "Mesh");
+        // Normals go by the inverse transpose to stay perpendicular to the
+        // surface under non-uniform scaling. Nodes are often hidden by
+        // scaling them to zero, which leaves nothing to draw.
+        let normal_matrix = match to_world.invert() {
+            Some(inverse) => inverse.transpose(),
+            None => return
+        };
+
+        for primitive_json in list(mesh, "primitives").iter() {
+            let primitive = object(primitive_json, "Mesh primitive");
+            let mode = number_or(primitive, "mode", 4.0) as uint;
+            if mode < 4 {
+                println!("Warning: {}: Skipping mesh of points or lines", self.path.display());
+                continue;
+            }
+            let attributes = object(primitive.find(&"attributes".to_string())
+                                             .expect("Mesh primitive doesn't have attributes"),
+                                    "Mesh primitive attributes");
+            let attribute = |name: &str| attributes.find(&name.to_string())
+                                                   .map(|json| self.accessor(as_index(json, name)));
+
+            let (positions, _) = attribute("POSITION")
+                .expect(format!("{}: Mesh primitive doesn't have positions", self.path.display()).as_slice());
+            let vertices: Vec<Point3<f32>> = positions.as_slice().chunks(3).map(|p| {
+                let v = to_world.mul_v(&Vector4::new(p[0] as f32, p[1] as f32, p[2] as f32, 1.0));
+                Point3::new(v.x, v.y, v.z)
+            }).collect();
+
+            let corners: Vec<uint> = match primitive.find(&"indices".to_string()) {
+                Some(indices) => {
+                    let (indices, _) = self.accessor(as_index(indices, "Mesh primitive indices"));
+                    indices.iter().map(|&i| i as uint).collect()
+                },
+                None => range(0, vertices.len()).collect()
+            };
+            let mut triangles = Vec::new();
+            match mode {
+                4 => for triangle in corners.as_slice().chunks(3).filter(|t| t.len() == 3) {
+                    triangles.push((triangle[0], triangle[1], triangle[2]));
+                },
+                // Every other triangle of a strip is wound the other way
+                5 => for i in range(2, corners.len()) {
+                    if i % 2 == 0 {
+                        triangles.push((corners[i - 2], corners[i - 1], corners[i]));
+                    } else {
+                        triangles.push((corners[i - 1], corners[i - 2], corners[i]));
+                    }
+                },
+                6 => for i in range(2, corners.len()) {
+                    triangles.push((corners[0], corners[i - 1], corners[i]));
+                },
+                x => fail!("{}: Unsupported primitive mode {}", self.path.display(), x)
+            }
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let (material, texture_set) = match primitive.find(&"material".to_string()) {
+                Some(material) => {
+                    let material = as_index(material, "Mesh primitive material");
+                    if material >= self.materials.len() {
+                        fail!("{}: Mesh refers to missing material {}", self.path.display(), material);
+                    }
+                    (self.materials[material].clone(), self.texture_sets[material])
+                },
+                None => (self.default_material.clone(), 0)
+            };
+
+            let mut geometry = TriangleMesh::new(vertices, triangles, bvh_params);
+            match attribute("NORMAL") {
+                Some((normals, _)) => {
+                    let normals = normals.as_slice().chunks(3).map(|n| {
+                        let v = normal_matrix.mul_v(&Vector4::new(n[0] as f32, n[1] as f32,
+                                                                  n[2] as f32, 0.0));
+                        Vector3::new(v.x, v.y, v.z)
+                    }).collect();
+                    geometry = geometry.with_normals(normals);
+                },
+                None => ()
+            }
+            match attribute(format!("TEXCOORD_{}", texture_set).as_slice()) {
+                Some((uvs, _)) => {
+                    let uvs = uvs.as_slice().chunks(2).map(|uv| (uv[0] as f32, uv[1] as f32)).collect();
+                    geometry = geometry.with_uvs(uvs);
+                },
+                None => ()
+            }
+            match attribute("COLOR_0") {
+                Some((colors, components)) => {
+                    // Alpha, if there is any, is dropped
+                    let colors = colors.as_slice().chunks(components).map(|c| {
+                        Color { r: c[0] as f32, g: c[1] as f32, b: c[2] as f32 }
+                    }).collect();
+                    geometry = geometry.with_colors(colors);
+                },
+                None => ()
+            }
+            asset.objects.push(SceneObject { geometry: box geometry,
+                                             material: material });
+        }
+    }
+
+    /// glTF cameras look along -z with y up. Only perspective cameras are
+    /// supported, and their aspect ratio is left to the renderer.
+    fn camera(&self, camera: uint, to_world: &Matrix4<f32>) -> Option<Camera> {
+        let cameras = list(self.json, "cameras");
+        if camera >= cameras.len() {
+            fail!("{}: Missing camera {}", self.path.display(), camera);
+        }
+        let camera = object(&cameras[camera], "Camera");
+        if string(camera, "type", "Camera") != "perspective" {
+            println!("Warning: {}: Skipping orthographic camera", self.path.display());
+            return None;
+        }
+        let perspective = object(camera.find(&"perspective".to_string())
+                                       .expect("Perspective camera doesn't have perspective"),
+                                 "Camera perspective");
+        let fov = perspective.find(&"yfov".to_string())
+                             .and_then(|yfov| yfov.as_f64())
+                             .expect("Perspective camera doesn't have a yfov") as f32;
+        let fov = fov.to_degrees();
+        let position = to_world.mul_v(&Vector4::new(0.0, 0.0, 0.0, 1.0));
+        let forward = to_world.mul_v(&Vector4::new(0.0, 0.0, -1.0, 0.0));
+        let up = to_world.mul_v(&Vector4::new(0.0, 1.0, 0.0, 0.0));
+        Some(Camera::new(Point3::new(position.x, position.y, position.z),
+                         Vector3::new(forward.x, forward.y, forward.z),
+                         Vector3::new(up.x, up.y, up.z),
+                         fov))
+    }
+
+    /// A light from the KHR_lights_punctual extension. Spot lights are
+    /// lit like point lights, without their cone.
+    fn light(&self, light: uint, to_world: &Matrix4<f32>) -> Box<Illuminator+Send+Sync> {
+        let lights = self.json.find(&"extensions".to_string())
+                              .and_then(|extensions| extensions.find(&"KHR_lights_punctual".to_string()))
+                              .map(|lights| list(object(lights, "KHR_lights_punctual"), "lights"))
+                              .unwrap_or(&[]);
+        if light >= lights.len() {
+            fail!("{}: Missing light {}", self.path.display(), light);
+        }
+        let light = object(&lights[light], "Light");
+        let color = match light.find(&"color".to_string()) {
+            Some(color) => {
+                let color = numbers(color, "Light color");
+                if color.len() < 3 {
+                    fail!("{}: Light color has {} values", self.path.display(), color.len());
+                }
+                Color { r: color[0], g: color[1], b: color[2] }
+            },
+            None => Color { r: 1.0, g: 1.0, b: 1.0 }
+        };
+        let intensity = number_or(light, "intensity", 1.0);
+        match string(light, "type", "Light") {
+            "directional" => {
+                // The light shines along -z, so it comes from +z
+                let towards = to_world.mul_v(&Vector4::new(0.0, 0.0, 1.0, 0.0));
+                box DirectionalLight { direction: Vector3::new(towards.x, towards.y, towards.z).normalize(),
+                                       color: color,
+                                       intensity: intensity,
+                                       // About the size of the sun, shadow
+                                       // sampling needs some spread
+                                       angle: 0.5 } as Box<Illuminator+Send+Sync>
+            },
+            "point" | "spot" => {
+                let position = to_world.mul_v(&Vector4::new(0.0, 0.0, 0.0, 1.0));
+                box PointLight { position: Point3::new(position.x, position.y, position.z),
+                                 color: color,
+                                 intensity: intensity,
+                                 radius: 0.0 } as Box<Illuminator+Send+Sync>
+            },
+            x => fail!("{}: Unsupported light type '{}'", self.path.display(), x)
+        }
+    }
+}
+
+/// A node's transform relative to its parent, from either its column
+/// major "matrix" or its translation, rotation and scale.
+fn node_matrix(node: &JsonObject) -> Matrix4<f32> {
+    match node.find(&"matrix".to_string()) {
+        Some(matrix) => {
+            let m = numbers(matrix, "Node matrix");
+            if m.len() != 16 {
+                fail!("Node matrix has {} entries instead of 16", m.len());
+            }
+            return Matrix4::new(m[0],  m[1],  m[2],  m[3],
+                                m[4],  m[5],  m[6],  m[7],
+                                m[8],  m[9],  m[10], m[11],
+                                m[12], m[13], m[14], m[15]);
+        },
+        None => ()
+    }
+    let t = match node.find(&"translation".to_string()) {
+        Some(json) => numbers(json, "Node translation"),
+        None => vec![0.0, 0.0, 0.0]
+    };
+    // Rotations are quaternions stored as [x, y, z, w]
+    let r = match node.find(&"rotation".to_string()) {
+        Some(json) => numbers(json, "Node rotation"),
+        None => vec![0.0, 0.0, 0.0, 1.0]
+    };
+    let s = match node.find(&"scale".to_string()) {
+        Some(json) => numbers(json, "Node scale"),
+        None => vec![1.0, 1.0, 1.0]
+    };
+    if t.len() != 3 || r.len() != 4 || s.len() != 3 {
+        fail!("Node translation, rotation or scale has the wrong number of values");
+    }
+    let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+    Matrix4::new((1.0 - 2.0*(y*y + z*z)) * s[0], 2.0*(x*y + w*z) * s[0], 2.0*(x*z - w*y) * s[0], 0.0,
+                 2.0*(x*y - w*z) * s[1], (1.0 - 2.0*(x*x + z*z)) * s[1], 2.0*(y*z + w*x) * s[1], 0.0,
+                 2.0*(x*z + w*y) * s[2], 2.0*(y*z - w*x) * s[2], (1.0 - 2.0*(x*x + y*y)) * s[2], 0.0,
+                 t[0], t[1], t[2], 1.0)
+}
+
+// glTF leaves out empty lists and default values, so most lookups have
+// something to fall back on.
+
+fn object<'a>(json: &'a Json, name: &str) -> &'a JsonObject {
+    json.as_object()
+        .expect(format!("{} isn't a JSON object", name).as_slice())
+}
+
+fn list<'a>(object: &'a JsonObject, key: &str) -> &'a [Json] {
+    match object.find(&key.to_string()) {
+        Some(json) => json.as_list()
+                          .expect(format!("glTF {} isn't a list", key).as_slice())
+                          .as_slice(),
+        None => &[]
+    }
+}
+
+fn string<'a>(object: &'a JsonObject, key: &str, name: &str) -> &'a str {
+    object.find(&key.to_string())
+          .expect(format!("{} doesn't have a {}", name, key).as_slice())
+          .as_string()
+          .expect(format!("{} {} isn't a string", name, key).as_slice())
+}
+
+fn as_index(json: &Json, name: &str) -> uint {
+    json.as_u64()
+        .expect(format!("{} isn't an index", name).as_slice()) as uint
+}
+
+fn index(object: &JsonObject, key: &str, name: &str) -> uint {
+    as_index(object.find(&key.to_string())
+                   .expect(format!("{} doesn't have a {}", name, key).as_slice()),
+             format!("{} {}", name, key).as_slice())
+}
+
+fn number_or(object: &JsonObject, key: &str, default: f32) -> f32 {
+    match object.find(&key.to_string()) {
+        Some(json) => json.as_f64()
+                          .expect(format!("glTF {} isn't a number", key).as_slice()) as f32,
+        None => default
+    }
+}
+
+fn numbers(json: &Json, name: &str) -> Vec<f32> {
+    let values: &JsonList = json.as_list()
+                                .expect(format!("{} isn't a list", name).as_slice());
+    values.iter()
+          .map(|x| x.as_f64().expect(format!("{} should only contain numbers", name).as_slice()) as f32)
+          .collect()
+}
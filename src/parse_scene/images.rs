@@ -0,0 +1,51 @@
+use lodepng;
+use image_types::Color;
+use scene::{Texture, BottomRow, bilinear_taps};
+
+/// A grayscale image with values from 0 to 1, a row at a time from the top.
+pub struct GrayscaleImage {
+    pub width: uint,
+    pub height: uint,
+    pub pixels: Vec<f32>
+}
+
+/// Loads a PNG as 16 bit grayscale, so 16 bit height maps keep their
+/// precision. Color images are converted by lodepng.
+pub fn load_grayscale(path: &Path) -> GrayscaleImage {
+    let bitmap = match lodepng::decode_file(path, lodepng::LCT_GREY, 16) {
+        Ok(bitmap) => bitmap,
+        Err(err) => fail!("Error reading image {}: {}", path.display(), err)
+    };
+    // Samples are stored big endian
+    let pixels = bitmap.buffer.as_slice().chunks(2).map(|sample| {
+        (((sample[0] as uint) << 8) | sample[1] as uint) as f32 / 65535.0
+    }).collect();
+    GrayscaleImage { width: bitmap.width as uint,
+                     height: bitmap.height as uint,
+                     pixels: pixels }
+}
+
+impl GrayscaleImage {
+    /// The value at texture coordinates, with v going up from the bottom
+    /// row, blended between the four nearest pixels. The image repeats
+    /// outside 0 to 1.
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let taps = bilinear_taps(self.width, self.height, u, v, BottomRow);
+        taps.iter().fold(0.0, |sum, &(index, weight)| sum + self.pixels[index] * weight)
+    }
+}
+
+/// Decodes a PNG held in memory, such as one embedded in a glTF file, as
+/// 8 bit RGB with values from 0 to 1.
+pub fn decode_color(data: &[u8]) -> Result<Texture, String> {
+    let bitmap = match lodepng::decode_memory(data, lodepng::LCT_RGB, 8) {
+        Ok(bitmap) => bitmap,
+        Err(err) => return Err(format!("{}", err))
+    };
+    let pixels = bitmap.buffer.as_slice().chunks(3).map(|pixel| {
+        Color { r: pixel[0] as f32 / 255.0,
+                g: pixel[1] as f32 / 255.0,
+                b: pixel[2] as f32 / 255.0 }
+    }).collect();
+    Ok(Texture::new(bitmap.width as uint, bitmap.height as uint, pixels))
+}
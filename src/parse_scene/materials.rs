@@ -31,9 +31,21 @@ fn parse_mat(material_json: &Json) -> (String, Arc<Material>) {
     let g = color[1].as_f64().expect("Color should only contain numbers") as f32;
     let b = color[2].as_f64().expect("Color should only contain numbers") as f32;
     
+    let metallic = match material_json.find(&"metallic".to_string()) {
+        Some(json) => json.as_f64().expect("Material metallic isn't a number") as f32,
+        None => 0.0
+    };
+    let roughness = match material_json.find(&"roughness".to_string()) {
+        Some(json) => json.as_f64().expect("Material roughness isn't a number") as f32,
+        None => 1.0
+    };
+    
     let mat = Material { color: Color { r: r,
                                         g: g,
-                                        b: b }
+                                        b: b },
+                         metallic: metallic,
+                         roughness: roughness,
+                         texture: None
                          };
     (name.to_string(), Arc::new(mat))
 }
@@ -1,10 +1,23 @@
-use scene::Scene;
+use scene::{Scene, Camera, Bvh, BvhParams, Accelerator, AcceleratorType};
+use scene::{BvhAccelerator, KdTreeAccelerator, GridAccelerator};
+use std::default::Default;
 use std::str;
 use serialize::json;
+use serialize::json::Json;
 use std::io::File;
 
 mod lights;
 mod objects;
+mod obj;
+mod ply;
+mod stl;
+mod strands;
+mod bpt;
+mod particles;
+mod vox;
+mod gltf;
+mod fields;
+mod images;
 mod materials;
 
 pub fn parse_scene(filename: &str) -> Scene {
@@ -45,21 +58,92 @@ pub fn parse_scene(filename: &str) -> Scene {
         num_bounces = 4;
     }
 
+    let accelerator_type = match scene_json.find(&"accelerator".to_string()) {
+        Some(accelerator_json) => parse_accelerator_type(accelerator_json),
+        None => BvhAccelerator
+    };
+
+    let bvh_params = match scene_json.find(&"bvh".to_string()) {
+        Some(bvh_json) => parse_bvh_params(bvh_json),
+        None => Default::default()
+    };
+
     let material_json = contents.find(&"materials".to_string())
         .expect("JSON missing materials section.");
     let materials = materials::parse_materials(material_json);
 
     let objects_json = contents.find(&"objects".to_string())
         .expect("JSON missing objects section.");
-    let objects = objects::parse_objects(objects_json, &materials);
+    let mut objects = objects::parse_objects(objects_json, &materials, &path.dir_path(), &bvh_params);
 
     let lights_json = contents.find(&"lights".to_string())
         .expect("JSON missing lights section");
-    let lights = lights::parse_lights(lights_json);
+    let mut lights = lights::parse_lights(lights_json);
+
+    // glTF assets add to the objects and lights above, and the first
+    // camera in them replaces the default one.
+    let mut camera: Camera = Default::default();
+    match contents.find(&"assets".to_string()) {
+        Some(assets_json) => {
+            let assets = gltf::parse_assets(assets_json, &path.dir_path(), &bvh_params);
+            objects.extend(assets.objects.into_iter());
+            lights.extend(assets.lights.into_iter());
+            match assets.cameras.into_iter().next() {
+                Some(asset_camera) => camera = asset_camera,
+                None => ()
+            }
+        },
+        None => ()
+    }
         
-    Scene { objects: objects,
+    Scene { camera: camera,
+            objects: objects,
             lights: lights,
             num_gi_samples: num_gi_samples,
             num_shadow_samples: num_shadow_samples,
-            bounces: num_bounces }
+            bounces: num_bounces,
+            accelerator_type: accelerator_type,
+            bvh_params: bvh_params,
+            accelerator: box Bvh::empty() as Box<Accelerator+Send+Sync> }
+}
+
+fn parse_accelerator_type(accelerator_json: &Json) -> AcceleratorType {
+    let name = accelerator_json.as_string()
+                               .expect("Scene 'accelerator' entry isn't a string");
+    match name {
+        "bvh" => BvhAccelerator,
+        "kd-tree" => KdTreeAccelerator,
+        "grid" => GridAccelerator,
+        x => fail!("Unsupported accelerator '{}', expected 'bvh', 'kd-tree' or 'grid'", x)
+    }
+}
+
+/// Reads the optional "bvh" entry of the scene section, which tunes the
+/// surface area heuristic for every BVH in the scene, and for the scene
+/// kd-tree if that's the accelerator. Missing values keep their defaults.
+fn parse_bvh_params(bvh_json: &Json) -> BvhParams {
+    let bvh = bvh_json.as_object()
+                      .expect("Scene 'bvh' entry isn't a JSON object");
+    let mut params: BvhParams = Default::default();
+    match bvh.find(&"traversal cost".to_string()) {
+        Some(cost) => params.traversal_cost = cost.as_f64()
+            .expect("'traversal cost' was not a number") as f32,
+        None => ()
+    }
+    match bvh.find(&"intersection cost".to_string()) {
+        Some(cost) => params.intersection_cost = cost.as_f64()
+            .expect("'intersection cost' was not a number") as f32,
+        None => ()
+    }
+    match bvh.find(&"max leaf size".to_string()) {
+        Some(size) => params.max_leaf_size = size.as_u64()
+            .expect("'max leaf size' was not a number") as uint,
+        None => ()
+    }
+    match bvh.find(&"bins".to_string()) {
+        Some(bins) => params.bins = bins.as_u64()
+            .expect("'bins' was not a number") as uint,
+        None => ()
+    }
+    params
 }
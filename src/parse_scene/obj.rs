@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::{File, BufferedReader};
+use cgmath::{Point3, Vector3};
+use scene::{TriangleMesh, BvhParams};
+
+/// The contents of a Wavefront OBJ file, with faces kept as polygons and
+/// split into groups by `g` and `usemtl` statements.
+pub struct ObjFile {
+    pub positions: Vec<Point3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub texcoords: Vec<(f32, f32)>,
+    pub groups: Vec<ObjGroup>
+}
+
+pub struct ObjGroup {
+    pub name: String,
+    pub material: Option<String>,
+    pub faces: Vec<Vec<ObjVertex>>
+}
+
+/// A face corner, as zero-based indices into the file's attribute lists.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct ObjVertex {
+    pub position: uint,
+    pub texcoord: Option<uint>,
+    pub normal: Option<uint>
+}
+
+pub fn read_obj(path: &Path) -> ObjFile {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let mut reader = BufferedReader::new(file);
+    let mut obj = ObjFile { positions: Vec::new(),
+                            normals: Vec::new(),
+                            texcoords: Vec::new(),
+                            groups: Vec::new() };
+    let mut group_name = "default".to_string();
+    let mut material = None;
+    let mut current = None;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => fail!("Error reading {}: {}", path.display(), err)
+        };
+        let context = format!("{}:{}", path.display(), line_number + 1);
+        let words: Vec<&str> = line.as_slice().words().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let args = words.slice_from(1);
+        match words[0] {
+            "v" => {
+                let (x, y, z) = parse_triple(args, context.as_slice());
+                obj.positions.push(Point3::new(x, y, z));
+            },
+            "vn" => {
+                let (x, y, z) = parse_triple(args, context.as_slice());
+                obj.normals.push(Vector3::new(x, y, z));
+            },
+            "vt" => {
+                let u = parse_float(args.get(0), context.as_slice());
+                let v = if args.len() > 1 {
+                    parse_float(args.get(1), context.as_slice())
+                } else {
+                    0.0
+                };
+                obj.texcoords.push((u, v));
+            },
+            "f" => {
+                let face: Vec<ObjVertex> = args.iter().map(|corner| {
+                    parse_corner(*corner, &obj, context.as_slice())
+                }).collect();
+                if face.len() < 3 {
+                    fail!("{}: Face has fewer than 3 vertices", context);
+                }
+                if current.is_none() {
+                    current = Some(find_group(&mut obj.groups, &group_name, &material));
+                }
+                obj.groups[current.unwrap()].faces.push(face);
+            },
+            "g" | "o" => {
+                group_name = if args.is_empty() {
+                    "default".to_string()
+                } else {
+                    args.connect(" ")
+                };
+                current = None;
+            },
+            "usemtl" => {
+                material = args.get(0).map(|name| name.to_string());
+                current = None;
+            },
+            // Comments, smoothing groups, material libraries and
+            // anything else we don't render are skipped.
+            _ => ()
+        }
+    }
+    obj
+}
+
+/// Finds the group with the given name and material, creating it if
+/// it doesn't exist yet, so that groups split up over the file are merged.
+fn find_group(groups: &mut Vec<ObjGroup>, name: &String, material: &Option<String>) -> uint {
+    for (index, group) in groups.iter().enumerate() {
+        if group.name == *name && group.material == *material {
+            return index;
+        }
+    }
+    groups.push(ObjGroup { name: name.clone(),
+                           material: material.clone(),
+                           faces: Vec::new() });
+    groups.len() - 1
+}
+
+fn parse_float(word: Option<&&str>, context: &str) -> f32 {
+    let word = *word.expect(format!("{}: Missing number", context).as_slice());
+    from_str::<f32>(word).expect(format!("{}: '{}' isn't a number", context, word).as_slice())
+}
+
+fn parse_triple(args: &[&str], context: &str) -> (f32, f32, f32) {
+    let x = parse_float(args.get(0), context);
+    let y = parse_float(args.get(1), context);
+    let z = parse_float(args.get(2), context);
+    (x, y, z)
+}
+
+/// Resolves a one-based (or negative, relative to the end) OBJ index.
+fn parse_index(word: &str, count: uint, context: &str) -> uint {
+    let index = from_str::<int>(word)
+        .expect(format!("{}: '{}' isn't an index", context, word).as_slice());
+    let resolved = if index < 0 { count as int + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as int {
+        fail!("{}: Index {} is out of range", context, index);
+    }
+    resolved as uint
+}
+
+fn parse_corner(corner: &str, obj: &ObjFile, context: &str) -> ObjVertex {
+    let parts: Vec<&str> = corner.split('/').collect();
+    let position = parse_index(parts[0], obj.positions.len(), context);
+    let texcoord = match parts.as_slice().get(1) {
+        Some(&"") | None => None,
+        Some(word) => Some(parse_index(*word, obj.texcoords.len(), context))
+    };
+    let normal = match parts.as_slice().get(2) {
+        Some(&"") | None => None,
+        Some(word) => Some(parse_index(*word, obj.normals.len(), context))
+    };
+    ObjVertex { position: position,
+                texcoord: texcoord,
+                normal: normal }
+}
+
+impl ObjGroup {
+    /// Triangulates the group's polygons as fans and builds a mesh that
+    /// only holds the vertices the group uses.
+    pub fn to_mesh(&self, obj: &ObjFile, bvh_params: &BvhParams) -> TriangleMesh {
+        let mut remap = HashMap::new();
+        let mut corners = Vec::new();
+        let mut triangles = Vec::new();
+        for face in self.faces.iter() {
+            let indices: Vec<uint> = face.iter().map(|corner| {
+                let existing = remap.find(corner).map(|&index| index);
+                match existing {
+                    Some(index) => index,
+                    None => {
+                        remap.insert(corner.clone(), corners.len());
+                        corners.push(corner.clone());
+                        corners.len() - 1
+                    }
+                }
+            }).collect();
+            for i in range(1, indices.len() - 1) {
+                triangles.push((indices[0], indices[i], indices[i + 1]));
+            }
+        }
+
+        let vertices = corners.iter().map(|c| obj.positions[c.position]).collect();
+        let mut mesh = TriangleMesh::new(vertices, triangles, bvh_params);
+        // Only use normals and texture coordinates when every corner has them
+        if corners.iter().all(|c| c.normal.is_some()) {
+            mesh = mesh.with_normals(corners.iter().map(|c| obj.normals[c.normal.unwrap()]).collect());
+        }
+        if corners.iter().all(|c| c.texcoord.is_some()) {
+            mesh = mesh.with_uvs(corners.iter().map(|c| obj.texcoords[c.texcoord.unwrap()]).collect());
+        }
+        mesh
+    }
+}
@@ -1,35 +1,470 @@
-use scene::{Sphere, SceneObject, Material};
-use std::collections::TreeMap;
-use serialize::json::Json;
+use scene::{Sphere, Plane, Disk, Rectangle, Triangle, TriangleMesh};
+use scene::{AxisAlignedBox, OrientedBox, Cylinder, Cone, Capsule, Torus, Heightfield};
+use scene::{SceneObject, Material, Intersectable, Transformed, SphereTraced, BvhParams};
+use scene::{Csg, CsgOperation, CsgUnion, CsgIntersection, CsgDifference};
+use scene::{ControlCage, CatmullClarkScheme, LoopScheme, DisplacementBase, BezierPatches};
+use scene::{Curves, CurveRibbon, CurveTube, Particles, Metaballs, Metaball, Voxels};
+use scene::{Lathe, catmull_rom};
+use std::collections::{TreeMap, HashMap};
+use serialize::json::{Json, JsonObject};
 use std::sync::Arc;
+use cgmath::{Point3, Vector3, Matrix4, Vector};
+use parse_scene::obj::read_obj;
+use parse_scene::ply::read_ply;
+use parse_scene::stl::{read_stl, StlOptions};
+use parse_scene::strands::read_strands;
+use parse_scene::bpt::read_bpt;
+use parse_scene::particles::read_particles;
+use parse_scene::vox::read_vox;
+use parse_scene::fields::field_from_json;
+use parse_scene::images::load_grayscale;
+use scene::rotate_euler;
 
-pub fn parse_objects(objects_json: &Json, materials: &TreeMap<String, Arc<Material>>) -> Vec<SceneObject> {
+pub fn parse_objects(objects_json: &Json, materials: &TreeMap<String, Arc<Material>>,
+                     base_dir: &Path, bvh_params: &BvhParams) -> Vec<SceneObject> {
     let objects = objects_json.as_list()
                               .expect("Objects isn't a list");
     let mut scene_objects = Vec::with_capacity(objects.len());
     for object in objects.iter() {
-        let obj = parse_obj(object, materials);
-        scene_objects.push(obj);
+        let objs = parse_obj(object, materials, base_dir, bvh_params);
+        scene_objects.extend(objs.into_iter());
     }
     scene_objects
 }
 
-fn parse_obj(object_json: &Json, materials: &TreeMap<String, Arc<Material>>) -> SceneObject {
+fn parse_obj(object_json: &Json, materials: &TreeMap<String, Arc<Material>>,
+             base_dir: &Path, bvh_params: &BvhParams) -> Vec<SceneObject> {
     let object = object_json.as_object()
                             .expect("Object isn't a JSON object");
     let object_type = object.find(&"type".to_string())
                             .expect("Object doesn't have a type")
                             .as_string()
                             .expect("Object type isn't a string");
-    if object_type.as_slice() != "sphere" {
-        fail!("Only spheres are currently supported!");
-    }
     let mat_name = object.find(&"material".to_string())
                          .expect("Object doesn't have a material")
                          .as_string()
                          .expect("Object material isn't a string");
     let material = materials.find(&mat_name.to_string())
                             .expect(format!("No material with name '{}'", mat_name).as_slice());
+    match object_type.as_slice() {
+        // An OBJ file becomes a scene object for each of its groups, since
+        // they can have their own materials.
+        "mesh" => {
+            let scene_objects = obj_file_from_json(object, material, materials, base_dir, bvh_params);
+            match object.find(&"transform".to_string()) {
+                Some(transform_json) => {
+                    let matrix = transform_from_json(transform_json);
+                    scene_objects.into_iter().map(|scene_object| {
+                        let SceneObject { geometry, material } = scene_object;
+                        SceneObject { geometry: box Transformed::new(geometry, matrix),
+                                      material: material }
+                    }).collect()
+                },
+                None => scene_objects
+            }
+        },
+        _ => vec![SceneObject { geometry: geometry_from_json(object, &**material, base_dir,
+                                                             bvh_params),
+                                material: material.clone() }]
+    }
+}
+
+/// The shape described by an object, placed by its optional "transform".
+/// Used both for objects in the scene and for the operands of CSG
+/// operations, which don't have materials of their own and are given the
+/// whole operation's `material` for shapes that make their own from it.
+fn geometry_from_json(object: &JsonObject, material: &Material, base_dir: &Path,
+                      bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let object_type = object.find(&"type".to_string())
+                            .expect("Object doesn't have a type")
+                            .as_string()
+                            .expect("Object type isn't a string");
+    let geometry = match object_type.as_slice() {
+        "sphere"        => sphere_from_json(object),
+        "stl"           => stl_from_json(object, base_dir, bvh_params),
+        "plane"         => plane_from_json(object),
+        "disk"          => disk_from_json(object),
+        "rectangle"     => rectangle_from_json(object),
+        "box"           => box_from_json(object),
+        "oriented box"  => oriented_box_from_json(object),
+        "cylinder"      => cylinder_from_json(object),
+        "cone"          => cone_from_json(object),
+        "capsule"       => capsule_from_json(object),
+        "torus"         => torus_from_json(object),
+        "lathe"         => lathe_from_json(object, bvh_params),
+        "triangle"      => triangle_from_json(object),
+        "triangle mesh" => mesh_from_json(object, bvh_params),
+        "ply"           => ply_from_json(object, base_dir, bvh_params),
+        "subdivision surface" => subdivision_from_json(object, base_dir, bvh_params),
+        "displaced surface" => displaced_from_json(object, base_dir, bvh_params),
+        "bezier patches" => bezier_from_json(object, base_dir, bvh_params),
+        "curves"        => curves_from_json(object, base_dir, bvh_params),
+        "particles"     => particles_from_json(object, base_dir, bvh_params),
+        "metaballs"     => metaballs_from_json(object, bvh_params),
+        "voxels"        => voxels_from_json(object, material, base_dir),
+        "union"         => csg_from_json(object, CsgUnion, material, base_dir, bvh_params),
+        "intersection"  => csg_from_json(object, CsgIntersection, material, base_dir, bvh_params),
+        "difference"    => csg_from_json(object, CsgDifference, material, base_dir, bvh_params),
+        "sdf"           => sdf_from_json(object),
+        "heightfield"   => heightfield_from_json(object, base_dir),
+        x               => fail!("Unsupported object type '{}'", x)
+    };
+
+    match object.find(&"transform".to_string()) {
+        Some(transform_json) => box Transformed::new(geometry, transform_from_json(transform_json)),
+        None => geometry
+    }
+}
+
+/// A CSG operation on the list of shapes in "objects". A difference takes
+/// all the others away from the first one.
+fn csg_from_json(object: &JsonObject, operation: CsgOperation, material: &Material,
+                 base_dir: &Path, bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let children = object.find(&"objects".to_string())
+                         .expect("CSG operation doesn't have objects")
+                         .as_list()
+                         .expect("CSG objects aren't a list");
+    let children = children.iter().map(|child| {
+        let child = child.as_object()
+                         .expect("CSG object isn't a JSON object");
+        geometry_from_json(child, material, base_dir, bvh_params)
+    }).collect();
+    box Csg::new(operation, children)
+}
+
+/// A triangle mesh from a PLY file, with whichever vertex normals, texture
+/// coordinates and colors it has. Vertex colors tint the object's material.
+fn ply_from_json(object: &JsonObject, base_dir: &Path,
+                 bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("PLY mesh doesn't have a file")
+                     .as_string()
+                     .expect("PLY mesh file isn't a string");
+    box read_ply(&base_dir.join(file)).to_mesh(bvh_params)
+}
+
+/// A smooth surface refined from a control cage, read from the OBJ "file"
+/// or given as "vertices" and "faces" lists of vertex indices. "levels"
+/// sets how many times it's subdivided. Catmull-Clark is used unless the
+/// cage is all triangles, and "scheme" can pick "catmull-clark" or "loop".
+/// Edges in "creases", each [a, b, sharpness], stay sharp for that many
+/// levels.
+fn subdivision_from_json(object: &JsonObject, base_dir: &Path,
+                         bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let cage = match object.find(&"file".to_string()) {
+        Some(file) => {
+            let file = file.as_string()
+                           .expect("Subdivision surface file isn't a string");
+            let obj = read_obj(&base_dir.join(file));
+            let faces = obj.groups.iter().flat_map(|group| group.faces.iter()).map(|face| {
+                face.iter().map(|corner| corner.position).collect()
+            }).collect();
+            ControlCage::new(obj.positions, faces)
+        },
+        None => {
+            let vertices = object.find(&"vertices".to_string())
+                                 .expect("Subdivision surface doesn't have a file or vertices")
+                                 .as_list()
+                                 .expect("Subdivision surface vertices aren't a list")
+                                 .iter()
+                                 .map(|v| point_from_json(v, "Subdivision surface vertex"))
+                                 .collect();
+            let faces = object.find(&"faces".to_string())
+                              .expect("Subdivision surface doesn't have faces")
+                              .as_list()
+                              .expect("Subdivision surface faces aren't a list")
+                              .iter()
+                              .map(|face| {
+                                  face.as_list()
+                                      .expect("Subdivision surface face isn't a list of indices")
+                                      .iter()
+                                      .map(|i| cage_index(i))
+                                      .collect()
+                              })
+                              .collect();
+            ControlCage::new(vertices, faces)
+        }
+    };
+    let creases = match object.find(&"creases".to_string()) {
+        Some(creases) => {
+            creases.as_list()
+                   .expect("Subdivision surface creases aren't a list")
+                   .iter()
+                   .map(|crease| {
+                       let crease = crease.as_list()
+                                          .expect("Crease isn't of form [a, b, sharpness]");
+                       if crease.len() != 3 {
+                           fail!("Crease isn't of form [a, b, sharpness]");
+                       }
+                       let sharpness = crease[2].as_f64()
+                                                .expect("Crease sharpness isn't a number") as f32;
+                       (cage_index(&crease[0]), cage_index(&crease[1]), sharpness)
+                   })
+                   .collect()
+        },
+        None => Vec::new()
+    };
+    let cage = cage.with_creases(creases);
+    let scheme = match object.find(&"scheme".to_string()) {
+        Some(scheme) => match scheme.as_string().expect("Subdivision scheme isn't a string") {
+            "catmull-clark" => CatmullClarkScheme,
+            "loop" => LoopScheme,
+            x => fail!("Unsupported subdivision scheme '{}'", x)
+        },
+        None if cage.is_triangles() => LoopScheme,
+        None => CatmullClarkScheme
+    };
+    let levels = match object.find(&"levels".to_string()) {
+        Some(levels) => levels.as_u64().expect("Subdivision levels isn't a number") as uint,
+        None => 2
+    };
+    box cage.subdivide(scheme, levels).to_mesh(bvh_params)
+}
+
+fn cage_index(json: &Json) -> uint {
+    json.as_u64().expect("Subdivision surface indices should only contain integers") as uint
+}
+
+/// The polygons of an OBJ "file" with texture coordinates, tessellated
+/// until no edge is longer than "edge length" and then moved along their
+/// normals by a grayscale PNG "displacement" map. White moves the surface
+/// out by "scale" and black doesn't move it, or with "midlevel" set, that
+/// shade stays put and darker ones move in.
+fn displaced_from_json(object: &JsonObject, base_dir: &Path,
+                       bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("Displaced surface doesn't have a file")
+                     .as_string()
+                     .expect("Displaced surface file isn't a string");
+    let map = object.find(&"displacement".to_string())
+                    .expect("Displaced surface doesn't have a displacement map")
+                    .as_string()
+                    .expect("Displaced surface displacement map isn't a string");
+    let scale = find_number(object, "scale", "Displaced surface");
+    let edge_length = find_number(object, "edge length", "Displaced surface");
+    let midlevel = match object.find(&"midlevel".to_string()) {
+        Some(json) => json.as_f64().expect("Displaced surface midlevel isn't a number") as f32,
+        None => 0.0
+    };
+
+    let obj = read_obj(&base_dir.join(file));
+    let mut remap = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for face in obj.groups.iter().flat_map(|group| group.faces.iter()) {
+        let indices: Vec<uint> = face.iter().map(|corner| {
+            let texcoord = corner.texcoord
+                .expect("Displaced surface needs texture coordinates on every face");
+            let key = (corner.position, texcoord);
+            let existing = remap.find(&key).map(|&index| index);
+            match existing {
+                Some(index) => index,
+                None => {
+                    remap.insert(key, vertices.len());
+                    vertices.push((corner.position, obj.texcoords[texcoord]));
+                    vertices.len() - 1
+                }
+            }
+        }).collect();
+        for i in range(1, indices.len() - 1) {
+            triangles.push((indices[0], indices[i], indices[i + 1]));
+        }
+    }
+
+    let image = load_grayscale(&base_dir.join(map));
+    let base = DisplacementBase::new(obj.positions.clone(), vertices, triangles);
+    box base.displace(edge_length, scale, midlevel, |u, v| image.sample(u, v), bvh_params)
+}
+
+/// Bicubic Bézier patches from a `.bpt` "file", or from a "patches" list
+/// of 16 control points each, tessellated to within "tolerance" of the
+/// true surface.
+fn bezier_from_json(object: &JsonObject, base_dir: &Path,
+                    bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let tolerance = find_number(object, "tolerance", "Bezier patches");
+    let patches = match object.find(&"file".to_string()) {
+        Some(file) => {
+            let file = file.as_string()
+                           .expect("Bezier patches file isn't a string");
+            read_bpt(&base_dir.join(file))
+        },
+        None => {
+            let patches = object.find(&"patches".to_string())
+                                .expect("Bezier patches don't have a file or patches")
+                                .as_list()
+                                .expect("Bezier patches patches aren't a list");
+            patches.iter().map(|patch| {
+                let points = patch.as_list()
+                                  .expect("Bezier patch isn't a list of control points");
+                if points.len() != 16 {
+                    fail!("Bezier patch has {} control points instead of 16", points.len());
+                }
+                let mut patch = [Point3::new(0.0f32, 0.0, 0.0), ..16];
+                for (point, json) in patch.iter_mut().zip(points.iter()) {
+                    *point = point_from_json(json, "Bezier patch control point");
+                }
+                patch
+            }).collect()
+        }
+    };
+    box BezierPatches::new(patches).tessellate(tolerance, bvh_params)
+}
+
+/// Hair, fur or grass, as "ribbon" or "tube" strands from a strand
+/// "file", or from a "strands" list. Each strand has its Bézier control
+/// "points" and a "width", either one number or [root, tip].
+fn curves_from_json(object: &JsonObject, base_dir: &Path,
+                    bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let shape = match object.find(&"shape".to_string()) {
+        Some(shape) => match shape.as_string().expect("Curves shape isn't a string") {
+            "ribbon" => CurveRibbon,
+            "tube" => CurveTube,
+            x => fail!("Unsupported curve shape '{}', expected 'ribbon' or 'tube'", x)
+        },
+        None => CurveRibbon
+    };
+    let strands = match object.find(&"file".to_string()) {
+        Some(file) => {
+            let file = file.as_string()
+                           .expect("Curves file isn't a string");
+            read_strands(&base_dir.join(file))
+        },
+        None => {
+            let strands = object.find(&"strands".to_string())
+                                .expect("Curves don't have a file or strands")
+                                .as_list()
+                                .expect("Curves strands aren't a list");
+            strands.iter().map(|strand| {
+                let strand = strand.as_object()
+                                   .expect("Curve strand isn't a JSON object");
+                let points = strand.find(&"points".to_string())
+                                   .expect("Curve strand doesn't have points")
+                                   .as_list()
+                                   .expect("Curve strand points aren't a list")
+                                   .iter()
+                                   .map(|p| point_from_json(p, "Curve point"))
+                                   .collect();
+                let width = strand.find(&"width".to_string())
+                                  .expect("Curve strand doesn't have a width");
+                let (root, tip) = match width.as_list() {
+                    Some(widths) if widths.len() == 2 => {
+                        (widths[0].as_f64().expect("Curve widths should only contain numbers") as f32,
+                         widths[1].as_f64().expect("Curve widths should only contain numbers") as f32)
+                    },
+                    Some(_) => fail!("Curve strand width isn't a number or [root, tip]"),
+                    None => {
+                        let width = width.as_f64()
+                                         .expect("Curve strand width isn't a number or [root, tip]") as f32;
+                        (width, width)
+                    }
+                };
+                (points, root, tip)
+            }).collect()
+        }
+    };
+    box Curves::new(shape, strands, bvh_params)
+}
+
+/// Particles from a CSV or binary "file", told apart by its extension.
+/// With a "radius" they all share it and the file only has positions,
+/// otherwise each particle's radius follows its position.
+fn particles_from_json(object: &JsonObject, base_dir: &Path,
+                       bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("Particles don't have a file")
+                     .as_string()
+                     .expect("Particles file isn't a string");
+    let radius = object.find(&"radius".to_string()).map(|json| {
+        json.as_f64().expect("Particles radius isn't a number") as f32
+    });
+    let data = read_particles(&base_dir.join(file), radius.is_none());
+    let radii = match radius {
+        Some(radius) => vec![radius],
+        None => data.radii
+    };
+    box Particles::new(data.xs, data.ys, data.zs, radii, bvh_params)
+}
+
+/// Blobs where the field of the "balls" reaches "threshold". Each ball has
+/// a "position" and "radius", and a "weight" that defaults to 1.
+fn metaballs_from_json(object: &JsonObject, bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let threshold = find_number(object, "threshold", "Metaballs");
+    let balls = object.find(&"balls".to_string())
+                      .expect("Metaballs don't have balls")
+                      .as_list()
+                      .expect("Metaballs balls aren't a list");
+    let balls = balls.iter().map(|ball| {
+        let ball = ball.as_object()
+                       .expect("Metaball isn't a JSON object");
+        let weight = match ball.find(&"weight".to_string()) {
+            Some(json) => json.as_f64().expect("Metaball weight isn't a number") as f32,
+            None => 1.0
+        };
+        let center = find_vector(ball, "position", "Metaball");
+        Metaball { center: Point3::new(center.x, center.y, center.z),
+                   radius: find_number(ball, "radius", "Metaball"),
+                   weight: weight }
+    }).collect();
+    box Metaballs::new(balls, threshold, bvh_params)
+}
+
+/// A MagicaVoxel "file", with its corner at "position" and each voxel
+/// "voxel size" wide. The voxels' palette colors tint the material.
+fn voxels_from_json(object: &JsonObject, material: &Material,
+                    base_dir: &Path) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("Voxels don't have a file")
+                     .as_string()
+                     .expect("Voxels file isn't a string");
+    let position = match object.find(&"position".to_string()) {
+        Some(json) => point_from_json(json, "Voxels position"),
+        None => Point3::new(0.0, 0.0, 0.0)
+    };
+    let voxel_size = match object.find(&"voxel size".to_string()) {
+        Some(json) => json.as_f64().expect("Voxels voxel size isn't a number") as f32,
+        None => 1.0
+    };
+    let model = read_vox(&base_dir.join(file));
+    box Voxels::new(model.voxels, model.size, model.palette, material, position, voxel_size)
+}
+
+/// A heightfield from a grayscale PNG, black at "position" and white
+/// "height" above it, stretched over "size" along x and y. The top of the
+/// image is at the far end along y.
+fn heightfield_from_json(object: &JsonObject, base_dir: &Path) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("Heightfield doesn't have a file")
+                     .as_string()
+                     .expect("Heightfield file isn't a string");
+    let pos = find_vector(object, "position", "Heightfield");
+    let size = object.find(&"size".to_string())
+                     .expect("Heightfield doesn't have a size")
+                     .as_list()
+                     .expect("Heightfield size isn't of form [x, y]");
+    if size.len() != 2 {
+        fail!("Heightfield size has {} values instead of 2", size.len());
+    }
+    let width = size[0].as_f64().expect("Heightfield size should only contain numbers") as f32;
+    let depth = size[1].as_f64().expect("Heightfield size should only contain numbers") as f32;
+    let height = find_number(object, "height", "Heightfield");
+
+    let image = load_grayscale(&base_dir.join(file));
+    // Heightfield rows go along +y, so flip the image over
+    let mut heights = Vec::with_capacity(image.pixels.len());
+    for row in range(0, image.height).rev() {
+        heights.push_all(image.pixels.slice(row * image.width, (row + 1) * image.width));
+    }
+    box Heightfield::new(heights, image.width, image.height,
+                         (pos.x, pos.y, pos.z), (width, depth), height)
+}
+
+fn sdf_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let field = object.find(&"field".to_string())
+                      .expect("SDF object doesn't have a field");
+    box SphereTraced::new(field_from_json(field))
+}
+
+fn sphere_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
     let pos = object.find(&"position".to_string())
                     .expect("Object doesn't have a position")
                     .as_list()
@@ -41,6 +476,347 @@ fn parse_obj(object_json: &Json, materials: &TreeMap<String, Arc<Material>>) ->
     let x = pos[0].as_f64().expect("Position should only contain numbers") as f32;
     let y = pos[1].as_f64().expect("Position should only contain numbers") as f32;
     let z = pos[2].as_f64().expect("Position should only contain numbers") as f32;
-    SceneObject { geometry: box Sphere::new((x, y, z), radius),
-                  material: material.clone() }
+    box Sphere::new((x, y, z), radius)
+}
+
+/// A triangle mesh from an STL file. With "weld" set, facets share the
+/// corners they have in common, and with a "crease angle" in degrees the
+/// normals are smoothed where facets meet at less than it.
+fn stl_from_json(object: &JsonObject, base_dir: &Path,
+                 bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let file = object.find(&"file".to_string())
+                     .expect("STL mesh doesn't have a file")
+                     .as_string()
+                     .expect("STL mesh file isn't a string");
+    let weld = match object.find(&"weld".to_string()) {
+        Some(json) => json.as_boolean().expect("STL mesh weld isn't true or false"),
+        None => false
+    };
+    let crease_angle = object.find(&"crease angle".to_string()).map(|json| {
+        json.as_f64().expect("STL mesh crease angle isn't a number") as f32
+    });
+    let options = StlOptions { weld: weld, crease_angle: crease_angle };
+    box read_stl(&base_dir.join(file)).to_mesh(&options, bvh_params)
+}
+
+fn plane_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let pos = find_vector(object, "position", "Plane");
+    let normal = find_vector(object, "normal", "Plane");
+    box Plane::new((pos.x, pos.y, pos.z), (normal.x, normal.y, normal.z))
+}
+
+fn disk_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let pos = find_vector(object, "position", "Disk");
+    let normal = find_vector(object, "normal", "Disk");
+    let radius = find_number(object, "radius", "Disk");
+    box Disk::new((pos.x, pos.y, pos.z), (normal.x, normal.y, normal.z), radius)
+}
+
+fn rectangle_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let corner = find_vector(object, "position", "Rectangle");
+    let edges = object.find(&"edges".to_string())
+                      .expect("Rectangle doesn't have edges")
+                      .as_list()
+                      .expect("Rectangle edges aren't of form [[x, y, z], [x, y, z]]");
+    if edges.len() != 2 {
+        fail!("Rectangle has {} edges instead of 2", edges.len());
+    }
+    let a = vector_from_json(&edges[0], "Rectangle edge");
+    let b = vector_from_json(&edges[1], "Rectangle edge");
+    box Rectangle::new((corner.x, corner.y, corner.z), (a.x, a.y, a.z), (b.x, b.y, b.z))
+}
+
+fn box_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let min = find_vector(object, "min", "Box");
+    let max = find_vector(object, "max", "Box");
+    box AxisAlignedBox::new((min.x, min.y, min.z), (max.x, max.y, max.z))
+}
+
+fn oriented_box_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let pos = find_vector(object, "position", "Oriented box");
+    let size = find_vector(object, "size", "Oriented box");
+    let rotation = find_vector(object, "rotation", "Oriented box");
+    box OrientedBox::new((pos.x, pos.y, pos.z), (size.x, size.y, size.z),
+                         (rotation.x, rotation.y, rotation.z))
+}
+
+fn cylinder_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let start = find_vector(object, "start", "Cylinder");
+    let end = find_vector(object, "end", "Cylinder");
+    let radius = find_number(object, "radius", "Cylinder");
+    box Cylinder::new((start.x, start.y, start.z), (end.x, end.y, end.z), radius)
+}
+
+fn cone_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let start = find_vector(object, "start", "Cone");
+    let end = find_vector(object, "end", "Cone");
+    let radius = find_number(object, "radius", "Cone");
+    // A nonzero end radius makes a truncated cone
+    let end_radius = match object.find(&"end radius".to_string()) {
+        Some(json) => json.as_f64().expect("Cone end radius isn't a number") as f32,
+        None => 0.0
+    };
+    box Cone::new((start.x, start.y, start.z), (end.x, end.y, end.z), radius, end_radius)
+}
+
+/// A "profile" of [radius, height] points turned around the "axis" from
+/// "position". With "spline" set, the profile is a smooth curve through
+/// the points, cut into "steps" segments between each pair. Splines are
+/// smooth shaded unless "smooth" says otherwise.
+fn lathe_from_json(object: &JsonObject, bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let position = find_vector(object, "position", "Lathe");
+    let axis = match object.find(&"axis".to_string()) {
+        Some(json) => vector_from_json(json, "Lathe axis"),
+        None => Vector3::unit_z()
+    };
+    let points = object.find(&"profile".to_string())
+                       .expect("Lathe doesn't have a profile")
+                       .as_list()
+                       .expect("Lathe profile isn't a list");
+    let profile: Vec<(f32, f32)> = points.iter().map(|point| {
+        let point = point.as_list()
+                         .expect("Lathe profile point isn't of form [radius, height]");
+        if point.len() != 2 {
+            fail!("Lathe profile point has {} values instead of 2", point.len());
+        }
+        (point[0].as_f64().expect("Lathe profile should only contain numbers") as f32,
+         point[1].as_f64().expect("Lathe profile should only contain numbers") as f32)
+    }).collect();
+    let spline = match object.find(&"spline".to_string()) {
+        Some(json) => json.as_boolean().expect("Lathe spline isn't true or false"),
+        None => false
+    };
+    let smooth = match object.find(&"smooth".to_string()) {
+        Some(json) => json.as_boolean().expect("Lathe smooth isn't true or false"),
+        None => spline
+    };
+    let profile = if spline {
+        let steps = match object.find(&"steps".to_string()) {
+            Some(json) => json.as_u64().expect("Lathe steps isn't a number") as uint,
+            None => 16
+        };
+        catmull_rom(profile.as_slice(), steps)
+    } else {
+        profile
+    };
+    box Lathe::new(Point3::new(position.x, position.y, position.z), axis, profile, smooth,
+                   bvh_params)
+}
+
+fn capsule_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let start = find_vector(object, "start", "Capsule");
+    let end = find_vector(object, "end", "Capsule");
+    let radius = find_number(object, "radius", "Capsule");
+    box Capsule::new((start.x, start.y, start.z), (end.x, end.y, end.z), radius)
+}
+
+fn torus_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let pos = find_vector(object, "position", "Torus");
+    let axis = match object.find(&"axis".to_string()) {
+        Some(json) => vector_from_json(json, "Torus axis"),
+        None => Vector3::unit_z()
+    };
+    let major_radius = find_number(object, "major radius", "Torus");
+    let minor_radius = find_number(object, "minor radius", "Torus");
+    box Torus::new((pos.x, pos.y, pos.z), (axis.x, axis.y, axis.z), major_radius, minor_radius)
+}
+
+fn triangle_from_json(object: &JsonObject) -> Box<Intersectable+Send+Sync> {
+    let vertices = object.find(&"vertices".to_string())
+                         .expect("Triangle doesn't have vertices")
+                         .as_list()
+                         .expect("Triangle vertices aren't a list");
+    if vertices.len() != 3 {
+        fail!("Triangle has {} vertices instead of 3", vertices.len());
+    }
+    let a = point_from_json(&vertices[0], "Triangle vertex");
+    let b = point_from_json(&vertices[1], "Triangle vertex");
+    let c = point_from_json(&vertices[2], "Triangle vertex");
+    box Triangle::new((a.x, a.y, a.z), (b.x, b.y, b.z), (c.x, c.y, c.z))
+}
+
+fn mesh_from_json(object: &JsonObject, bvh_params: &BvhParams) -> Box<Intersectable+Send+Sync> {
+    let vertices = object.find(&"vertices".to_string())
+                         .expect("Mesh doesn't have vertices")
+                         .as_list()
+                         .expect("Mesh vertices aren't a list")
+                         .iter()
+                         .map(|v| point_from_json(v, "Mesh vertex"))
+                         .collect();
+    let indices = object.find(&"indices".to_string())
+                        .expect("Mesh doesn't have indices")
+                        .as_list()
+                        .expect("Mesh indices aren't a list of [a, b, c] triangles");
+    let mut triangles = Vec::with_capacity(indices.len());
+    for triangle in indices.iter() {
+        let triangle = triangle.as_list()
+                               .expect("Mesh triangle isn't of form [a, b, c]");
+        if triangle.len() != 3 {
+            fail!("Mesh triangle has {} indices instead of 3", triangle.len());
+        }
+        let a = triangle[0].as_u64().expect("Indices should only contain integers") as uint;
+        let b = triangle[1].as_u64().expect("Indices should only contain integers") as uint;
+        let c = triangle[2].as_u64().expect("Indices should only contain integers") as uint;
+        triangles.push((a, b, c));
+    }
+
+    let mesh = TriangleMesh::new(vertices, triangles, bvh_params);
+    let mesh = match object.find(&"normals".to_string()) {
+        Some(normals) => {
+            let normals = normals.as_list()
+                                 .expect("Mesh normals aren't a list")
+                                 .iter()
+                                 .map(|n| vector_from_json(n, "Mesh normal"))
+                                 .collect();
+            mesh.with_normals(normals)
+        },
+        None => mesh
+    };
+    box mesh
+}
+
+/// Loads an OBJ file as one object per group. Each group is given the
+/// material named for it in the optional "groups" map, or else the
+/// material its `usemtl` or group name refers to, or else the object's
+/// own material.
+fn obj_file_from_json(object: &JsonObject, default_material: &Arc<Material>,
+                      materials: &TreeMap<String, Arc<Material>>,
+                      base_dir: &Path, bvh_params: &BvhParams) -> Vec<SceneObject> {
+    let file = object.find(&"file".to_string())
+                     .expect("Mesh doesn't have a file")
+                     .as_string()
+                     .expect("Mesh file isn't a string");
+    let group_materials = object.find(&"groups".to_string())
+                                .map(|groups| groups.as_object()
+                                                    .expect("Mesh groups isn't an object"));
+    let obj = read_obj(&base_dir.join(file));
+
+    let mut scene_objects = Vec::with_capacity(obj.groups.len());
+    for group in obj.groups.iter() {
+        let mapped = group_materials.and_then(|groups| groups.find(&group.name)).map(|name| {
+            let name = name.as_string()
+                           .expect("Mesh group material isn't a string");
+            materials.find(&name.to_string())
+                     .expect(format!("No material with name '{}'", name).as_slice())
+        });
+        let by_name = group.material.as_ref()
+                                    .and_then(|name| materials.find(name))
+                                    .or_else(|| materials.find(&group.name));
+        let material = mapped.or(by_name).unwrap_or(default_material);
+        scene_objects.push(SceneObject { geometry: box group.to_mesh(&obj, bvh_params),
+                                         material: material.clone() });
+    }
+    scene_objects
+}
+
+/// Builds an object to world matrix from either a raw row-major 4x4
+/// "matrix", or from "scale", "rotate" (Euler angles in degrees) or
+/// "quaternion" ([w, x, y, z]), and "translate", applied in that order.
+pub fn transform_from_json(json: &Json) -> Matrix4<f32> {
+    let transform = json.as_object()
+                        .expect("Transform isn't a JSON object");
+    match transform.find(&"matrix".to_string()) {
+        Some(matrix) => {
+            // Accept either a flat list of 16 numbers or a list of 4 rows
+            let mut rows = Vec::with_capacity(16);
+            for row in matrix.as_list().expect("Transform matrix isn't a list").iter() {
+                match row.as_list() {
+                    Some(row) => rows.extend(row.iter().map(|x| matrix_entry(x))),
+                    None => rows.push(matrix_entry(row))
+                }
+            }
+            if rows.len() != 16 {
+                fail!("Transform matrix has {} entries instead of 16", rows.len());
+            }
+            let m = rows.as_slice();
+            return Matrix4::new(m[0], m[4], m[8],  m[12],
+                                m[1], m[5], m[9],  m[13],
+                                m[2], m[6], m[10], m[14],
+                                m[3], m[7], m[11], m[15]);
+        },
+        None => ()
+    }
+
+    let scale = match transform.find(&"scale".to_string()) {
+        Some(json) if json.is_number() => {
+            let s = json.as_f64().unwrap() as f32;
+            Vector3::new(s, s, s)
+        },
+        Some(json) => vector_from_json(json, "Transform scale"),
+        None => Vector3::new(1.0, 1.0, 1.0)
+    };
+    let axes = match (transform.find(&"rotate".to_string()),
+                      transform.find(&"quaternion".to_string())) {
+        (Some(_), Some(_)) => fail!("Transform has both rotate and quaternion"),
+        (Some(json), None) => {
+            let angles = vector_from_json(json, "Transform rotate");
+            (rotate_euler(&Vector3::unit_x(), &angles),
+             rotate_euler(&Vector3::unit_y(), &angles),
+             rotate_euler(&Vector3::unit_z(), &angles))
+        },
+        (None, Some(json)) => quaternion_axes(json),
+        (None, None) => (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z())
+    };
+    let translate = match transform.find(&"translate".to_string()) {
+        Some(json) => vector_from_json(json, "Transform translate"),
+        None => Vector3::new(0.0, 0.0, 0.0)
+    };
+
+    let (x, y, z) = axes;
+    let (x, y, z) = (x.mul_s(scale.x), y.mul_s(scale.y), z.mul_s(scale.z));
+    Matrix4::new(x.x, x.y, x.z, 0.0,
+                 y.x, y.y, y.z, 0.0,
+                 z.x, z.y, z.z, 0.0,
+                 translate.x, translate.y, translate.z, 1.0)
+}
+
+fn matrix_entry(json: &Json) -> f32 {
+    json.as_f64().expect("Transform matrix should only contain numbers") as f32
+}
+
+/// Where a [w, x, y, z] quaternion rotates the x, y and z axes to.
+fn quaternion_axes(json: &Json) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+    let q = json.as_list()
+                .expect("Transform quaternion isn't of form [w, x, y, z]");
+    if q.len() != 4 {
+        fail!("Transform quaternion isn't of form [w, x, y, z]");
+    }
+    let q: Vec<f32> = q.iter()
+        .map(|x| x.as_f64().expect("Quaternion should only contain numbers") as f32)
+        .collect();
+    let length = q.iter().fold(0.0, |sum, &x| sum + x*x).sqrt();
+    let (w, x, y, z) = (q[0] / length, q[1] / length, q[2] / length, q[3] / length);
+    (Vector3::new(1.0 - 2.0*(y*y + z*z), 2.0*(x*y + w*z), 2.0*(x*z - w*y)),
+     Vector3::new(2.0*(x*y - w*z), 1.0 - 2.0*(x*x + z*z), 2.0*(y*z + w*x)),
+     Vector3::new(2.0*(x*z + w*y), 2.0*(y*z - w*x), 1.0 - 2.0*(x*x + y*y)))
+}
+
+pub fn find_number(object: &JsonObject, key: &str, name: &str) -> f32 {
+    object.find(&key.to_string())
+          .expect(format!("{} doesn't have a {}", name, key).as_slice())
+          .as_f64()
+          .expect(format!("{} {} isn't a number", name, key).as_slice()) as f32
+}
+
+pub fn find_vector(object: &JsonObject, key: &str, name: &str) -> Vector3<f32> {
+    let json = object.find(&key.to_string())
+                     .expect(format!("{} doesn't have a {}", name, key).as_slice());
+    vector_from_json(json, format!("{} {}", name, key).as_slice())
+}
+
+fn point_from_json(json: &Json, name: &str) -> Point3<f32> {
+    let v = vector_from_json(json, name);
+    Point3::new(v.x, v.y, v.z)
+}
+
+pub fn vector_from_json(json: &Json, name: &str) -> Vector3<f32> {
+    let list = json.as_list()
+                   .expect(format!("{} isn't of form [x, y, z]", name).as_slice());
+    if list.len() != 3 {
+        fail!("{} isn't of form [x, y, z]", name);
+    }
+    let x = list[0].as_f64().expect(format!("{} should only contain numbers", name).as_slice()) as f32;
+    let y = list[1].as_f64().expect(format!("{} should only contain numbers", name).as_slice()) as f32;
+    let z = list[2].as_f64().expect(format!("{} should only contain numbers", name).as_slice()) as f32;
+    Vector3::new(x, y, z)
 }
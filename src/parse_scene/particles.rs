@@ -0,0 +1,107 @@
+use std::ascii::StrAsciiExt;
+use std::io::{File, BufferedReader};
+use std::mem::transmute;
+
+/// Particle coordinates, and their radii if the file had them.
+pub struct ParticleData {
+    pub xs: Vec<f32>,
+    pub ys: Vec<f32>,
+    pub zs: Vec<f32>,
+    pub radii: Vec<f32>
+}
+
+/// Reads particles from a `.csv` file, with one particle per line, or from
+/// a `.bin` or `.raw` file of little endian 32 bit floats, one record after
+/// another. Each particle is x, y and z, followed by its radius when
+/// `with_radii` is set. A CSV file can start with a line of column names.
+pub fn read_particles(path: &Path, with_radii: bool) -> ParticleData {
+    let columns = if with_radii { 4 } else { 3 };
+    // Anything else read as raw floats would load as garbage
+    let extension = path.extension_str().map(|e| e.to_ascii_lower());
+    match extension.as_ref().map(|e| e.as_slice()) {
+        Some("csv") => read_csv(path, columns),
+        Some("bin") | Some("raw") => read_binary(path, columns),
+        _ => fail!("{}: Particle files have to be .csv, .bin or .raw", path.display())
+    }
+}
+
+impl ParticleData {
+    fn with_capacity(count: uint, columns: uint) -> ParticleData {
+        ParticleData { xs: Vec::with_capacity(count),
+                       ys: Vec::with_capacity(count),
+                       zs: Vec::with_capacity(count),
+                       radii: Vec::with_capacity(if columns == 4 { count } else { 0 }) }
+    }
+
+    fn push(&mut self, record: &[f32]) {
+        self.xs.push(record[0]);
+        self.ys.push(record[1]);
+        self.zs.push(record[2]);
+        if record.len() == 4 {
+            self.radii.push(record[3]);
+        }
+    }
+}
+
+fn read_binary(path: &Path, columns: uint) -> ParticleData {
+    let contents = match File::open(path).read_to_end() {
+        Ok(contents) => contents,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let record_size = 4 * columns;
+    if contents.len() % record_size != 0 {
+        fail!("{} is {} bytes, which isn't a whole number of {} byte particles",
+              path.display(), contents.len(), record_size);
+    }
+    let count = contents.len() / record_size;
+    let mut particles = ParticleData::with_capacity(count, columns);
+    let mut record = [0.0f32, ..4];
+    for i in range(0, count) {
+        for c in range(0, columns) {
+            let offset = i * record_size + 4 * c;
+            let bits = range(0u, 4).rev().fold(0u32, |bits, b| {
+                (bits << 8) | contents[offset + b] as u32
+            });
+            record[c] = unsafe { transmute::<u32, f32>(bits) };
+        }
+        particles.push(record.slice_to(columns));
+    }
+    particles
+}
+
+fn read_csv(path: &Path, columns: uint) -> ParticleData {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let mut reader = BufferedReader::new(file);
+    let mut particles = ParticleData::with_capacity(0, columns);
+    let mut record = [0.0f32, ..4];
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => fail!("Error reading {}: {}", path.display(), err)
+        };
+        let context = format!("{}:{}", path.display(), line_number + 1);
+        let line = line.as_slice().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() != columns {
+            fail!("{}: Particle has {} columns instead of {}", context, fields.len(), columns);
+        }
+        for (c, field) in fields.iter().enumerate() {
+            record[c] = match from_str::<f32>(*field) {
+                Some(number) => number,
+                // Column names
+                None if line_number == 0 => break,
+                None => fail!("{}: '{}' isn't a number", context, field)
+            };
+            if c + 1 == columns {
+                particles.push(record.slice_to(columns));
+            }
+        }
+    }
+    particles
+}
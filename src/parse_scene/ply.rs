@@ -0,0 +1,369 @@
+use std::io::File;
+use std::mem::transmute;
+use std::str;
+use cgmath::{Point3, Vector3};
+use image_types::Color;
+use scene::{TriangleMesh, BvhParams};
+
+/// The triangles and vertex attributes of a Stanford PLY file. Attributes
+/// the file doesn't have are left empty.
+pub struct PlyMesh {
+    pub positions: Vec<Point3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<(f32, f32)>,
+    pub colors: Vec<Color>,
+    pub triangles: Vec<(uint, uint, uint)>
+}
+
+#[deriving(PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[deriving(Clone, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+enum Property {
+    Scalar(String, ScalarType),
+    /// A list with its length stored as the first type, then its items
+    List(String, ScalarType, ScalarType)
+}
+
+struct Element {
+    name: String,
+    count: uint,
+    properties: Vec<Property>
+}
+
+/// Reads values from the body of the file, whatever its format.
+struct Body<'a> {
+    data: &'a [u8],
+    position: uint,
+    format: Format,
+    path: &'a Path
+}
+
+impl ScalarType {
+    fn parse(name: &str, path: &Path) -> ScalarType {
+        match name {
+            "char" | "int8"    => Int8,
+            "uchar" | "uint8"  => UInt8,
+            "short" | "int16"  => Int16,
+            "ushort" | "uint16" => UInt16,
+            "int" | "int32"    => Int32,
+            "uint" | "uint32"  => UInt32,
+            "float" | "float32" => Float32,
+            "double" | "float64" => Float64,
+            x => fail!("{}: Unsupported PLY property type '{}'", path.display(), x)
+        }
+    }
+
+    fn size(&self) -> uint {
+        match *self {
+            Int8 | UInt8 => 1,
+            Int16 | UInt16 => 2,
+            Int32 | UInt32 | Float32 => 4,
+            Float64 => 8
+        }
+    }
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match *self {
+            Scalar(ref name, _) => name.as_slice(),
+            List(ref name, _, _) => name.as_slice()
+        }
+    }
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar: ScalarType) -> f64 {
+        if self.format == Ascii {
+            self.read_ascii()
+        } else {
+            self.read_binary(scalar)
+        }
+    }
+
+    fn read_ascii(&mut self) -> f64 {
+        let is_space = |byte: u8| (byte as char).is_whitespace();
+        while self.position < self.data.len() && is_space(self.data[self.position]) {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.position < self.data.len() && !is_space(self.data[self.position]) {
+            self.position += 1;
+        }
+        if start == self.position {
+            fail!("{}: File ends before all its elements", self.path.display());
+        }
+        let word = str::from_utf8(self.data.slice(start, self.position));
+        match word.and_then(|word| from_str::<f64>(word)) {
+            Some(value) => value,
+            None => fail!("{}: '{}' isn't a number", self.path.display(), word)
+        }
+    }
+
+    fn read_binary(&mut self, scalar: ScalarType) -> f64 {
+        let size = scalar.size();
+        if self.position + size > self.data.len() {
+            fail!("{}: File ends before all its elements", self.path.display());
+        }
+        // Gather the bytes into an integer, most significant first
+        let mut bits = 0u64;
+        for i in range(0, size) {
+            let byte = if self.format == BinaryBigEndian {
+                self.data[self.position + i]
+            } else {
+                self.data[self.position + size - 1 - i]
+            };
+            bits = (bits << 8) | byte as u64;
+        }
+        self.position += size;
+        match scalar {
+            Int8 => bits as u8 as i8 as f64,
+            Int16 => bits as u16 as i16 as f64,
+            Int32 => bits as u32 as i32 as f64,
+            UInt8 | UInt16 | UInt32 => bits as f64,
+            Float32 => unsafe { transmute::<u32, f32>(bits as u32) as f64 },
+            Float64 => unsafe { transmute::<u64, f64>(bits) }
+        }
+    }
+
+    /// Reads every property of one element, with lists read but left out.
+    fn read_element(&mut self, element: &Element) -> Vec<f64> {
+        element.properties.iter().map(|property| {
+            match *property {
+                Scalar(_, scalar) => self.read(scalar),
+                List(_, count, item) => {
+                    let count = self.read(count) as uint;
+                    for _ in range(0, count) {
+                        self.read(item);
+                    }
+                    0.0
+                }
+            }
+        }).collect()
+    }
+}
+
+/// The index of the first property with one of the given names.
+fn find_property(element: &Element, names: &[&str]) -> Option<uint> {
+    element.properties.iter().position(|property| names.contains(&property.name()))
+}
+
+pub fn read_ply(path: &Path) -> PlyMesh {
+    let contents = match File::open(path).read_to_end() {
+        Ok(contents) => contents,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+
+    let mut position = 0;
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line_number = 0u;
+    loop {
+        let end = match contents.slice_from(position).iter().position(|&byte| byte == b'\n') {
+            Some(length) => position + length,
+            None => fail!("{}: Header doesn't have an end_header line", path.display())
+        };
+        let line = str::from_utf8(contents.slice(position, end))
+                       .expect(format!("{}: Header isn't text", path.display()).as_slice());
+        position = end + 1;
+        line_number += 1;
+        let words: Vec<&str> = line.words().collect();
+        if line_number == 1 {
+            if words.len() != 1 || words[0] != "ply" {
+                fail!("{} isn't a PLY file", path.display());
+            }
+            continue;
+        }
+        if words.is_empty() {
+            continue;
+        }
+        let context = format!("{}:{}", path.display(), line_number);
+        match words[0] {
+            "format" if words.len() >= 2 => {
+                format = Some(match words[1] {
+                    "ascii" => Ascii,
+                    "binary_little_endian" => BinaryLittleEndian,
+                    "binary_big_endian" => BinaryBigEndian,
+                    x => fail!("{}: Unsupported PLY format '{}'", context, x)
+                });
+            },
+            "element" if words.len() >= 3 => {
+                let count = from_str::<uint>(words[2])
+                    .expect(format!("{}: Element count isn't a number", context).as_slice());
+                elements.push(Element { name: words[1].to_string(),
+                                        count: count,
+                                        properties: Vec::new() });
+            },
+            "property" => {
+                let property = if words.len() >= 5 && words[1] == "list" {
+                    List(words[4].to_string(),
+                         ScalarType::parse(words[2], path),
+                         ScalarType::parse(words[3], path))
+                } else if words.len() >= 3 {
+                    Scalar(words[2].to_string(), ScalarType::parse(words[1], path))
+                } else {
+                    fail!("{}: Property is missing its type or name", context)
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => fail!("{}: Property comes before any element", context)
+                }
+            },
+            "end_header" => break,
+            // Comments and obj_info lines
+            _ => ()
+        }
+    }
+
+    let format = format.expect(format!("{}: Header doesn't give a format", path.display()).as_slice());
+    let mut body = Body { data: contents.slice_from(position),
+                          position: 0,
+                          format: format,
+                          path: path };
+    let mut ply = PlyMesh { positions: Vec::new(),
+                            normals: Vec::new(),
+                            uvs: Vec::new(),
+                            colors: Vec::new(),
+                            triangles: Vec::new() };
+
+    for element in elements.iter() {
+        match element.name.as_slice() {
+            "vertex" => read_vertices(&mut body, element, &mut ply),
+            "face" => read_faces(&mut body, element, &mut ply),
+            // Edges, materials and anything else is skipped
+            _ => {
+                for _ in range(0, element.count) {
+                    body.read_element(element);
+                }
+            }
+        }
+    }
+    for &(a, b, c) in ply.triangles.iter() {
+        let count = ply.positions.len();
+        if a >= count || b >= count || c >= count {
+            fail!("{}: Face refers to a vertex past the {} in the file", path.display(), count);
+        }
+    }
+    ply
+}
+
+fn read_vertices(body: &mut Body, element: &Element, ply: &mut PlyMesh) {
+    let position = (find_property(element, &["x"]),
+                    find_property(element, &["y"]),
+                    find_property(element, &["z"]));
+    let (x, y, z) = match position {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => fail!("{}: Vertices don't have x, y and z properties", body.path.display())
+    };
+    let normal = match (find_property(element, &["nx"]),
+                        find_property(element, &["ny"]),
+                        find_property(element, &["nz"])) {
+        (Some(nx), Some(ny), Some(nz)) => Some((nx, ny, nz)),
+        _ => None
+    };
+    let uv = match (find_property(element, &["u", "s", "texture_u", "texture_s"]),
+                    find_property(element, &["v", "t", "texture_v", "texture_t"])) {
+        (Some(u), Some(v)) => Some((u, v)),
+        _ => None
+    };
+    let color = match (find_property(element, &["red", "r", "diffuse_red"]),
+                       find_property(element, &["green", "g", "diffuse_green"]),
+                       find_property(element, &["blue", "b", "diffuse_blue"])) {
+        (Some(r), Some(g), Some(b)) => {
+            // Integer colors go up to the largest value of their type
+            let scale = match element.properties[r] {
+                Scalar(_, UInt8) => 1.0 / 255.0,
+                Scalar(_, UInt16) => 1.0 / 65535.0,
+                _ => 1.0
+            };
+            Some((r, g, b, scale))
+        },
+        _ => None
+    };
+
+    for _ in range(0, element.count) {
+        let values = body.read_element(element);
+        ply.positions.push(Point3::new(values[x] as f32, values[y] as f32, values[z] as f32));
+        match normal {
+            Some((nx, ny, nz)) => {
+                ply.normals.push(Vector3::new(values[nx] as f32, values[ny] as f32, values[nz] as f32))
+            },
+            None => ()
+        }
+        match uv {
+            Some((u, v)) => ply.uvs.push((values[u] as f32, values[v] as f32)),
+            None => ()
+        }
+        match color {
+            Some((r, g, b, scale)) => ply.colors.push(Color { r: (values[r] * scale) as f32,
+                                                              g: (values[g] * scale) as f32,
+                                                              b: (values[b] * scale) as f32 }),
+            None => ()
+        }
+    }
+}
+
+fn read_faces(body: &mut Body, element: &Element, ply: &mut PlyMesh) {
+    let indices = find_property(element, &["vertex_indices", "vertex_index"]);
+    let indices = match indices {
+        Some(index) => index,
+        None => fail!("{}: Faces don't have a vertex_indices list", body.path.display())
+    };
+    for _ in range(0, element.count) {
+        for (index, property) in element.properties.iter().enumerate() {
+            match *property {
+                Scalar(_, scalar) => {
+                    body.read(scalar);
+                },
+                List(_, count, item) => {
+                    let count = body.read(count) as uint;
+                    let corners: Vec<uint> = range(0, count).map(|_| body.read(item) as uint).collect();
+                    if index != indices {
+                        continue;
+                    }
+                    if corners.len() < 3 {
+                        fail!("{}: Face has fewer than 3 vertices", body.path.display());
+                    }
+                    // Fan out from the first corner, which is fine for the
+                    // convex polygons scanners produce.
+                    for i in range(1, corners.len() - 1) {
+                        ply.triangles.push((corners[0], corners[i], corners[i + 1]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl PlyMesh {
+    pub fn to_mesh(self, bvh_params: &BvhParams) -> TriangleMesh {
+        let PlyMesh { positions, normals, uvs, colors, triangles } = self;
+        let mut mesh = TriangleMesh::new(positions, triangles, bvh_params);
+        if !normals.is_empty() {
+            mesh = mesh.with_normals(normals);
+        }
+        if !uvs.is_empty() {
+            mesh = mesh.with_uvs(uvs);
+        }
+        if !colors.is_empty() {
+            mesh = mesh.with_colors(colors);
+        }
+        mesh
+    }
+}
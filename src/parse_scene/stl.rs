@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::io::File;
+use std::mem::transmute;
+use std::str;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Point3, Vector3};
+use cgmath::dot;
+use scene::{TriangleMesh, BvhParams};
+
+/// The facets of an STL file. STL has no shared vertices, so each facet
+/// has its own three corners.
+pub struct StlMesh {
+    pub facets: Vec<[Point3<f32>, ..3]>
+}
+
+/// How to turn the loose facets of an STL file into a mesh.
+pub struct StlOptions {
+    /// Share corners at the same position between facets
+    pub weld: bool,
+    /// Smooth the normals across edges where neighbouring facets meet at
+    /// less than this many degrees. Without it facets are shaded flat.
+    pub crease_angle: Option<f32>
+}
+
+pub fn read_stl(path: &Path) -> StlMesh {
+    let contents = match File::open(path).read_to_end() {
+        Ok(contents) => contents,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    // Plenty of binary files start with "solid" too, so go by whether the
+    // size matches the facet count a binary file would have.
+    if contents.len() >= 84 {
+        let count = read_u32(contents.as_slice(), 80) as uint;
+        if contents.len() == 84 + 50 * count {
+            return read_binary(contents.as_slice(), count);
+        }
+    }
+    if contents.as_slice().starts_with(b"solid") {
+        read_ascii(contents.as_slice(), path)
+    } else {
+        fail!("{} isn't an ASCII STL file, and is the wrong size to be a binary one",
+              path.display())
+    }
+}
+
+fn read_u32(data: &[u8], offset: uint) -> u32 {
+    // Binary STL is always little endian
+    range(0, 4).rev().fold(0u32, |bits, i| (bits << 8) | data[offset + i] as u32)
+}
+
+fn read_f32(data: &[u8], offset: uint) -> f32 {
+    unsafe { transmute::<u32, f32>(read_u32(data, offset)) }
+}
+
+fn read_binary(data: &[u8], count: uint) -> StlMesh {
+    let facets = range(0, count).map(|i| {
+        // Each facet is a normal, three corners and two attribute bytes.
+        // The normal is left out, since plenty of exporters get it wrong.
+        let start = 84 + 50 * i + 12;
+        let corner = |c: uint| {
+            let offset = start + 12 * c;
+            Point3::new(read_f32(data, offset),
+                        read_f32(data, offset + 4),
+                        read_f32(data, offset + 8))
+        };
+        [corner(0), corner(1), corner(2)]
+    }).collect();
+    StlMesh { facets: facets }
+}
+
+fn read_ascii(data: &[u8], path: &Path) -> StlMesh {
+    let text = str::from_utf8(data)
+                   .expect(format!("{} isn't a binary STL file or text", path.display()).as_slice());
+    let mut facets = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    for (number, line) in text.lines().enumerate() {
+        let context = format!("{}:{}", path.display(), number + 1);
+        let words: Vec<&str> = line.words().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match words[0] {
+            "vertex" => {
+                if words.len() < 4 {
+                    fail!("{}: Vertex doesn't have 3 coordinates", context);
+                }
+                let coordinate = |word: &str| {
+                    from_str::<f32>(word)
+                        .expect(format!("{}: '{}' isn't a number", context, word).as_slice())
+                };
+                corners.push(Point3::new(coordinate(words[1]),
+                                         coordinate(words[2]),
+                                         coordinate(words[3])));
+            },
+            "endfacet" => {
+                if corners.len() != 3 {
+                    fail!("{}: Facet has {} vertices instead of 3", context, corners.len());
+                }
+                facets.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+            },
+            // solid, facet normal, outer loop, endloop and endsolid
+            _ => ()
+        }
+    }
+    StlMesh { facets: facets }
+}
+
+/// A key that's the same for corners at exactly the same position.
+fn position_key(point: &Point3<f32>) -> (u32, u32, u32) {
+    // Adding zero turns -0 into 0, so they weld together
+    unsafe {
+        (transmute::<f32, u32>(point.x + 0.0),
+         transmute::<f32, u32>(point.y + 0.0),
+         transmute::<f32, u32>(point.z + 0.0))
+    }
+}
+
+impl StlMesh {
+    pub fn to_mesh(&self, options: &StlOptions, bvh_params: &BvhParams) -> TriangleMesh {
+        // Find which corners are at the same position, both for welding and
+        // to find the facets around each position for smoothing.
+        let mut positions = Vec::new();
+        let mut corner_positions = Vec::with_capacity(3 * self.facets.len());
+        {
+            let mut lookup = HashMap::new();
+            for facet in self.facets.iter() {
+                for corner in facet.iter() {
+                    let key = position_key(corner);
+                    let index = match lookup.find(&key) {
+                        Some(&index) => index,
+                        None => {
+                            lookup.insert(key, positions.len());
+                            positions.push(*corner);
+                            positions.len() - 1
+                        }
+                    };
+                    corner_positions.push(index);
+                }
+            }
+        }
+
+        // Facets with no area can't be hit and have no normal, so leave
+        // them out. CAD exports have plenty of them.
+        let areas: Vec<Vector3<f32>> = self.facets.iter().map(|facet| {
+            facet[1].sub_p(&facet[0]).cross(&facet[2].sub_p(&facet[0]))
+        }).collect();
+        let facets: Vec<uint> = range(0, self.facets.len()).filter(|&f| {
+            areas[f].length2() > 0.0
+        }).collect();
+
+        // Corner normals, the area weighted average of the facets around
+        // the corner that meet its own facet at less than the crease angle
+        let corner_normals = options.crease_angle.map(|angle| {
+            let threshold = (angle * PI / 180.0).cos();
+            let mut around = Vec::from_elem(positions.len(), Vec::new());
+            for &f in facets.iter() {
+                for c in range(0u, 3) {
+                    around[corner_positions[3 * f + c]].push(f);
+                }
+            }
+            let mut normals = Vec::from_elem(3 * self.facets.len(), Vector3::new(0.0f32, 0.0, 0.0));
+            for &f in facets.iter() {
+                let own = areas[f].normalize();
+                for c in range(0u, 3) {
+                    let sum = around[corner_positions[3 * f + c]].iter().fold(areas[f], |sum, &other| {
+                        if other != f && dot(own, areas[other].normalize()) >= threshold {
+                            sum.add_v(&areas[other])
+                        } else {
+                            sum
+                        }
+                    });
+                    normals[3 * f + c] = sum.normalize();
+                }
+            }
+            normals
+        });
+
+        // Corners can only share a vertex if they share a normal too
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut triangles = Vec::with_capacity(facets.len());
+        let mut lookup = HashMap::new();
+        for &f in facets.iter() {
+            let mut indices = [0u, ..3];
+            for c in range(0u, 3) {
+                let corner = 3 * f + c;
+                let normal = corner_normals.as_ref().map(|normals| normals[corner]);
+                let key = (corner_positions[corner],
+                           normal.map(|n| position_key(&Point3::from_vec(&n))));
+                let next = vertices.len();
+                let index = if options.weld {
+                    match lookup.find(&key) {
+                        Some(&index) => index,
+                        None => {
+                            lookup.insert(key, next);
+                            next
+                        }
+                    }
+                } else {
+                    next
+                };
+                if index == next {
+                    vertices.push(positions[corner_positions[corner]]);
+                    match normal {
+                        Some(normal) => normals.push(normal),
+                        None => ()
+                    }
+                }
+                indices[c] = index;
+            }
+            triangles.push((indices[0], indices[1], indices[2]));
+        }
+
+        let mesh = TriangleMesh::new(vertices, triangles, bvh_params);
+        if normals.is_empty() {
+            mesh
+        } else {
+            mesh.with_normals(normals)
+        }
+    }
+}
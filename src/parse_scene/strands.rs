@@ -0,0 +1,38 @@
+use std::io::{File, BufferedReader};
+use cgmath::Point3;
+
+/// Reads a strand file, where each line is one strand: its width at the
+/// root and at the tip, then the x, y and z of its 3n + 1 Bézier control
+/// points. Blank lines and lines starting with # are skipped.
+pub fn read_strands(path: &Path) -> Vec<(Vec<Point3<f32>>, f32, f32)> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    let mut reader = BufferedReader::new(file);
+    let mut strands = Vec::new();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => fail!("Error reading {}: {}", path.display(), err)
+        };
+        let context = format!("{}:{}", path.display(), line_number + 1);
+        let line = line.as_slice().trim();
+        if line.is_empty() || line.starts_with("#") {
+            continue;
+        }
+        let numbers: Vec<f32> = line.words().map(|word| {
+            match from_str::<f32>(word) {
+                Some(number) => number,
+                None => fail!("{}: '{}' isn't a number", context, word)
+            }
+        }).collect();
+        if numbers.len() < 2 || (numbers.len() - 2) % 3 != 0 {
+            fail!("{}: Strand should be two widths then x, y and z for each control point",
+                  context);
+        }
+        let points = numbers.slice_from(2).chunks(3).map(|p| Point3::new(p[0], p[1], p[2])).collect();
+        strands.push((points, numbers[0], numbers[1]));
+    }
+    strands
+}
@@ -0,0 +1,119 @@
+use std::io::File;
+use image_types::Color;
+
+/// A model from a MagicaVoxel file, with its voxels as palette indices, x
+/// fastest, then y, then z, and 0 for empty.
+pub struct VoxModel {
+    pub size: (uint, uint, uint),
+    pub voxels: Vec<u8>,
+    /// 256 colors, where index 0 is never used
+    pub palette: Vec<Color>
+}
+
+fn read_u32(bytes: &[u8], offset: uint) -> u32 {
+    range(0u, 4).rev().fold(0u32, |value, b| (value << 8) | bytes[offset + b] as u32)
+}
+
+fn byte_color(r: u8, g: u8, b: u8) -> Color {
+    Color { r: r as f32 / 255.0, g: g as f32 / 255.0, b: b as f32 / 255.0 }
+}
+
+/// The palette MagicaVoxel uses for files without their own: every mix of
+/// six levels of red, green and blue except black, then ramps of red,
+/// green, blue and gray.
+fn default_palette() -> Vec<Color> {
+    let levels = [0xffu8, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let ramp = [0xeeu8, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = vec![byte_color(0, 0, 0)];
+    for &r in levels.iter() {
+        for &g in levels.iter() {
+            for &b in levels.iter() {
+                if r != 0 || g != 0 || b != 0 {
+                    palette.push(byte_color(r, g, b));
+                }
+            }
+        }
+    }
+    for &(r, g, b) in [(1u8, 0u8, 0u8), (0, 1, 0), (0, 0, 1), (1, 1, 1)].iter() {
+        for &level in ramp.iter() {
+            palette.push(byte_color(r * level, g * level, b * level));
+        }
+    }
+    palette
+}
+
+/// Reads the first model in a MagicaVoxel `.vox` file, along with the
+/// file's palette. Files with several models only have their first one
+/// loaded, since placing the rest needs the file's scene graph.
+pub fn read_vox(path: &Path) -> VoxModel {
+    let bytes = match File::open(path).read_to_end() {
+        Ok(bytes) => bytes,
+        Err(err) => fail!("Error reading {}: {}", path.display(), err)
+    };
+    if bytes.len() < 20 || bytes.slice_to(4) != b"VOX " || bytes.slice(8, 12) != b"MAIN" {
+        fail!("{} isn't a MagicaVoxel file", path.display());
+    }
+
+    let mut size = None;
+    let mut models = 0u;
+    let mut voxels = None;
+    let mut palette = default_palette();
+    // The chunks after MAIN's header are all its children
+    let mut offset = 20;
+    while offset < bytes.len() {
+        if offset + 12 > bytes.len() {
+            fail!("{}: Chunk header at byte {} runs past the end of the file",
+                  path.display(), offset);
+        }
+        let id = bytes.slice(offset, offset + 4);
+        let content_size = read_u32(bytes.as_slice(), offset + 4) as uint;
+        let children_size = read_u32(bytes.as_slice(), offset + 8) as uint;
+        let content = offset + 12;
+        if content + content_size + children_size > bytes.len() {
+            fail!("{}: Chunk at byte {} runs past the end of the file", path.display(), offset);
+        }
+        if id == b"SIZE" && content_size >= 12 {
+            models += 1;
+            if size.is_none() {
+                size = Some((read_u32(bytes.as_slice(), content) as uint,
+                             read_u32(bytes.as_slice(), content + 4) as uint,
+                             read_u32(bytes.as_slice(), content + 8) as uint));
+            }
+        } else if id == b"XYZI" && content_size >= 4 && voxels.is_none() {
+            let (x, y, z) = match size {
+                Some(size) => size,
+                None => fail!("{}: Model voxels come before its size", path.display())
+            };
+            let count = read_u32(bytes.as_slice(), content) as uint;
+            if 4 + 4 * count > content_size {
+                fail!("{}: Model has {} voxels but only room for {}",
+                      path.display(), count, (content_size - 4) / 4);
+            }
+            let mut grid = Vec::from_elem(x * y * z, 0u8);
+            for voxel in bytes.slice(content + 4, content + 4 + 4 * count).chunks(4) {
+                let (vx, vy, vz) = (voxel[0] as uint, voxel[1] as uint, voxel[2] as uint);
+                if vx >= x || vy >= y || vz >= z {
+                    fail!("{}: Voxel at ({}, {}, {}) is outside the {}x{}x{} model",
+                          path.display(), vx, vy, vz, x, y, z);
+                }
+                grid[(vz * y + vy) * x + vx] = voxel[3];
+            }
+            voxels = Some(grid);
+        } else if id == b"RGBA" && content_size >= 1024 {
+            // Palette entry i is for color index i + 1, and the last entry
+            // is never used.
+            for (i, rgba) in bytes.slice(content, content + 1020).chunks(4).enumerate() {
+                palette[i + 1] = byte_color(rgba[0], rgba[1], rgba[2]);
+            }
+        }
+        offset = content + content_size + children_size;
+    }
+
+    if models > 1 {
+        println!("Warning: {}: Only loading the first of {} models", path.display(), models);
+    }
+    match (size, voxels) {
+        (Some(size), Some(voxels)) => VoxModel { size: size, voxels: voxels, palette: palette },
+        _ => fail!("{} doesn't have a model", path.display())
+    }
+}
@@ -0,0 +1,135 @@
+use std::cmp::max;
+use std::default::Default;
+use cgmath::{Vector3, Ray3};
+use scene::{BoundingBox, Bvh, BvhParams, KdTree, Grid};
+
+// Counts are kept per task, so render threads never share them
+local_data_key!(TASK_STATS: TraversalStats)
+
+/// A spatial index over anything with a bounding box, used to skip
+/// primitives a ray can't hit. Like the BVH it only stores indices, and
+/// callers pass in a closure to intersect the primitive with a given index.
+pub trait Accelerator {
+    /// The index and distance of the closest primitive along `ray` that's
+    /// nearer than `max`. `intersect` is called with a primitive index and
+    /// the distance to beat, and should return the distance to the
+    /// primitive if it's hit any nearer than that.
+    fn closest(&self, ray: &Ray3<f32>, max: f32,
+               intersect: |uint, f32| -> Option<f32>) -> Option<(uint, f32)>;
+
+    /// Whether any primitive is hit nearer than `max`, for shadow rays.
+    /// Stops at the first hit found.
+    fn any(&self, ray: &Ray3<f32>, max: f32, intersect: |uint, f32| -> Option<f32>) -> bool;
+
+    /// Prints the shape of the structure, to help tune it.
+    fn print_build_stats(&self, name: &str, params: &BvhParams);
+}
+
+/// Which structure to build over the objects in a scene.
+#[deriving(Clone, Show, PartialEq)]
+pub enum AcceleratorType {
+    BvhAccelerator,
+    KdTreeAccelerator,
+    GridAccelerator
+}
+
+/// Which level a query was made at, so the work done finding objects in the
+/// scene and finding primitives inside objects can be tuned separately.
+#[deriving(Clone, Show, PartialEq)]
+pub enum QueryKind {
+    SceneQuery,
+    /// Queries over the parts of one object, like a mesh's triangles
+    ObjectQuery,
+    /// Queries for every primitive along a line, like CSG spans, which
+    /// can't stop early and would skew the others
+    AllHitsQuery
+}
+
+#[deriving(Clone, Default)]
+pub struct QueryCounts {
+    pub queries: uint,
+    pub nodes_visited: uint,
+    pub primitives_tested: uint
+}
+
+/// How much work ray queries did. Only counted when built with the
+/// `stats` feature, since counting costs time in the innermost loops.
+#[deriving(Clone, Default)]
+pub struct TraversalStats {
+    pub scene: QueryCounts,
+    pub object: QueryCounts,
+    pub all_hits: QueryCounts
+}
+
+impl QueryCounts {
+    fn add(&self, other: &QueryCounts) -> QueryCounts {
+        QueryCounts { queries: self.queries + other.queries,
+                      nodes_visited: self.nodes_visited + other.nodes_visited,
+                      primitives_tested: self.primitives_tested + other.primitives_tested }
+    }
+
+    fn print(&self, name: &str) {
+        let per_query = |count: uint| count as f64 / max(self.queries, 1) as f64;
+        println!("{} traversal: {} queries, {:.2} nodes visited and {:.2} primitives tested \
+                  per query",
+                 name, self.queries, per_query(self.nodes_visited),
+                 per_query(self.primitives_tested));
+    }
+}
+
+impl TraversalStats {
+    pub fn add(&self, other: &TraversalStats) -> TraversalStats {
+        TraversalStats { scene: self.scene.add(&other.scene),
+                         object: self.object.add(&other.object),
+                         all_hits: self.all_hits.add(&other.all_hits) }
+    }
+}
+
+pub fn build_accelerator(accelerator_type: AcceleratorType, bounds: &[BoundingBox],
+                         params: &BvhParams) -> Box<Accelerator+Send+Sync+'static> {
+    match accelerator_type {
+        BvhAccelerator => box Bvh::build(bounds, params).for_scene() as Box<Accelerator+Send+Sync>,
+        KdTreeAccelerator => box KdTree::build(bounds, params) as Box<Accelerator+Send+Sync>,
+        GridAccelerator => box Grid::build(bounds) as Box<Accelerator+Send+Sync>
+    }
+}
+
+pub fn inverse_direction(ray: &Ray3<f32>) -> Vector3<f32> {
+    Vector3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z)
+}
+
+/// Counts the work done by one query. Nodes are tree nodes for the BVH
+/// and kd-tree, and cells for the grid.
+#[inline]
+pub fn record_query(kind: QueryKind, nodes_visited: uint, primitives_tested: uint) {
+    if !cfg!(feature = "stats") {
+        return;
+    }
+    let mut stats = TASK_STATS.replace(None).unwrap_or(Default::default());
+    {
+        let counts = match kind {
+            SceneQuery => &mut stats.scene,
+            ObjectQuery => &mut stats.object,
+            AllHitsQuery => &mut stats.all_hits
+        };
+        counts.queries += 1;
+        counts.nodes_visited += nodes_visited;
+        counts.primitives_tested += primitives_tested;
+    }
+    TASK_STATS.replace(Some(stats));
+}
+
+/// The work counted on this task so far, which starts it again from zero.
+pub fn take_traversal_stats() -> TraversalStats {
+    TASK_STATS.replace(None).unwrap_or(Default::default())
+}
+
+/// Prints the work counted across every task, if it was counted at all.
+pub fn print_traversal_stats(stats: &TraversalStats) {
+    if !cfg!(feature = "stats") {
+        return;
+    }
+    stats.scene.print("Scene");
+    stats.object.print("Object");
+    stats.all_hits.print("All hits");
+}
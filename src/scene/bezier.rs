@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3};
+use scene::{TriangleMesh, BoundingBox, BvhParams};
+use scene::tessellation::{EdgeSplitter, split_triangle};
+
+/// Each patch starts out as just two triangles over its whole surface, so
+/// this many levels is already far finer than any sensible tolerance.
+const MAX_DEPTH: uint = 16;
+
+/// Bicubic Bézier patches, like the Utah teapot. Each patch is 16 control
+/// points, row by row, with u going along each row of 4 and v from one row
+/// to the next.
+pub struct BezierPatches {
+    patches: Vec<[Point3<f32>, ..16]>
+}
+
+/// One patch as it's being split into triangles, where whether an edge is
+/// split only depends on the surface along it. On the boundary of a patch
+/// that's the curve shared with the next patch, so neighbouring patches
+/// agree on where to split too.
+struct Tessellation {
+    patch: [Point3<f32>, ..16],
+    /// Squared size of the patch, which small derivatives are measured
+    /// against to find where it's degenerate
+    size2: f32,
+    tolerance2: f32,
+    uvs: Vec<(f32, f32)>,
+    positions: Vec<Point3<f32>>,
+    triangles: Vec<(uint, uint, uint)>,
+    midpoints: HashMap<(uint, uint), uint>
+}
+
+fn edge_key(a: uint, b: uint) -> (uint, uint) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// The weights of the control points of a cubic Bézier curve at `t`, and
+/// the weights for its derivative.
+fn bernstein(t: f32) -> ([f32, ..4], [f32, ..4]) {
+    let s = 1.0 - t;
+    ([s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t],
+     [-3.0 * s * s, 3.0 * s * s - 6.0 * s * t, 6.0 * s * t - 3.0 * t * t, 3.0 * t * t])
+}
+
+/// The point on a patch at (u, v), and how it moves along u and v.
+fn evaluate(patch: &[Point3<f32>, ..16], u: f32, v: f32)
+            -> (Point3<f32>, Vector3<f32>, Vector3<f32>) {
+    let (bu, du) = bernstein(u);
+    let (bv, dv) = bernstein(v);
+    let zero = Vector3::new(0.0f32, 0.0, 0.0);
+    let (mut point, mut dpdu, mut dpdv) = (zero, zero, zero);
+    for row in range(0u, 4) {
+        for column in range(0u, 4) {
+            let p = patch[4 * row + column].to_vec();
+            point = point.add_v(&p.mul_s(bu[column] * bv[row]));
+            dpdu = dpdu.add_v(&p.mul_s(du[column] * bv[row]));
+            dpdv = dpdv.add_v(&p.mul_s(bu[column] * dv[row]));
+        }
+    }
+    (Point3::from_vec(&point), dpdu, dpdv)
+}
+
+impl BezierPatches {
+    pub fn new(patches: Vec<[Point3<f32>, ..16]>) -> BezierPatches {
+        BezierPatches { patches: patches }
+    }
+
+    /// Splits each patch into triangles, more finely where it curves more,
+    /// until every edge is within `tolerance` of the surface. The mesh has
+    /// normals and texture coordinates from the patches.
+    pub fn tessellate(&self, tolerance: f32, bvh_params: &BvhParams) -> TriangleMesh {
+        if !(tolerance > 0.0) {
+            fail!("Bezier patch tolerance has to be more than 0, not {}", tolerance);
+        }
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut triangles = Vec::new();
+        for patch in self.patches.iter() {
+            let size2 = BoundingBox::around(patch.as_slice()).extent().length2();
+            let mut tessellation = Tessellation { patch: *patch,
+                                                  size2: size2,
+                                                  tolerance2: tolerance * tolerance,
+                                                  uvs: Vec::new(),
+                                                  positions: Vec::new(),
+                                                  triangles: Vec::new(),
+                                                  midpoints: HashMap::new() };
+            let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+            for &(u, v) in corners.iter() {
+                tessellation.vertex(u, v);
+            }
+            split_triangle(&mut tessellation, 0, 1, 2, MAX_DEPTH);
+            split_triangle(&mut tessellation, 0, 2, 3, MAX_DEPTH);
+
+            let offset = positions.len();
+            for &(u, v) in tessellation.uvs.iter() {
+                normals.push(tessellation.normal(u, v));
+            }
+            triangles.extend(tessellation.triangles.iter().map(|&(a, b, c)| {
+                (a + offset, b + offset, c + offset)
+            }));
+            positions.extend(tessellation.positions.into_iter());
+            uvs.extend(tessellation.uvs.into_iter());
+        }
+        TriangleMesh::new(positions, triangles, bvh_params)
+            .with_normals(normals)
+            .with_uvs(uvs)
+    }
+}
+
+impl Tessellation {
+    fn vertex(&mut self, u: f32, v: f32) -> uint {
+        let (point, _, _) = evaluate(&self.patch, u, v);
+        self.uvs.push((u, v));
+        self.positions.push(point);
+        self.positions.len() - 1
+    }
+
+    /// The surface normal at (u, v). Where a row of control points meets
+    /// at one point, like the top of the teapot's lid, one derivative
+    /// vanishes, so the normal comes from just inside the patch instead.
+    fn normal(&self, u: f32, v: f32) -> Vector3<f32> {
+        let degenerate = |d: &Vector3<f32>| d.length2() <= self.size2 * 1e-10;
+        let (_, dpdu, dpdv) = evaluate(&self.patch, u, v);
+        let (dpdu, dpdv) = if degenerate(&dpdu) || degenerate(&dpdv) {
+            let (_, dpdu, dpdv) = evaluate(&self.patch, u + (0.5 - u) * 0.001,
+                                           v + (0.5 - v) * 0.001);
+            (dpdu, dpdv)
+        } else {
+            (dpdu, dpdv)
+        };
+        let normal = dpdu.cross(&dpdv);
+        if normal.length2() > 0.0 { normal.normalize() } else { Vector3::unit_z() }
+    }
+}
+
+impl EdgeSplitter for Tessellation {
+    /// Whether the surface strays more than the tolerance from the edge
+    /// between two vertices. It's checked at a few points, so curves that
+    /// cross back over the edge halfway along are still caught.
+    fn needs_split(&self, a: uint, b: uint) -> bool {
+        let ((ua, va), (ub, vb)) = (self.uvs[a], self.uvs[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        [0.25f32, 0.5, 0.75].iter().any(|&t| {
+            let (point, _, _) = evaluate(&self.patch, ua + (ub - ua) * t, va + (vb - va) * t);
+            let chord = pa.add_v(&pb.sub_p(&pa).mul_s(t));
+            point.sub_p(&chord).length2() > self.tolerance2
+        })
+    }
+
+    /// The vertex halfway along an edge, made the first time it's asked for.
+    fn midpoint(&mut self, a: uint, b: uint) -> uint {
+        match self.midpoints.find(&edge_key(a, b)) {
+            Some(&vertex) => return vertex,
+            None => ()
+        }
+        let ((ua, va), (ub, vb)) = (self.uvs[a], self.uvs[b]);
+        let vertex = self.vertex((ua + ub) * 0.5, (va + vb) * 0.5);
+        self.midpoints.insert(edge_key(a, b), vertex);
+        vertex
+    }
+
+    fn add_triangle(&mut self, a: uint, b: uint, c: uint) {
+        self.triangles.push((a, b, c));
+    }
+}
@@ -0,0 +1,135 @@
+use std::f32::INFINITY;
+use cgmath::{Point, Matrix};
+use cgmath::{Point3, Vector3, Matrix4, Vector4};
+use scene::util::component;
+
+/// An axis aligned bounding box. Geometry that goes on forever, like a
+/// plane, has an infinite box.
+#[deriving(Clone)]
+pub struct BoundingBox {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>
+}
+
+impl BoundingBox {
+    pub fn new(a: &Point3<f32>, b: &Point3<f32>) -> BoundingBox {
+        BoundingBox { min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+                      max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)) }
+    }
+
+    /// A box around nothing, which is the identity for `union`.
+    pub fn empty() -> BoundingBox {
+        BoundingBox { min: Point3::new(INFINITY, INFINITY, INFINITY),
+                      max: Point3::new(-INFINITY, -INFINITY, -INFINITY) }
+    }
+
+    pub fn infinite() -> BoundingBox {
+        BoundingBox { min: Point3::new(-INFINITY, -INFINITY, -INFINITY),
+                      max: Point3::new(INFINITY, INFINITY, INFINITY) }
+    }
+
+    pub fn around(points: &[Point3<f32>]) -> BoundingBox {
+        points.iter().fold(BoundingBox::empty(), |bounds, point| bounds.include(point))
+    }
+
+    pub fn is_finite(&self) -> bool {
+        let extent = self.max.sub_p(&self.min);
+        extent.x.is_finite() && extent.y.is_finite() && extent.z.is_finite()
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox { min: Point3::new(self.min.x.min(other.min.x),
+                                       self.min.y.min(other.min.y),
+                                       self.min.z.min(other.min.z)),
+                      max: Point3::new(self.max.x.max(other.max.x),
+                                       self.max.y.max(other.max.y),
+                                       self.max.z.max(other.max.z)) }
+    }
+
+    /// The box where both boxes overlap.
+    pub fn intersection(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox { min: Point3::new(self.min.x.max(other.min.x),
+                                       self.min.y.max(other.min.y),
+                                       self.min.z.max(other.min.z)),
+                      max: Point3::new(self.max.x.min(other.max.x),
+                                       self.max.y.min(other.max.y),
+                                       self.max.z.min(other.max.z)) }
+    }
+
+    pub fn include(&self, point: &Point3<f32>) -> BoundingBox {
+        self.union(&BoundingBox { min: *point, max: *point })
+    }
+
+    /// Grows the box by `amount` on every side.
+    pub fn expand(&self, amount: f32) -> BoundingBox {
+        BoundingBox { min: Point3::new(self.min.x - amount, self.min.y - amount, self.min.z - amount),
+                      max: Point3::new(self.max.x + amount, self.max.y + amount, self.max.z + amount) }
+    }
+
+    pub fn centroid(&self) -> Point3<f32> {
+        Point3::new((self.min.x + self.max.x) * 0.5,
+                    (self.min.y + self.max.y) * 0.5,
+                    (self.min.z + self.max.z) * 0.5)
+    }
+
+    pub fn extent(&self) -> Vector3<f32> {
+        self.max.sub_p(&self.min)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    /// The axis the box is longest along.
+    pub fn largest_axis(&self) -> uint {
+        let extent = self.extent();
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The box around this box after it's been transformed by `matrix`.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingBox {
+        if !self.is_finite() {
+            return BoundingBox::infinite();
+        }
+        let mut bounds = BoundingBox::empty();
+        for i in range(0u, 8) {
+            let x = if i & 1 == 0 { self.min.x } else { self.max.x };
+            let y = if i & 2 == 0 { self.min.y } else { self.max.y };
+            let z = if i & 4 == 0 { self.min.z } else { self.max.z };
+            let v = matrix.mul_v(&Vector4::new(x, y, z, 1.0));
+            bounds = bounds.include(&Point3::new(v.x, v.y, v.z));
+        }
+        bounds
+    }
+
+    /// The span of distances along a ray inside the box, clipped to
+    /// `[0, max]`. Takes the reciprocal of the ray's direction, which is
+    /// worth computing once per ray when testing many boxes.
+    pub fn hit(&self, origin: &Point3<f32>, inverse_direction: &Vector3<f32>,
+               max: f32) -> Option<(f32, f32)> {
+        let mut enter = 0.0f32;
+        let mut exit = max;
+        for axis in range(0, 3) {
+            let o = component(&origin.to_vec(), axis);
+            let inverse = component(inverse_direction, axis);
+            let t0 = (component(&self.min.to_vec(), axis) - o) * inverse;
+            let t1 = (component(&self.max.to_vec(), axis) - o) * inverse;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            // max and min ignore the NaN produced by 0 * infinity, which
+            // happens when the ray runs exactly along a face of the box.
+            enter = enter.max(near);
+            exit = exit.min(far);
+            if enter > exit {
+                return None;
+            }
+        }
+        Some((enter, exit))
+    }
+}
@@ -0,0 +1,365 @@
+use std::cmp::max;
+use std::default::Default;
+use std::f32::INFINITY;
+use time::precise_time_ns;
+use cgmath::Point;
+use cgmath::{Point3, Ray3};
+use scene::{Accelerator, BoundingBox};
+use scene::accelerator::{inverse_direction, record_query, QueryKind};
+use scene::accelerator::{SceneQuery, ObjectQuery, AllHitsQuery};
+use scene::util::component;
+
+/// Traversal keeps the nodes still to visit in a fixed array rather than a
+/// heap allocated stack, and it never holds more than one node per level
+/// plus one, so trees are kept shallower than this.
+const STACK_SIZE: uint = 64;
+const MAX_DEPTH: uint = STACK_SIZE - 1;
+
+/// Tuning for the surface area heuristic. The costs are relative, only
+/// their ratio matters: a split is kept when traversing it plus
+/// intersecting the children, weighted by the chance of a ray hitting
+/// them, is cheaper than intersecting everything in the node.
+#[deriving(Clone, Show)]
+pub struct BvhParams {
+    pub traversal_cost: f32,
+    pub intersection_cost: f32,
+    pub max_leaf_size: uint,
+    pub bins: uint
+}
+
+impl Default for BvhParams {
+    fn default() -> BvhParams {
+        BvhParams { traversal_cost: 0.125,
+                    intersection_cost: 1.0,
+                    max_leaf_size: 4,
+                    bins: 16 }
+    }
+}
+
+/// A bounding volume hierarchy over anything with a bounding box. It only
+/// stores indices, so the same code serves the objects in a scene and the
+/// triangles in a mesh; callers pass in a closure to intersect the
+/// primitive with a given index.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<uint>,
+    // Primitives with infinite bounds can't go in the tree and are
+    // tested against every ray instead.
+    unbounded: Vec<uint>,
+    max_depth: uint,
+    build_time: u64,
+    query_kind: QueryKind
+}
+
+/// Nodes are stored depth first, so an interior node's first child is
+/// right after it and `offset` is the index of the second child. For
+/// leaves, `offset` is where their `count` primitives start in `indices`.
+struct BvhNode {
+    bounds: BoundingBox,
+    offset: uint,
+    count: uint,
+    axis: uint
+}
+
+#[deriving(Clone)]
+struct Bin {
+    bounds: BoundingBox,
+    count: uint
+}
+
+impl Bvh {
+    pub fn empty() -> Bvh {
+        Bvh { nodes: Vec::new(),
+              indices: Vec::new(),
+              unbounded: Vec::new(),
+              max_depth: 0,
+              build_time: 0,
+              query_kind: ObjectQuery }
+    }
+
+    /// Counts queries on this BVH as scene level ones in the traversal stats.
+    pub fn for_scene(mut self) -> Bvh {
+        self.query_kind = SceneQuery;
+        self
+    }
+
+    /// Calls `visit` once with each primitive whose bounds the ray passes
+    /// through nearer than `max`, in no particular order, for queries that
+    /// need every crossing along a line rather than the first.
+    pub fn all(&self, ray: &Ray3<f32>, max: f32, visit: |uint|) {
+        for &primitive in self.unbounded.iter() {
+            visit(primitive);
+        }
+        let mut nodes_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+        if self.nodes.is_empty() {
+            record_query(AllHitsQuery, nodes_visited, primitives_tested);
+            return;
+        }
+
+        let inverse = inverse_direction(ray);
+        let mut stack = [0u, ..STACK_SIZE];
+        let mut stack_size = 1u;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            nodes_visited += 1;
+            if node.bounds.hit(&ray.origin, &inverse, max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                primitives_tested += node.count;
+                for &primitive in self.indices.slice(node.offset, node.offset + node.count).iter() {
+                    visit(primitive);
+                }
+            } else {
+                stack[stack_size] = node.offset;
+                stack[stack_size + 1] = index + 1;
+                stack_size += 2;
+            }
+        }
+        record_query(AllHitsQuery, nodes_visited, primitives_tested);
+    }
+
+    pub fn build(bounds: &[BoundingBox], params: &BvhParams) -> Bvh {
+        let start = precise_time_ns();
+        let mut bvh = Bvh::empty();
+        let mut items = Vec::with_capacity(bounds.len());
+        for (index, bound) in bounds.iter().enumerate() {
+            if bound.is_finite() {
+                items.push(index);
+            } else {
+                bvh.unbounded.push(index);
+            }
+        }
+        let centroids: Vec<Point3<f32>> = bounds.iter().map(|b| b.centroid()).collect();
+        if !items.is_empty() {
+            bvh.build_node(bounds, centroids.as_slice(), items, params, 1);
+        }
+        bvh.build_time = precise_time_ns() - start;
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[BoundingBox], centroids: &[Point3<f32>],
+                  items: Vec<uint>, params: &BvhParams, depth: uint) -> uint {
+        let node_bounds = items.iter().fold(BoundingBox::empty(), |b, &i| b.union(&bounds[i]));
+        let centroid_bounds = items.iter().fold(BoundingBox::empty(), |b, &i| b.include(&centroids[i]));
+        let axis = centroid_bounds.largest_axis();
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: node_bounds.clone(),
+                                  offset: self.indices.len(),
+                                  count: items.len(),
+                                  axis: axis });
+        self.max_depth = max(self.max_depth, depth);
+
+        let min = component(&centroid_bounds.min.to_vec(), axis);
+        let extent = component(&centroid_bounds.extent(), axis);
+        if items.len() == 1 || extent <= 0.0 || depth >= MAX_DEPTH {
+            self.indices.push_all(items.as_slice());
+            return index;
+        }
+
+        // Bin the primitives by centroid and evaluate the surface area
+        // heuristic for splitting between each pair of bins.
+        let num_bins = max(params.bins, 2);
+        let mut bins = Vec::from_elem(num_bins, Bin { bounds: BoundingBox::empty(), count: 0 });
+        for &i in items.iter() {
+            let b = bin_index(&centroids[i], axis, min, extent, num_bins);
+            let merged = bins[b].bounds.union(&bounds[i]);
+            bins[b].bounds = merged;
+            bins[b].count += 1;
+        }
+
+        let mut best_cost = INFINITY;
+        let mut best_split = 0;
+        for split in range(1, num_bins) {
+            let (left_bounds, left_count) = merge_bins(bins.slice_to(split));
+            let (right_bounds, right_count) = merge_bins(bins.slice_from(split));
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = params.traversal_cost + params.intersection_cost *
+                (left_count as f32 * left_bounds.surface_area() +
+                 right_count as f32 * right_bounds.surface_area()) / node_bounds.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        let leaf_cost = params.intersection_cost * items.len() as f32;
+        if items.len() <= params.max_leaf_size && leaf_cost <= best_cost {
+            self.indices.push_all(items.as_slice());
+            return index;
+        }
+
+        let (left, right) = if best_split > 0 {
+            items.partition(|&i| bin_index(&centroids[i], axis, min, extent, num_bins) < best_split)
+        } else {
+            // Every centroid landed in one bin, fall back to a median split
+            let mut items = items;
+            items.sort_by(|&a, &b| {
+                let a = component(&centroids[a].to_vec(), axis);
+                let b = component(&centroids[b].to_vec(), axis);
+                a.partial_cmp(&b).unwrap()
+            });
+            let middle = items.len() / 2;
+            let right = items.slice_from(middle).to_vec();
+            items.truncate(middle);
+            (items, right)
+        };
+
+        self.build_node(bounds, centroids, left, params, depth + 1);
+        let second = self.build_node(bounds, centroids, right, params, depth + 1);
+        self.nodes[index].offset = second;
+        self.nodes[index].count = 0;
+        index
+    }
+}
+
+impl Accelerator for Bvh {
+    fn closest(&self, ray: &Ray3<f32>, max: f32,
+               intersect: |uint, f32| -> Option<f32>) -> Option<(uint, f32)> {
+        let mut closest = None;
+        let mut max = max;
+        let mut nodes_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance < max => {
+                    max = distance;
+                    closest = Some((primitive, distance));
+                },
+                _ => ()
+            }
+        }
+        if self.nodes.is_empty() {
+            record_query(self.query_kind, nodes_visited, primitives_tested);
+            return closest;
+        }
+
+        let inverse = inverse_direction(ray);
+        let mut stack = [0u, ..STACK_SIZE];
+        let mut stack_size = 1u;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            nodes_visited += 1;
+            if node.bounds.hit(&ray.origin, &inverse, max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                primitives_tested += node.count;
+                for &primitive in self.indices.slice(node.offset, node.offset + node.count).iter() {
+                    match intersect(primitive, max) {
+                        Some(distance) if distance < max => {
+                            max = distance;
+                            closest = Some((primitive, distance));
+                        },
+                        _ => ()
+                    }
+                }
+            } else if component(&ray.direction, node.axis) < 0.0 {
+                // Visit the nearer child first, so `max` shrinks sooner
+                stack[stack_size] = index + 1;
+                stack[stack_size + 1] = node.offset;
+                stack_size += 2;
+            } else {
+                stack[stack_size] = node.offset;
+                stack[stack_size + 1] = index + 1;
+                stack_size += 2;
+            }
+        }
+        record_query(self.query_kind, nodes_visited, primitives_tested);
+        closest
+    }
+
+    fn any(&self, ray: &Ray3<f32>, max: f32, intersect: |uint, f32| -> Option<f32>) -> bool {
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance <= max => {
+                    record_query(self.query_kind, 0, self.unbounded.len());
+                    return true;
+                },
+                _ => ()
+            }
+        }
+        if self.nodes.is_empty() {
+            record_query(self.query_kind, 0, self.unbounded.len());
+            return false;
+        }
+        let mut nodes_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+
+        let inverse = inverse_direction(ray);
+        let mut stack = [0u, ..STACK_SIZE];
+        let mut stack_size = 1u;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            nodes_visited += 1;
+            if node.bounds.hit(&ray.origin, &inverse, max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                primitives_tested += node.count;
+                for &primitive in self.indices.slice(node.offset, node.offset + node.count).iter() {
+                    match intersect(primitive, max) {
+                        Some(distance) if distance <= max => {
+                            record_query(self.query_kind, nodes_visited, primitives_tested);
+                            return true;
+                        },
+                        _ => ()
+                    }
+                }
+            } else {
+                stack[stack_size] = node.offset;
+                stack[stack_size + 1] = index + 1;
+                stack_size += 2;
+            }
+        }
+        record_query(self.query_kind, nodes_visited, primitives_tested);
+        false
+    }
+
+    fn print_build_stats(&self, name: &str, params: &BvhParams) {
+        let leaves = self.nodes.iter().filter(|node| node.count > 0).count();
+        let primitives = self.indices.len();
+        // The expected cost of tracing a ray through the whole tree, as
+        // estimated by the same heuristic used to build it.
+        let sah_cost = match self.nodes.as_slice().get(0) {
+            Some(root) => {
+                let root_area = root.bounds.surface_area();
+                self.nodes.iter().fold(0.0, |cost, node| {
+                    let weight = node.bounds.surface_area() / root_area;
+                    if node.count > 0 {
+                        cost + weight * params.intersection_cost * node.count as f32
+                    } else {
+                        cost + weight * params.traversal_cost
+                    }
+                })
+            },
+            None => 0.0
+        };
+        println!("{} BVH: {} primitives ({} unbounded), {} nodes, {} leaves, \
+                  {:.2} primitives per leaf, depth {}, SAH cost {:.2}, built in {:.2} ms",
+                 name, primitives, self.unbounded.len(), self.nodes.len(), leaves,
+                 primitives as f32 / max(leaves, 1) as f32, self.max_depth, sah_cost,
+                 self.build_time as f64 / 1e6);
+    }
+}
+
+fn merge_bins(bins: &[Bin]) -> (BoundingBox, uint) {
+    bins.iter().fold((BoundingBox::empty(), 0), |(bounds, count), bin| {
+        (bounds.union(&bin.bounds), count + bin.count)
+    })
+}
+
+fn bin_index(centroid: &Point3<f32>, axis: uint, min: f32, extent: f32, num_bins: uint) -> uint {
+    let offset = (component(&centroid.to_vec(), axis) - min) / extent;
+    let bin = (offset * num_bins as f32) as uint;
+    if bin < num_bins { bin } else { num_bins - 1 }
+}
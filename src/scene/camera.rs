@@ -0,0 +1,48 @@
+use std::default::Default;
+use std::f32::consts::PI;
+use cgmath::{EuclideanVector, Vector};
+use cgmath::{Vector3, Point3, Ray3, Ray};
+
+/// A pinhole camera. Screen coordinates go from -1 at the bottom of the
+/// image to 1 at the top, and as far either side as the aspect ratio takes
+/// them.
+pub struct Camera {
+    pub position: Point3<f32>,
+    forward: Vector3<f32>,
+    right: Vector3<f32>,
+    up: Vector3<f32>,
+    /// Tangent of half the vertical field of view
+    scale: f32
+}
+
+impl Camera {
+    /// A camera looking along `forward`, turned so `up` is towards the top
+    /// of the image, with a vertical field of view in degrees.
+    pub fn new(position: Point3<f32>, forward: Vector3<f32>, up: Vector3<f32>,
+               fov: f32) -> Camera {
+        let forward = forward.normalize();
+        let right = forward.cross(&up);
+        if right.length2() == 0.0 {
+            fail!("Camera up vector is along its view direction");
+        }
+        let right = right.normalize();
+        Camera { position: position,
+                 forward: forward,
+                 right: right,
+                 up: right.cross(&forward),
+                 scale: (fov * PI / 360.0).tan() }
+    }
+
+    pub fn ray(&self, x: f32, y: f32) -> Ray3<f32> {
+        let direction = self.forward.add_v(&self.right.mul_s(x * self.scale))
+                                    .add_v(&self.up.mul_s(y * self.scale));
+        Ray::new(self.position, direction.normalize())
+    }
+}
+
+impl Default for Camera {
+    /// Looking along +y from just behind the origin, with z up.
+    fn default() -> Camera {
+        Camera::new(Point3::new(0.0, -2.0, 0.0), Vector3::unit_y(), Vector3::unit_z(), 90.0)
+    }
+}
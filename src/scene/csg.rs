@@ -0,0 +1,186 @@
+use std::f32::INFINITY;
+use cgmath::{EuclideanVector, Point};
+use cgmath::{Point3, Vector3, Ray3, Ray};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox};
+use scene::util::{orthonormal_basis, gamma, abs_vector};
+
+/// How a CSG node combines the volumes of its children.
+#[deriving(Clone, Show, PartialEq)]
+pub enum CsgOperation {
+    CsgUnion,
+    CsgIntersection,
+    /// The first child with all of the others taken away
+    CsgDifference
+}
+
+/// Constructive solid geometry: a solid made by combining the volumes of
+/// other solids, found by merging the spans each of them covers along a
+/// ray. Children can be any objects with spans, including other CSG nodes.
+pub struct Csg {
+    operation: CsgOperation,
+    children: Vec<Box<Intersectable+Send+Sync+'static>>,
+    bounds: BoundingBox
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, children: Vec<Box<Intersectable+Send+Sync+'static>>) -> Csg {
+        if children.is_empty() {
+            fail!("CSG operation doesn't have any objects");
+        }
+        // Surfaces don't have spans for any ray, so any ray will do to find them
+        let probe = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x());
+        if children.iter().any(|child| child.spans(&probe).is_none()) {
+            fail!("CSG operations only work on solids, not surfaces like disks, \
+                   rectangles and triangles");
+        }
+        let bounds = match operation {
+            CsgUnion => children.iter().fold(BoundingBox::empty(), |b, child| b.union(&child.bounds())),
+            CsgIntersection => children.iter().skip(1).fold(children[0].bounds(), |b, child| {
+                b.intersection(&child.bounds())
+            }),
+            CsgDifference => children[0].bounds()
+        };
+        Csg { operation: operation,
+              children: children,
+              bounds: bounds }
+    }
+
+    fn child_spans(&self, index: uint, ray: &Ray3<f32>) -> Vec<(f32, f32)> {
+        self.children[index].spans(ray)
+                            .expect("CSG object doesn't have spans")
+    }
+
+    fn combined_spans(&self, ray: &Ray3<f32>) -> Vec<(f32, f32)> {
+        let mut spans = self.child_spans(0, ray);
+        for index in range(1, self.children.len()) {
+            // Nothing is left to intersect with or take away from
+            if spans.is_empty() && self.operation != CsgUnion {
+                break;
+            }
+            let other = self.child_spans(index, ray);
+            spans = match self.operation {
+                CsgUnion => combine(spans.as_slice(), other.as_slice(), |a, b| a || b),
+                CsgIntersection => combine(spans.as_slice(), other.as_slice(), |a, b| a && b),
+                CsgDifference => combine(spans.as_slice(), other.as_slice(), |a, b| a && !b)
+            };
+        }
+        spans
+    }
+}
+
+/// Merges two sets of spans into the parts of the line where `keep` says
+/// being inside the first and/or the second set is inside the result.
+fn combine(a: &[(f32, f32)], b: &[(f32, f32)], keep: |bool, bool| -> bool) -> Vec<(f32, f32)> {
+    // Every span boundary toggles whether the line is inside its set
+    let mut boundaries = Vec::with_capacity(2 * (a.len() + b.len()));
+    for &(enter, exit) in a.iter() {
+        boundaries.push((enter, true));
+        boundaries.push((exit, true));
+    }
+    for &(enter, exit) in b.iter() {
+        boundaries.push((enter, false));
+        boundaries.push((exit, false));
+    }
+    boundaries.sort_by(|&(s, _), &(t, _)| s.partial_cmp(&t).unwrap());
+
+    let mut spans = Vec::new();
+    let (mut in_a, mut in_b, mut inside) = (false, false, false);
+    let mut start = 0.0;
+    let mut i = 0;
+    while i < boundaries.len() {
+        let (t, _) = boundaries[i];
+        // Take every boundary at the same distance before checking, so
+        // solids that just touch don't leave zero width slivers.
+        while i < boundaries.len() {
+            let (s, in_first) = boundaries[i];
+            if s != t {
+                break;
+            }
+            if in_first { in_a = !in_a } else { in_b = !in_b }
+            i += 1;
+        }
+        let now_inside = keep(in_a, in_b);
+        if now_inside && !inside {
+            start = t;
+        } else if !now_inside && inside {
+            spans.push((start, t));
+        }
+        inside = now_inside;
+    }
+    spans
+}
+
+impl Intersectable for Csg {
+    /// The hit takes its surface coordinates from the child whose surface
+    /// it's on, and its primitive is that child's index.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let mut boundary = None;
+        for &(enter, exit) in self.combined_spans(ray).iter() {
+            if enter >= tmin {
+                boundary = Some((enter, true));
+                break;
+            }
+            if exit >= tmin && exit < INFINITY {
+                boundary = Some((exit, false));
+                break;
+            }
+        }
+        let (distance, entering) = match boundary {
+            Some((distance, entering)) if distance <= tmax => (distance, entering),
+            _ => return None
+        };
+
+        // The surface here belongs to whichever child has a boundary here
+        let mut closest = INFINITY;
+        let mut surface = 0;
+        for index in range(0, self.children.len()) {
+            for &(enter, exit) in self.child_spans(index, ray).iter() {
+                let error = (enter - distance).abs().min((exit - distance).abs());
+                if error < closest {
+                    closest = error;
+                    surface = index;
+                }
+            }
+        }
+
+        // Ask the child for its hit at the boundary. The child finds its
+        // spans and its hits separately, so they can differ by rounding, and
+        // its first hit from a little before the boundary is taken as long
+        // as the boundary is within that hit's own error bounds. Its normal
+        // points out of the child, so point it out of the combined solid
+        // instead: against the ray where it enters and along it where it
+        // leaves.
+        let origin = abs_vector(&ray.origin.to_vec());
+        let rounding = gamma(16.0) * (distance.abs() + origin.x.max(origin.y).max(origin.z));
+        let child_hit = self.children[surface].intersection(ray, distance - rounding, INFINITY);
+        let hit = match child_hit {
+            Some(hit) if (hit.distance - distance).abs() <=
+                         rounding + hit.error.length() / ray.direction.length() => hit,
+            _ => {
+                let (tangent, bitangent) = orthonormal_basis(&ray.direction);
+                Hit::new(ray, distance, -ray.direction, (0.0, 0.0), tangent, bitangent)
+            }
+        };
+        let outward = (dot(hit.geometric_normal, ray.direction) < 0.0) == entering;
+        let (geometric, shading) = if outward {
+            (hit.geometric_normal, hit.shading_normal)
+        } else {
+            (-hit.geometric_normal, -hit.shading_normal)
+        };
+        Some(Hit { distance: distance,
+                   geometric_normal: geometric,
+                   shading_normal: shading,
+                   front_face: entering,
+                   primitive: surface,
+                   ..hit })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        Some(self.combined_spans(ray))
+    }
+}
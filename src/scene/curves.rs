@@ -0,0 +1,276 @@
+use std::f32::INFINITY;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox, Accelerator, Bvh, BvhParams};
+
+/// How a curve looks across its width.
+#[deriving(Clone, Show, PartialEq)]
+pub enum CurveShape {
+    /// A flat strip that always faces the ray, cheap and fine for thin hair
+    /// and fur seen from a distance.
+    CurveRibbon,
+    /// A round tube, for thicker strands like grass seen up close
+    CurveTube
+}
+
+/// A cubic Bézier piece of a strand, with the strand's width where it
+/// starts and ends.
+struct Segment {
+    points: [Point3<f32>, ..4],
+    widths: (f32, f32)
+}
+
+/// Strands of connected cubic Bézier curves whose width changes linearly
+/// from root to tip, like hair, fur or grass. The segments of every strand
+/// go in one BVH, and are hit by splitting them in the ray's own space
+/// until they're nearly straight.
+/// See Nakamaru and Ohno, "Ray Tracing For Curves Primitive" (2002)
+pub struct Curves {
+    shape: CurveShape,
+    segments: Vec<Segment>,
+    bvh: Bvh,
+    bounds: BoundingBox
+}
+
+struct CurveHit {
+    distance: f32,
+    segment: uint,
+    /// How far along the segment the hit is
+    u: f32
+}
+
+/// The point at `u` along a cubic Bézier curve.
+fn evaluate(points: &[Vector3<f32>, ..4], u: f32) -> Vector3<f32> {
+    let lerp = |p: &Vector3<f32>, q: &Vector3<f32>| p.mul_s(1.0 - u).add_v(&q.mul_s(u));
+    let (p01, p12, p23) = (lerp(&points[0], &points[1]), lerp(&points[1], &points[2]),
+                           lerp(&points[2], &points[3]));
+    let (p012, p123) = (lerp(&p01, &p12), lerp(&p12, &p23));
+    lerp(&p012, &p123)
+}
+
+/// The tangent at `u` along a cubic Bézier curve, not normalized.
+fn tangent(points: &[Vector3<f32>, ..4], u: f32) -> Vector3<f32> {
+    let lerp = |p: &Vector3<f32>, q: &Vector3<f32>| p.mul_s(1.0 - u).add_v(&q.mul_s(u));
+    let (d0, d1, d2) = (points[1].sub_v(&points[0]), points[2].sub_v(&points[1]),
+                        points[3].sub_v(&points[2]));
+    lerp(&lerp(&d0, &d1), &lerp(&d1, &d2)).mul_s(3.0)
+}
+
+/// Splits a cubic Bézier curve in half.
+fn split(points: &[Vector3<f32>, ..4]) -> ([Vector3<f32>, ..4], [Vector3<f32>, ..4]) {
+    let mid = |p: &Vector3<f32>, q: &Vector3<f32>| p.add_v(q).mul_s(0.5);
+    let (p01, p12, p23) = (mid(&points[0], &points[1]), mid(&points[1], &points[2]),
+                           mid(&points[2], &points[3]));
+    let (p012, p123) = (mid(&p01, &p12), mid(&p12, &p23));
+    let center = mid(&p012, &p123);
+    ([points[0], p01, p012, center], [center, p123, p23, points[3]])
+}
+
+impl Segment {
+    fn bounds(&self) -> BoundingBox {
+        let (w0, w1) = self.widths;
+        BoundingBox::around(self.points.as_slice()).expand(w0.max(w1) * 0.5)
+    }
+
+    fn width(&self, u: f32) -> f32 {
+        let (w0, w1) = self.widths;
+        w0 * (1.0 - u) + w1 * u
+    }
+
+    fn vectors(&self) -> [Vector3<f32>, ..4] {
+        [self.points[0].to_vec(), self.points[1].to_vec(),
+         self.points[2].to_vec(), self.points[3].to_vec()]
+    }
+
+    /// The nearest hit from `min` to `max` along the ray, as a distance and
+    /// how far along the segment it is.
+    fn intersect(&self, ray: &Ray3<f32>, min: f32, max: f32) -> Option<(f32, f32)> {
+        // Move the curve to where the ray starts at the origin and goes
+        // along +z, so it only needs testing in 2D.
+        let z = ray.direction;
+        let helper = if z.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let x = z.cross(&helper).normalize();
+        let y = z.cross(&x);
+        let mut points = [Vector3::new(0.0f32, 0.0, 0.0), ..4];
+        for i in range(0u, 4) {
+            let p = self.points[i].sub_p(&ray.origin);
+            points[i] = Vector3::new(dot(p, x), dot(p, y), dot(p, z));
+        }
+
+        // Split until each piece is within a small part of the width of a
+        // straight line, going by how far the curve bends.
+        let (w0, w1) = self.widths;
+        let bend = range(0u, 2).fold(0.0f32, |bend, i| {
+            let b = points[i].sub_v(&points[i + 1].mul_s(2.0)).add_v(&points[i + 2]);
+            bend.max(b.x.abs().max(b.y.abs()).max(b.z.abs()))
+        });
+        let epsilon = w0.max(w1) * 0.05;
+        let depth = if bend > 0.0 && epsilon > 0.0 {
+            let depth = (1.41421356 * 6.0 * bend / (8.0 * epsilon)).log2() * 0.5;
+            depth.max(0.0).min(10.0).ceil() as uint
+        } else {
+            0
+        };
+        self.intersect_piece(&points, 0.0, 1.0, depth, min, max)
+    }
+
+    fn intersect_piece(&self, points: &[Vector3<f32>, ..4], u0: f32, u1: f32, depth: uint,
+                       min: f32, max: f32) -> Option<(f32, f32)> {
+        // Skip pieces whose bounds don't contain the ray
+        let half_width = self.width(u0).max(self.width(u1)) * 0.5;
+        let low = points.iter().fold(Vector3::new(INFINITY, INFINITY, INFINITY), |low, p| {
+            Vector3::new(low.x.min(p.x), low.y.min(p.y), low.z.min(p.z))
+        });
+        let high = points.iter().fold(Vector3::new(-INFINITY, -INFINITY, -INFINITY), |high, p| {
+            Vector3::new(high.x.max(p.x), high.y.max(p.y), high.z.max(p.z))
+        });
+        if low.x - half_width > 0.0 || high.x + half_width < 0.0 ||
+           low.y - half_width > 0.0 || high.y + half_width < 0.0 ||
+           low.z - half_width > max || high.z + half_width < min {
+            return None;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(points);
+            let middle = (u0 + u1) * 0.5;
+            let near = self.intersect_piece(&first, u0, middle, depth - 1, min, max);
+            let max = match near {
+                Some((distance, _)) => distance,
+                None => max
+            };
+            return match self.intersect_piece(&second, middle, u1, depth - 1, min, max) {
+                Some(hit) => Some(hit),
+                None => near
+            };
+        }
+
+        // The ray has to be past the perpendiculars at either end of the
+        // piece, so neighbouring pieces don't both get hit.
+        let start = points[1].sub_v(&points[0]);
+        if start.x * -points[0].x + start.y * -points[0].y < 0.0 {
+            return None;
+        }
+        let end = points[3].sub_v(&points[2]);
+        if end.x * -points[3].x + end.y * -points[3].y > 0.0 {
+            return None;
+        }
+
+        // Treat the piece as the line between its ends
+        let along = points[3].sub_v(&points[0]);
+        let length2 = along.x * along.x + along.y * along.y;
+        if length2 == 0.0 {
+            return None;
+        }
+        let w = ((-points[0].x * along.x - points[0].y * along.y) / length2).max(0.0).min(1.0);
+        let u = u0 + (u1 - u0) * w;
+        let point = evaluate(points, w);
+        let half_width = self.width(u) * 0.5;
+        let distance2 = point.x * point.x + point.y * point.y;
+        if distance2 > half_width * half_width || point.z < min || point.z > max {
+            return None;
+        }
+        Some((point.z, u))
+    }
+}
+
+impl Curves {
+    /// Each strand is 3n + 1 control points for n Bézier curves joined end
+    /// to end, along with its width at the root and at the tip.
+    pub fn new(shape: CurveShape, strands: Vec<(Vec<Point3<f32>>, f32, f32)>,
+               params: &BvhParams) -> Curves {
+        let mut segments = Vec::new();
+        for (points, root, tip) in strands.into_iter() {
+            if points.len() < 4 || (points.len() - 1) % 3 != 0 {
+                fail!("Curve strand has {} control points, it needs 3n + 1", points.len());
+            }
+            if root < 0.0 || tip < 0.0 {
+                fail!("Curve strand widths can't be negative");
+            }
+            let count = (points.len() - 1) / 3;
+            for i in range(0, count) {
+                let (u0, u1) = (i as f32 / count as f32, (i + 1) as f32 / count as f32);
+                segments.push(Segment { points: [points[3 * i], points[3 * i + 1],
+                                                 points[3 * i + 2], points[3 * i + 3]],
+                                        widths: (root + (tip - root) * u0, root + (tip - root) * u1) });
+            }
+        }
+        let segment_bounds: Vec<BoundingBox> = segments.iter().map(|s| s.bounds()).collect();
+        let bvh = Bvh::build(segment_bounds.as_slice(), params);
+        Curves { shape: shape,
+                 bounds: segment_bounds.iter().fold(BoundingBox::empty(), |b, s| b.union(s)),
+                 segments: segments,
+                 bvh: bvh }
+    }
+
+    fn closest_hit(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<CurveHit> {
+        let mut closest_u = 0.0;
+        let closest = self.bvh.closest(ray, tmax, |index, max| {
+            match self.segments[index].intersect(ray, tmin, max) {
+                // The BVH only keeps strictly nearer hits, so a tie mustn't
+                // change u either
+                Some((distance, u)) if distance < max => {
+                    closest_u = u;
+                    Some(distance)
+                },
+                _ => None
+            }
+        });
+        closest.map(|(index, distance)| {
+            CurveHit { distance: distance,
+                       segment: index,
+                       u: closest_u }
+        })
+    }
+}
+
+impl Intersectable for Curves {
+    /// u goes along each segment of a strand and v across its width.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let hit = match self.closest_hit(ray, tmin, tmax) {
+            Some(hit) => hit,
+            None => return None
+        };
+        let segment = &self.segments[hit.segment];
+        let points = segment.vectors();
+        let center = Point3::from_vec(&evaluate(&points, hit.u));
+        let width = segment.width(hit.u);
+        let distance = match self.shape {
+            CurveRibbon => hit.distance,
+            // Tubes bulge towards the ray from the middle of the curve
+            CurveTube => {
+                let point = ray.origin.add_v(&ray.direction.mul_s(hit.distance));
+                let radius = width * 0.5;
+                let offset2 = point.sub_p(&center).length2().min(radius * radius);
+                (hit.distance - (radius * radius - offset2).sqrt()).max(tmin)
+            }
+        };
+        let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+        let dpdu = tangent(&points, hit.u);
+        let along = if dpdu.length2() > 0.0 { dpdu.normalize() } else { Vector3::unit_z() };
+
+        // Ribbons face back along the ray, tubes face out from their middle
+        let facing = -ray.direction.sub_v(&along.mul_s(dot(ray.direction, along)));
+        let normal = match self.shape {
+            CurveRibbon => facing,
+            CurveTube => {
+                let out = point.sub_p(&center);
+                let out = out.sub_v(&along.mul_s(dot(out, along)));
+                if out.length2() > 0.0 { out } else { facing }
+            }
+        };
+        let normal = if normal.length2() > 0.0 { normal.normalize() } else { -ray.direction };
+        let across = normal.cross(&along);
+        let v = if width > 0.0 { 0.5 + dot(point.sub_p(&center), across) / width } else { 0.5 };
+        // Curves are only hit to within a fraction of their width, so
+        // leaving rays have to get well clear of them.
+        let error = Vector3::new(2.0 * width, 2.0 * width, 2.0 * width);
+        Some(Hit::new(ray, distance, normal, (hit.u, v.max(0.0).min(1.0)), dpdu, across.mul_s(width))
+                 .with_point(point, error)
+                 .with_primitive(hit.segment))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3};
+use scene::{TriangleMesh, BvhParams};
+use scene::tessellation::{EdgeSplitter, split_triangle};
+
+/// Base triangles can be many times longer than the edge length, so they
+/// get more levels of splitting than Bézier patches do.
+const MAX_DEPTH: uint = 24;
+
+/// A triangle mesh with texture coordinates, to be finely tessellated and
+/// moved along its normals by a displacement map. Vertices are a position
+/// and texture coordinates, and vertices at the same position always move
+/// together, so texture seams don't open up cracks.
+pub struct DisplacementBase {
+    positions: Vec<Point3<f32>>,
+    vertices: Vec<(uint, (f32, f32))>,
+    triangles: Vec<(uint, uint, uint)>
+}
+
+/// The base mesh as it's being split up, where whether an edge is split
+/// only depends on its length.
+struct Tessellation {
+    positions: Vec<Point3<f32>>,
+    /// Directions to displace each position along
+    normals: Vec<Vector3<f32>>,
+    vertices: Vec<(uint, (f32, f32))>,
+    triangles: Vec<(uint, uint, uint)>,
+    position_midpoints: HashMap<(uint, uint), uint>,
+    vertex_midpoints: HashMap<(uint, uint), uint>,
+    max_length2: f32
+}
+
+fn edge_key(a: uint, b: uint) -> (uint, uint) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Sums the area weighted normals of the triangles around each position.
+fn position_normals(positions: &[Point3<f32>], vertices: &[(uint, (f32, f32))],
+                    triangles: &[(uint, uint, uint)]) -> Vec<Vector3<f32>> {
+    let mut normals = Vec::from_elem(positions.len(), Vector3::new(0.0f32, 0.0, 0.0));
+    for &(a, b, c) in triangles.iter() {
+        let ((a, _), (b, _), (c, _)) = (vertices[a], vertices[b], vertices[c]);
+        let normal = positions[b].sub_p(&positions[a]).cross(&positions[c].sub_p(&positions[a]));
+        for &p in [a, b, c].iter() {
+            normals[p] = normals[p].add_v(&normal);
+        }
+    }
+    // Positions no triangle uses still need some normal
+    normals.into_iter().map(|n| {
+        if n.length2() > 0.0 { n.normalize() } else { Vector3::unit_z() }
+    }).collect()
+}
+
+impl DisplacementBase {
+    pub fn new(positions: Vec<Point3<f32>>, vertices: Vec<(uint, (f32, f32))>,
+               triangles: Vec<(uint, uint, uint)>) -> DisplacementBase {
+        for &(position, _) in vertices.iter() {
+            if position >= positions.len() {
+                fail!("Displaced vertex indexes past the {} positions", positions.len());
+            }
+        }
+        for &(a, b, c) in triangles.iter() {
+            if a >= vertices.len() || b >= vertices.len() || c >= vertices.len() {
+                fail!("Displaced triangle ({}, {}, {}) indexes past the {} vertices",
+                      a, b, c, vertices.len());
+            }
+        }
+        DisplacementBase { positions: positions,
+                           vertices: vertices,
+                           triangles: triangles }
+    }
+
+    /// Splits the triangles until no edge is longer than `edge_length`,
+    /// then moves every position along its normal by `scale` times how
+    /// far `height` at its texture coordinates is above `midlevel`.
+    pub fn displace(&self, edge_length: f32, scale: f32, midlevel: f32,
+                    height: |f32, f32| -> f32, bvh_params: &BvhParams) -> TriangleMesh {
+        if !(edge_length > 0.0) {
+            fail!("Displacement edge length has to be more than 0, not {}", edge_length);
+        }
+        let mut tessellation = Tessellation {
+            normals: position_normals(self.positions.as_slice(), self.vertices.as_slice(),
+                                      self.triangles.as_slice()),
+            positions: self.positions.clone(),
+            vertices: self.vertices.clone(),
+            triangles: Vec::new(),
+            position_midpoints: HashMap::new(),
+            vertex_midpoints: HashMap::new(),
+            max_length2: edge_length * edge_length
+        };
+        for &(a, b, c) in self.triangles.iter() {
+            split_triangle(&mut tessellation, a, b, c, MAX_DEPTH);
+        }
+        let Tessellation { positions, normals, vertices, triangles, .. } = tessellation;
+
+        // Vertices at the same position can have different texture
+        // coordinates, so move the position by their average height.
+        let mut heights = Vec::from_elem(positions.len(), (0.0f32, 0u));
+        for &(position, (u, v)) in vertices.iter() {
+            let (sum, count) = heights[position];
+            heights[position] = (sum + height(u, v), count + 1);
+        }
+        let displaced: Vec<Point3<f32>> = positions.iter().zip(normals.iter()).zip(heights.iter())
+                                                   .map(|((p, n), &(sum, count))| {
+            let h = if count > 0 { sum / count as f32 } else { midlevel };
+            p.add_v(&n.mul_s(scale * (h - midlevel)))
+        }).collect();
+
+        let shading = position_normals(displaced.as_slice(), vertices.as_slice(), triangles.as_slice());
+        let mesh_positions = vertices.iter().map(|&(p, _)| displaced[p]).collect();
+        let mesh_normals = vertices.iter().map(|&(p, _)| shading[p]).collect();
+        let mesh_uvs = vertices.iter().map(|&(_, uv)| uv).collect();
+        TriangleMesh::new(mesh_positions, triangles, bvh_params)
+            .with_normals(mesh_normals)
+            .with_uvs(mesh_uvs)
+    }
+}
+
+impl EdgeSplitter for Tessellation {
+    fn needs_split(&self, a: uint, b: uint) -> bool {
+        let ((pa, _), (pb, _)) = (self.vertices[a], self.vertices[b]);
+        self.positions[pa].sub_p(&self.positions[pb]).length2() > self.max_length2
+    }
+
+    /// The vertex halfway along an edge, made the first time it's asked for.
+    fn midpoint(&mut self, a: uint, b: uint) -> uint {
+        match self.vertex_midpoints.find(&edge_key(a, b)) {
+            Some(&vertex) => return vertex,
+            None => ()
+        }
+        let (pa, (ua, va)) = self.vertices[a];
+        let (pb, (ub, vb)) = self.vertices[b];
+        let position = match self.position_midpoints.find(&edge_key(pa, pb)).map(|&p| p) {
+            Some(position) => position,
+            None => {
+                let point = self.positions[pa].add_v(&self.positions[pb].sub_p(&self.positions[pa])
+                                                                         .mul_s(0.5));
+                let normal = self.normals[pa].add_v(&self.normals[pb]);
+                let normal = if normal.length2() > 0.0 { normal.normalize() } else { self.normals[pa] };
+                self.positions.push(point);
+                self.normals.push(normal);
+                self.position_midpoints.insert(edge_key(pa, pb), self.positions.len() - 1);
+                self.positions.len() - 1
+            }
+        };
+        self.vertices.push((position, ((ua + ub) * 0.5, (va + vb) * 0.5)));
+        self.vertex_midpoints.insert(edge_key(a, b), self.vertices.len() - 1);
+        self.vertices.len() - 1
+    }
+
+    fn add_triangle(&mut self, a: uint, b: uint, c: uint) {
+        self.triangles.push((a, b, c));
+    }
+}
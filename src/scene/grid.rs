@@ -0,0 +1,335 @@
+use std::cmp::{min, max};
+use std::f32::INFINITY;
+use time::precise_time_ns;
+use cgmath::{Point, Vector};
+use cgmath::{Vector3, Ray3};
+use scene::{Accelerator, BoundingBox, BvhParams};
+use scene::accelerator::{inverse_direction, record_query, SceneQuery};
+use scene::util::component;
+
+/// Cells along the longest side of the grid per cube root of the number
+/// of primitives, which works out at a few cells per primitive.
+const DENSITY: f32 = 3.0;
+const MAX_RESOLUTION: uint = 128;
+
+/// A uniform grid over the bounds of everything in it, walked a cell at a
+/// time along the ray. Cheap to build and quick when primitives are small
+/// and evenly spread out, but large primitives land in many cells and
+/// tight clusters end up sharing a few.
+/// See Amanatides and Woo, "A Fast Voxel Traversal Algorithm for Ray
+/// Tracing" (1987)
+pub struct Grid {
+    bounds: BoundingBox,
+    resolution: [uint, ..3],
+    cell_size: Vector3<f32>,
+    // The primitives in cell i are indices[cells[i]] up to indices[cells[i + 1]]
+    cells: Vec<uint>,
+    indices: Vec<uint>,
+    unbounded: Vec<uint>,
+    primitives: uint,
+    build_time: u64
+}
+
+/// The cells a ray passes through, in order, along with the distance at
+/// which it leaves each one.
+struct CellWalk {
+    resolution: [uint, ..3],
+    cell: [int, ..3],
+    step: [int, ..3],
+    next_crossing: [f32, ..3],
+    delta: [f32, ..3],
+    exit: f32,
+    done: bool
+}
+
+impl Grid {
+    pub fn build(bounds: &[BoundingBox]) -> Grid {
+        let start = precise_time_ns();
+        let mut grid = Grid { bounds: BoundingBox::empty(),
+                              resolution: [1, 1, 1],
+                              cell_size: Vector3::new(0.0, 0.0, 0.0),
+                              cells: Vec::new(),
+                              indices: Vec::new(),
+                              unbounded: Vec::new(),
+                              primitives: 0,
+                              build_time: 0 };
+        let mut items = Vec::with_capacity(bounds.len());
+        for (index, bound) in bounds.iter().enumerate() {
+            if bound.is_finite() {
+                items.push(index);
+                grid.bounds = grid.bounds.union(bound);
+            } else {
+                grid.unbounded.push(index);
+            }
+        }
+        grid.primitives = items.len();
+        if items.is_empty() {
+            grid.build_time = precise_time_ns() - start;
+            return grid;
+        }
+
+        let extent = grid.bounds.extent();
+        let longest = component(&extent, grid.bounds.largest_axis());
+        let cells_per_unit = if longest > 0.0 {
+            DENSITY * (items.len() as f32).cbrt() / longest
+        } else {
+            0.0
+        };
+        for axis in range(0u, 3) {
+            let cells = (component(&extent, axis) * cells_per_unit).round() as uint;
+            grid.resolution[axis] = max(min(cells, MAX_RESOLUTION), 1);
+        }
+        grid.cell_size = Vector3::new(extent.x / grid.resolution[0] as f32,
+                                      extent.y / grid.resolution[1] as f32,
+                                      extent.z / grid.resolution[2] as f32);
+
+        // Count the primitives in each cell first, so they can all go in
+        // one flat list.
+        let num_cells = grid.resolution[0] * grid.resolution[1] * grid.resolution[2];
+        let mut counts = Vec::from_elem(num_cells, 0u);
+        for &i in items.iter() {
+            let (low, high) = grid.cell_range(&bounds[i]);
+            for z in range(low[2], high[2] + 1) {
+                for y in range(low[1], high[1] + 1) {
+                    for x in range(low[0], high[0] + 1) {
+                        counts[grid.cell_index(x, y, z)] += 1;
+                    }
+                }
+            }
+        }
+        let mut cells = Vec::with_capacity(num_cells + 1);
+        cells.push(0u);
+        for &count in counts.iter() {
+            let offset = cells[cells.len() - 1] + count;
+            cells.push(offset);
+        }
+
+        let mut next = cells.slice_to(num_cells).to_vec();
+        let mut indices = Vec::from_elem(cells[num_cells], 0u);
+        for &i in items.iter() {
+            let (low, high) = grid.cell_range(&bounds[i]);
+            for z in range(low[2], high[2] + 1) {
+                for y in range(low[1], high[1] + 1) {
+                    for x in range(low[0], high[0] + 1) {
+                        let cell = grid.cell_index(x, y, z);
+                        indices[next[cell]] = i;
+                        next[cell] += 1;
+                    }
+                }
+            }
+        }
+        grid.cells = cells;
+        grid.indices = indices;
+        grid.build_time = precise_time_ns() - start;
+        grid
+    }
+
+    fn cell_index(&self, x: uint, y: uint, z: uint) -> uint {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    /// The cell containing `position` along `axis`, clamped to the grid.
+    fn cell_along(&self, position: f32, axis: uint) -> uint {
+        let size = component(&self.cell_size, axis);
+        if size <= 0.0 {
+            return 0;
+        }
+        let offset = (position - component(&self.bounds.min.to_vec(), axis)) / size;
+        if offset <= 0.0 {
+            0
+        } else {
+            min(offset as uint, self.resolution[axis] - 1)
+        }
+    }
+
+    /// The first and last cell a bounding box overlaps on each axis.
+    fn cell_range(&self, bounds: &BoundingBox) -> ([uint, ..3], [uint, ..3]) {
+        let mut low = [0u, 0, 0];
+        let mut high = [0u, 0, 0];
+        for axis in range(0u, 3) {
+            low[axis] = self.cell_along(component(&bounds.min.to_vec(), axis), axis);
+            high[axis] = self.cell_along(component(&bounds.max.to_vec(), axis), axis);
+        }
+        (low, high)
+    }
+
+    /// Starts walking the cells along `ray` from where it enters the grid.
+    fn walk(&self, ray: &Ray3<f32>, inverse: &Vector3<f32>, enter: f32, exit: f32) -> CellWalk {
+        let point = ray.origin.add_v(&ray.direction.mul_s(enter));
+        let mut walk = CellWalk { resolution: self.resolution,
+                                  cell: [0, 0, 0],
+                                  step: [0, 0, 0],
+                                  next_crossing: [INFINITY, INFINITY, INFINITY],
+                                  delta: [INFINITY, INFINITY, INFINITY],
+                                  exit: exit,
+                                  done: false };
+        for axis in range(0u, 3) {
+            let position = component(&point.to_vec(), axis);
+            let cell = self.cell_along(position, axis);
+            let direction = component(&ray.direction, axis);
+            let inverse = component(inverse, axis);
+            let min = component(&self.bounds.min.to_vec(), axis);
+            let size = component(&self.cell_size, axis);
+            walk.cell[axis] = cell as int;
+            // A ray parallel to an axis, or a grid that's flat along it,
+            // never crosses a cell boundary on that axis before leaving.
+            if direction == 0.0 || size <= 0.0 {
+                continue;
+            }
+            if direction > 0.0 {
+                walk.step[axis] = 1;
+                walk.next_crossing[axis] = enter + (min + (cell + 1) as f32 * size - position) * inverse;
+                walk.delta[axis] = size * inverse;
+            } else {
+                walk.step[axis] = -1;
+                walk.next_crossing[axis] = enter + (min + cell as f32 * size - position) * inverse;
+                walk.delta[axis] = -size * inverse;
+            }
+        }
+        walk
+    }
+}
+
+impl Iterator<(uint, f32)> for CellWalk {
+    fn next(&mut self) -> Option<(uint, f32)> {
+        if self.done {
+            return None;
+        }
+        let index = ((self.cell[2] as uint * self.resolution[1]) + self.cell[1] as uint)
+                    * self.resolution[0] + self.cell[0] as uint;
+
+        // Leave through whichever cell boundary the ray reaches first
+        let axis = if self.next_crossing[0] < self.next_crossing[1] {
+            if self.next_crossing[0] < self.next_crossing[2] { 0 } else { 2 }
+        } else {
+            if self.next_crossing[1] < self.next_crossing[2] { 1 } else { 2 }
+        };
+        let crossing = self.next_crossing[axis];
+        if crossing >= self.exit {
+            self.done = true;
+            return Some((index, self.exit));
+        }
+        self.cell[axis] += self.step[axis];
+        self.next_crossing[axis] += self.delta[axis];
+        if self.cell[axis] < 0 || self.cell[axis] >= self.resolution[axis] as int {
+            self.done = true;
+        }
+        Some((index, crossing))
+    }
+}
+
+impl Accelerator for Grid {
+    fn closest(&self, ray: &Ray3<f32>, max: f32,
+               intersect: |uint, f32| -> Option<f32>) -> Option<(uint, f32)> {
+        let mut closest = None;
+        let mut max = max;
+        let mut cells_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance < max => {
+                    max = distance;
+                    closest = Some((primitive, distance));
+                },
+                _ => ()
+            }
+        }
+
+        let inverse = inverse_direction(ray);
+        let span = if self.cells.is_empty() {
+            None
+        } else {
+            self.bounds.hit(&ray.origin, &inverse, max)
+        };
+        let (enter, exit) = match span {
+            Some(span) => span,
+            None => {
+                record_query(SceneQuery, cells_visited, primitives_tested);
+                return closest;
+            }
+        };
+
+        for (cell, cell_exit) in self.walk(ray, &inverse, enter, exit) {
+            cells_visited += 1;
+            let (start, end) = (self.cells[cell], self.cells[cell + 1]);
+            primitives_tested += end - start;
+            for &primitive in self.indices.slice(start, end).iter() {
+                // Like the kd-tree, only accept hits inside this cell, since
+                // primitives overlapping several cells are found from each.
+                let limit = if max < cell_exit { max } else { cell_exit };
+                match intersect(primitive, limit) {
+                    Some(distance) if distance < limit => {
+                        max = distance;
+                        closest = Some((primitive, distance));
+                    },
+                    _ => ()
+                }
+            }
+            if max <= cell_exit {
+                break;
+            }
+        }
+        record_query(SceneQuery, cells_visited, primitives_tested);
+        closest
+    }
+
+    fn any(&self, ray: &Ray3<f32>, max: f32, intersect: |uint, f32| -> Option<f32>) -> bool {
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance <= max => {
+                    record_query(SceneQuery, 0, self.unbounded.len());
+                    return true;
+                },
+                _ => ()
+            }
+        }
+        let mut cells_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+
+        let inverse = inverse_direction(ray);
+        let span = if self.cells.is_empty() {
+            None
+        } else {
+            self.bounds.hit(&ray.origin, &inverse, max)
+        };
+        let (enter, exit) = match span {
+            Some(span) => span,
+            None => {
+                record_query(SceneQuery, cells_visited, primitives_tested);
+                return false;
+            }
+        };
+
+        for (cell, _) in self.walk(ray, &inverse, enter, exit) {
+            cells_visited += 1;
+            let (start, end) = (self.cells[cell], self.cells[cell + 1]);
+            primitives_tested += end - start;
+            for &primitive in self.indices.slice(start, end).iter() {
+                match intersect(primitive, max) {
+                    Some(distance) if distance <= max => {
+                        record_query(SceneQuery, cells_visited, primitives_tested);
+                        return true;
+                    },
+                    _ => ()
+                }
+            }
+        }
+        record_query(SceneQuery, cells_visited, primitives_tested);
+        false
+    }
+
+    fn print_build_stats(&self, name: &str, _params: &BvhParams) {
+        let num_cells = self.resolution[0] * self.resolution[1] * self.resolution[2];
+        let occupied = if self.cells.is_empty() {
+            0
+        } else {
+            self.cells.as_slice().windows(2).filter(|pair| pair[1] > pair[0]).count()
+        };
+        println!("{} grid: {} primitives ({} unbounded), {}x{}x{} cells ({} empty), \
+                  {:.2} primitives per occupied cell, built in {:.2} ms",
+                 name, self.primitives, self.unbounded.len(),
+                 self.resolution[0], self.resolution[1], self.resolution[2],
+                 num_cells - occupied, self.indices.len() as f32 / max(occupied, 1) as f32,
+                 self.build_time as f64 / 1e6);
+    }
+}
@@ -0,0 +1,263 @@
+use std::cmp::min;
+use std::f32::INFINITY;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use scene::{Intersectable, Hit, BoundingBox};
+use scene::mesh::{WatertightRay, face_normal, triangle_tangents, barycentric_point};
+
+/// A grid of height samples spread over a rectangle, rendered as two
+/// triangles per grid cell without ever building them. Rays find the
+/// cells they might hit by descending a quadtree of the highest and lowest
+/// sample under each block of cells, a maximum mipmap, so big height maps
+/// only cost a few bytes per sample.
+/// See Tevs, Ihrke and Seidel, "Maximum Mipmaps for Fast, Accurate, and
+/// Scalable Dynamic Height Field Rendering" (2008)
+pub struct Heightfield {
+    /// Heights in world space, `columns` per row, rows going along +y.
+    heights: Vec<f32>,
+    columns: uint,
+    rows: uint,
+    origin: Point3<f32>,
+    cell_size: Vector3<f32>,
+    /// The first level has a texel per 2x2 block of cells, each level
+    /// after it has a texel per 2x2 block of the one before, up to a
+    /// single texel over the whole heightfield.
+    levels: Vec<Level>,
+    bounds: BoundingBox
+}
+
+struct Level {
+    columns: uint,
+    rows: uint,
+    min: Vec<f32>,
+    max: Vec<f32>
+}
+
+struct HeightfieldHit {
+    distance: f32,
+    corners: (uint, uint, uint),
+    barycentric: (f32, f32, f32)
+}
+
+impl Heightfield {
+    /// `heights` go from 0 to 1 and are scaled by `height`, with the
+    /// samples stretched over `size` from `origin`.
+    pub fn new(heights: Vec<f32>, columns: uint, rows: uint,
+               origin: (f32, f32, f32), size: (f32, f32), height: f32) -> Heightfield {
+        if columns < 2 || rows < 2 {
+            fail!("Heightfield needs at least 2x2 samples, not {}x{}", columns, rows);
+        }
+        if heights.len() != columns * rows {
+            fail!("Heightfield has {} samples instead of {}x{}", heights.len(), columns, rows);
+        }
+        let (x, y, z) = origin;
+        let (width, depth) = size;
+        let heights: Vec<f32> = heights.iter().map(|&h| z + h * height).collect();
+
+        // Blocks of 2x2 cells take their extremes straight from the samples
+        let cell_columns = columns - 1;
+        let cell_rows = rows - 1;
+        let mut first = Level::new((cell_columns + 1) / 2, (cell_rows + 1) / 2);
+        for j in range(0, first.rows) {
+            for i in range(0, first.columns) {
+                let mut low = INFINITY;
+                let mut high = -INFINITY;
+                for sy in range(2 * j, min(2 * j + 2, cell_rows) + 1) {
+                    for sx in range(2 * i, min(2 * i + 2, cell_columns) + 1) {
+                        low = low.min(heights[sy * columns + sx]);
+                        high = high.max(heights[sy * columns + sx]);
+                    }
+                }
+                first.min[j * first.columns + i] = low;
+                first.max[j * first.columns + i] = high;
+            }
+        }
+
+        let mut levels = vec![first];
+        loop {
+            let next = {
+                let previous = &levels[levels.len() - 1];
+                if previous.columns == 1 && previous.rows == 1 {
+                    break;
+                }
+                let mut next = Level::new((previous.columns + 1) / 2, (previous.rows + 1) / 2);
+                for j in range(0, next.rows) {
+                    for i in range(0, next.columns) {
+                        let mut low = INFINITY;
+                        let mut high = -INFINITY;
+                        for pj in range(2 * j, min(2 * j + 2, previous.rows)) {
+                            for pi in range(2 * i, min(2 * i + 2, previous.columns)) {
+                                low = low.min(previous.min[pj * previous.columns + pi]);
+                                high = high.max(previous.max[pj * previous.columns + pi]);
+                            }
+                        }
+                        next.min[j * next.columns + i] = low;
+                        next.max[j * next.columns + i] = high;
+                    }
+                }
+                next
+            };
+            levels.push(next);
+        }
+
+        let bounds = {
+            let top = &levels[levels.len() - 1];
+            BoundingBox::new(&Point3::new(x, y, top.min[0]),
+                             &Point3::new(x + width, y + depth, top.max[0]))
+        };
+        Heightfield { heights: heights,
+                      columns: columns,
+                      rows: rows,
+                      origin: Point3::new(x, y, z),
+                      cell_size: Vector3::new(width / cell_columns as f32,
+                                              depth / cell_rows as f32,
+                                              0.0),
+                      levels: levels,
+                      bounds: bounds }
+    }
+
+    /// The point at the sample with the given index into `heights`.
+    fn position(&self, index: uint) -> Point3<f32> {
+        let (x, y) = (index % self.columns, index / self.columns);
+        Point3::new(self.origin.x + x as f32 * self.cell_size.x,
+                    self.origin.y + y as f32 * self.cell_size.y,
+                    self.heights[index])
+    }
+
+    /// The surface coordinates of a sample, from 0 to 1 across the grid.
+    fn uv(&self, index: uint) -> (f32, f32) {
+        let (x, y) = (index % self.columns, index / self.columns);
+        (x as f32 / (self.columns - 1) as f32, y as f32 / (self.rows - 1) as f32)
+    }
+
+    /// Numbers the triangles two per cell, given the first two corners of
+    /// one the way `closest_hit` makes them.
+    fn triangle_index(&self, a: uint, b: uint) -> uint {
+        let cell = (a / self.columns) * (self.columns - 1) + a % self.columns;
+        if b == a + 1 { 2 * cell } else { 2 * cell + 1 }
+    }
+
+    /// The smooth normal at a sample, from the slope to its neighbours.
+    fn normal(&self, index: uint) -> Vector3<f32> {
+        let (x, y) = (index % self.columns, index / self.columns);
+        let (x0, x1) = (if x > 0 { x - 1 } else { x }, min(x + 1, self.columns - 1));
+        let (y0, y1) = (if y > 0 { y - 1 } else { y }, min(y + 1, self.rows - 1));
+        let height = |x: uint, y: uint| self.heights[y * self.columns + x];
+        let dx = (height(x1, y) - height(x0, y)) / ((x1 - x0) as f32 * self.cell_size.x);
+        let dy = (height(x, y1) - height(x, y0)) / ((y1 - y0) as f32 * self.cell_size.y);
+        Vector3::new(-dx, -dy, 1.0).normalize()
+    }
+
+    /// The box around the cells under a texel of a mipmap level.
+    fn node_bounds(&self, level: uint, i: uint, j: uint) -> BoundingBox {
+        let texel = &self.levels[level];
+        let cells = 2u << level;
+        let x0 = self.origin.x + (i * cells) as f32 * self.cell_size.x;
+        let y0 = self.origin.y + (j * cells) as f32 * self.cell_size.y;
+        let x1 = (x0 + cells as f32 * self.cell_size.x).min(self.bounds.max.x);
+        let y1 = (y0 + cells as f32 * self.cell_size.y).min(self.bounds.max.y);
+        BoundingBox::new(&Point3::new(x0, y0, texel.min[j * texel.columns + i]),
+                         &Point3::new(x1, y1, texel.max[j * texel.columns + i]))
+    }
+
+    fn closest_hit(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<HeightfieldHit> {
+        let inverse = Vector3::new(1.0 / ray.direction.x,
+                                   1.0 / ray.direction.y,
+                                   1.0 / ray.direction.z);
+        let watertight = WatertightRay::new(ray);
+        let mut closest = None;
+        let mut max = tmax;
+
+        let root = self.levels.len() - 1;
+        let mut stack = match self.node_bounds(root, 0, 0).hit(&ray.origin, &inverse, max) {
+            Some((enter, _)) => vec![(root, 0u, 0u, enter)],
+            None => return None
+        };
+        loop {
+            let (level, i, j, enter) = match stack.pop() {
+                Some(node) => node,
+                None => break
+            };
+            if enter > max {
+                continue;
+            }
+
+            if level == 0 {
+                for y in range(2 * j, min(2 * j + 2, self.rows - 1)) {
+                    for x in range(2 * i, min(2 * i + 2, self.columns - 1)) {
+                        // Split each cell along the diagonal from (x, y) to (x + 1, y + 1)
+                        let a = y * self.columns + x;
+                        let diagonal = a + self.columns + 1;
+                        for &(b, c) in [(a + 1, diagonal), (diagonal, a + self.columns)].iter() {
+                            let hit = watertight.intersect(&self.position(a),
+                                                           &self.position(b),
+                                                           &self.position(c));
+                            match hit {
+                                Some((distance, barycentric)) if distance >= tmin && distance <= max => {
+                                    max = distance;
+                                    closest = Some(HeightfieldHit { distance: distance,
+                                                                    corners: (a, b, c),
+                                                                    barycentric: barycentric });
+                                },
+                                _ => ()
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // Push the children the ray passes through furthest first, so
+            // the nearest comes off the stack next.
+            let below = &self.levels[level - 1];
+            let mut children = Vec::with_capacity(4);
+            for cj in range(2 * j, min(2 * j + 2, below.rows)) {
+                for ci in range(2 * i, min(2 * i + 2, below.columns)) {
+                    match self.node_bounds(level - 1, ci, cj).hit(&ray.origin, &inverse, max) {
+                        Some((enter, _)) => children.push((level - 1, ci, cj, enter)),
+                        None => ()
+                    }
+                }
+            }
+            children.sort_by(|&(_, _, _, a), &(_, _, _, b)| b.partial_cmp(&a).unwrap());
+            stack.extend(children.into_iter());
+        }
+        closest
+    }
+}
+
+impl Level {
+    fn new(columns: uint, rows: uint) -> Level {
+        Level { columns: columns,
+                rows: rows,
+                min: Vec::from_elem(columns * rows, 0.0),
+                max: Vec::from_elem(columns * rows, 0.0) }
+    }
+}
+
+impl Intersectable for Heightfield {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        self.closest_hit(ray, tmin, tmax).map(|hit| {
+            let (a, b, c) = hit.corners;
+            let (u, v, w) = hit.barycentric;
+            let (pa, pb, pc) = (self.position(a), self.position(b), self.position(c));
+            let (uv_a, uv_b, uv_c) = (self.uv(a), self.uv(b), self.uv(c));
+            let ((sa, ta), (sb, tb), (sc, tc)) = (uv_a, uv_b, uv_c);
+            let (dpdu, dpdv) = triangle_tangents(&pa, &pb, &pc, uv_a, uv_b, uv_c);
+            let normal = self.normal(a).mul_s(u)
+                             .add_v(&self.normal(b).mul_s(v))
+                             .add_v(&self.normal(c).mul_s(w))
+                             .normalize();
+            let (point, error) = barycentric_point(&pa, &pb, &pc, hit.barycentric);
+            Hit::new(ray, hit.distance, face_normal(&pa, &pb, &pc),
+                     (sa * u + sb * v + sc * w, ta * u + tb * v + tc * w), dpdu, dpdv)
+                .with_point(point, error)
+                .with_shading_normal(normal)
+                .with_primitive(self.triangle_index(a, b))
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
@@ -1,7 +1,103 @@
-use scene::{Intersection, SceneObject};
-use cgmath::{Ray3, Point3};
+use scene::{BoundingBox, Material};
+use scene::util::{gamma, abs_vector};
+use image_types::Color;
+use cgmath::{Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use cgmath::dot;
+
+/// Where a ray hits an object, and what the surface is like there.
+pub struct Hit {
+    pub distance: f32,
+    pub point: Point3<f32>,
+    /// How far off each coordinate of `point` could be from rounding, so
+    /// rays leaving the surface can start far enough from it.
+    pub error: Vector3<f32>,
+    /// The true normal of the surface, out of solids, and on the side of
+    /// flat surfaces their winding or normal says is the front.
+    pub geometric_normal: Vector3<f32>,
+    /// The normal to shade with, such as one interpolated from vertex
+    /// normals, on the same side of the surface as the geometric normal.
+    pub shading_normal: Vector3<f32>,
+    /// Coordinates on the surface, which textures are looked up by
+    pub uv: (f32, f32),
+    /// How the point on the surface moves as u and v increase. Along with
+    /// the normal they make up the tangent frame.
+    pub dpdu: Vector3<f32>,
+    pub dpdv: Vector3<f32>,
+    /// Whether the ray hit the side the geometric normal faces, rather
+    /// than leaving a solid or hitting the back of a surface
+    pub front_face: bool,
+    /// Which part of the object was hit, like the triangle in a mesh, or
+    /// 0 for objects that are a single shape
+    pub primitive: uint,
+    /// The object's own color at the hit, like interpolated vertex colors,
+    /// which tints its material
+    pub color: Option<Color>
+}
+
+impl Hit {
+    /// A hit on part 0 of an object, shaded with its geometric normal.
+    /// The point is found by going `distance` along the ray, with error
+    /// bounds that allow for the distance being a few operations off.
+    pub fn new(ray: &Ray3<f32>, distance: f32, normal: Vector3<f32>, uv: (f32, f32),
+               dpdu: Vector3<f32>, dpdv: Vector3<f32>) -> Hit {
+        let along = ray.direction.mul_s(distance);
+        Hit { distance: distance,
+              point: ray.origin.add_v(&along),
+              error: abs_vector(&ray.origin.to_vec()).add_v(&abs_vector(&along)).mul_s(gamma(7.0)),
+              geometric_normal: normal,
+              shading_normal: normal,
+              uv: uv,
+              dpdu: dpdu,
+              dpdv: dpdv,
+              front_face: dot(normal, ray.direction) <= 0.0,
+              primitive: 0,
+              color: None }
+    }
+
+    /// Replaces the point with one the object found more accurately than
+    /// going along the ray, along with its error bounds.
+    pub fn with_point(mut self, point: Point3<f32>, error: Vector3<f32>) -> Hit {
+        self.point = point;
+        self.error = error;
+        self
+    }
+
+    pub fn with_shading_normal(mut self, normal: Vector3<f32>) -> Hit {
+        // Keep the shading normal on the geometric normal's side of the
+        // surface, even where interpolated normals have tipped over.
+        self.shading_normal = if dot(normal, self.geometric_normal) < 0.0 { -normal } else { normal };
+        self
+    }
+
+    pub fn with_primitive(mut self, primitive: uint) -> Hit {
+        self.primitive = primitive;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Hit {
+        self.color = Some(color);
+        self
+    }
+}
 
 pub trait Intersectable {
-    fn intersection(&self, ray: &Ray3<f32>) -> Option<f32>;
-    fn intersection_info(&self, point: &Point3<f32>, object: &SceneObject) -> Intersection;
+    /// The closest hit at a distance from `tmin` to `tmax` along the ray.
+    /// Solids are hit from the inside too, where the ray leaves them.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit>;
+    fn bounds(&self) -> BoundingBox;
+
+    /// The intervals of the ray's whole line that are inside the object,
+    /// ascending and not overlapping. Distances behind the ray's origin
+    /// are negative. Only objects that enclose a volume have these, the
+    /// rest return `None` and can't be used in CSG operations.
+    fn spans(&self, _ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        None
+    }
+
+    /// The material at a hit, for objects made of several like voxels with
+    /// a palette. The rest return `None` and use their scene object's.
+    fn material<'a>(&'a self, _hit: &Hit) -> Option<&'a Material> {
+        None
+    }
 }
@@ -0,0 +1,372 @@
+use std::cmp::{max, Equal};
+use std::f32::INFINITY;
+use time::precise_time_ns;
+use cgmath::Point;
+use cgmath::{Vector3, Ray3};
+use scene::{Accelerator, BoundingBox, BvhParams};
+use scene::accelerator::{inverse_direction, record_query, SceneQuery};
+use scene::util::component;
+
+/// How much cheaper a split that leaves one side empty is made to look,
+/// since rays through the empty side skip straight past it.
+const EMPTY_BONUS: f32 = 0.2;
+
+const LEAF: uint = 3;
+
+// Event kinds, in the order they're swept when they share a position
+const END: uint = 0;
+const PLANAR: uint = 1;
+const START: uint = 2;
+
+/// A kd-tree built with the surface area heuristic, using the same costs
+/// as the BVH. Its cells never overlap, so traversal can stop at the
+/// first leaf with a hit, but primitives crossing a split are referenced
+/// from both sides.
+/// See Wald and Havran, "On building fast kd-trees for Ray Tracing, and on
+/// doing that in O(N log N)" (2006)
+pub struct KdTree {
+    nodes: Vec<KdNode>,
+    indices: Vec<uint>,
+    unbounded: Vec<uint>,
+    bounds: BoundingBox,
+    primitives: uint,
+    max_depth: uint,
+    build_time: u64
+}
+
+/// Nodes are stored depth first like the BVH's. Interior nodes split
+/// along `axis` at `split`, their first child is right after them and
+/// `offset` is the index of the second, which holds everything above the
+/// split. Leaves have `axis` set to `LEAF`, and their `count` primitives
+/// start at `offset` in `indices`.
+struct KdNode {
+    split: f32,
+    offset: uint,
+    count: uint,
+    axis: uint
+}
+
+struct Event {
+    position: f32,
+    kind: uint
+}
+
+impl KdTree {
+    pub fn build(bounds: &[BoundingBox], params: &BvhParams) -> KdTree {
+        let start = precise_time_ns();
+        let mut tree = KdTree { nodes: Vec::new(),
+                                indices: Vec::new(),
+                                unbounded: Vec::new(),
+                                bounds: BoundingBox::empty(),
+                                primitives: 0,
+                                max_depth: 0,
+                                build_time: 0 };
+        let mut items = Vec::with_capacity(bounds.len());
+        for (index, bound) in bounds.iter().enumerate() {
+            if bound.is_finite() {
+                items.push(index);
+                tree.bounds = tree.bounds.union(bound);
+            } else {
+                tree.unbounded.push(index);
+            }
+        }
+        tree.primitives = items.len();
+        if !items.is_empty() {
+            // The usual rule of thumb for how deep a kd-tree is worth going
+            let depth_limit = 8 + (1.3 * (items.len() as f32).log2()) as uint;
+            let root_bounds = tree.bounds.clone();
+            tree.build_node(bounds, items, &root_bounds, params, 0, depth_limit);
+        }
+        tree.build_time = precise_time_ns() - start;
+        tree
+    }
+
+    fn build_node(&mut self, bounds: &[BoundingBox], items: Vec<uint>, node_bounds: &BoundingBox,
+                  params: &BvhParams, depth: uint, depth_limit: uint) -> uint {
+        let index = self.nodes.len();
+        self.nodes.push(KdNode { split: 0.0,
+                                 offset: self.indices.len(),
+                                 count: items.len(),
+                                 axis: LEAF });
+        self.max_depth = max(self.max_depth, depth);
+
+        let split = if depth < depth_limit && items.len() > 1 {
+            find_split(bounds, items.as_slice(), node_bounds, params)
+        } else {
+            None
+        };
+        let leaf_cost = params.intersection_cost * items.len() as f32;
+        let (axis, position) = match split {
+            Some((cost, axis, position)) if cost < leaf_cost => (axis, position),
+            _ => {
+                self.indices.push_all(items.as_slice());
+                return index;
+            }
+        };
+
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for &i in items.iter() {
+            let low = component(&bounds[i].min.to_vec(), axis);
+            let high = component(&bounds[i].max.to_vec(), axis);
+            // Primitives lying flat in the split plane go left, which is
+            // where find_split counted them.
+            if low < position || (low == position && high == position) {
+                left.push(i);
+            }
+            if high > position {
+                right.push(i);
+            }
+        }
+
+        let (left_bounds, right_bounds) = split_bounds(node_bounds, axis, position);
+        self.build_node(bounds, left, &left_bounds, params, depth + 1, depth_limit);
+        let second = self.build_node(bounds, right, &right_bounds, params, depth + 1, depth_limit);
+        self.nodes[index].split = position;
+        self.nodes[index].offset = second;
+        self.nodes[index].count = 0;
+        self.nodes[index].axis = axis;
+        index
+    }
+
+    /// Queues the children of an interior node that the ray passes
+    /// through between `enter` and `exit`, so the nearer one is popped first.
+    fn push_children(&self, index: uint, ray: &Ray3<f32>, inverse: &Vector3<f32>,
+                     enter: f32, exit: f32, stack: &mut Vec<(uint, f32, f32)>) {
+        let node = &self.nodes[index];
+        let origin = component(&ray.origin.to_vec(), node.axis);
+        let direction = component(&ray.direction, node.axis);
+        let t = (node.split - origin) * component(inverse, node.axis);
+        // The child on the same side of the split as the origin is nearer
+        let (near, far) = if origin < node.split || (origin == node.split && direction <= 0.0) {
+            (index + 1, node.offset)
+        } else {
+            (node.offset, index + 1)
+        };
+        if direction == 0.0 || t > exit || t <= 0.0 {
+            stack.push((near, enter, exit));
+        } else if t < enter {
+            stack.push((far, enter, exit));
+        } else {
+            stack.push((far, t, exit));
+            stack.push((near, enter, t));
+        }
+    }
+}
+
+impl Accelerator for KdTree {
+    fn closest(&self, ray: &Ray3<f32>, max: f32,
+               intersect: |uint, f32| -> Option<f32>) -> Option<(uint, f32)> {
+        let mut closest = None;
+        let mut max = max;
+        let mut nodes_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance < max => {
+                    max = distance;
+                    closest = Some((primitive, distance));
+                },
+                _ => ()
+            }
+        }
+
+        let inverse = inverse_direction(ray);
+        let span = if self.nodes.is_empty() {
+            None
+        } else {
+            self.bounds.hit(&ray.origin, &inverse, max)
+        };
+        let (enter, exit) = match span {
+            Some(span) => span,
+            None => {
+                record_query(SceneQuery, nodes_visited, primitives_tested);
+                return closest;
+            }
+        };
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push((0u, enter, exit));
+        loop {
+            let (index, enter, exit) = match stack.pop() {
+                Some(entry) => entry,
+                None => break
+            };
+            // Nodes come off the stack front to back, so the rest are further
+            if enter > max {
+                break;
+            }
+            nodes_visited += 1;
+            let node = &self.nodes[index];
+            if node.axis != LEAF {
+                self.push_children(index, ray, &inverse, enter, exit, &mut stack);
+                continue;
+            }
+
+            primitives_tested += node.count;
+            for &primitive in self.indices.slice(node.offset, node.offset + node.count).iter() {
+                // Only accept hits inside this leaf, one further along
+                // might be beaten by a primitive in a leaf we haven't reached.
+                let limit = if max < exit { max } else { exit };
+                match intersect(primitive, limit) {
+                    Some(distance) if distance < limit => {
+                        max = distance;
+                        closest = Some((primitive, distance));
+                    },
+                    _ => ()
+                }
+            }
+            if max <= exit {
+                break;
+            }
+        }
+        record_query(SceneQuery, nodes_visited, primitives_tested);
+        closest
+    }
+
+    fn any(&self, ray: &Ray3<f32>, max: f32, intersect: |uint, f32| -> Option<f32>) -> bool {
+        for &primitive in self.unbounded.iter() {
+            match intersect(primitive, max) {
+                Some(distance) if distance <= max => {
+                    record_query(SceneQuery, 0, self.unbounded.len());
+                    return true;
+                },
+                _ => ()
+            }
+        }
+        let mut nodes_visited = 0;
+        let mut primitives_tested = self.unbounded.len();
+
+        let inverse = inverse_direction(ray);
+        let span = if self.nodes.is_empty() {
+            None
+        } else {
+            self.bounds.hit(&ray.origin, &inverse, max)
+        };
+        let (enter, exit) = match span {
+            Some(span) => span,
+            None => {
+                record_query(SceneQuery, nodes_visited, primitives_tested);
+                return false;
+            }
+        };
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push((0u, enter, exit));
+        loop {
+            let (index, enter, exit) = match stack.pop() {
+                Some(entry) => entry,
+                None => break
+            };
+            nodes_visited += 1;
+            let node = &self.nodes[index];
+            if node.axis != LEAF {
+                self.push_children(index, ray, &inverse, enter, exit, &mut stack);
+                continue;
+            }
+
+            primitives_tested += node.count;
+            for &primitive in self.indices.slice(node.offset, node.offset + node.count).iter() {
+                match intersect(primitive, max) {
+                    Some(distance) if distance <= max => {
+                        record_query(SceneQuery, nodes_visited, primitives_tested);
+                        return true;
+                    },
+                    _ => ()
+                }
+            }
+        }
+        record_query(SceneQuery, nodes_visited, primitives_tested);
+        false
+    }
+
+    fn print_build_stats(&self, name: &str, _params: &BvhParams) {
+        let leaves = self.nodes.iter().filter(|node| node.axis == LEAF).count();
+        let empty = self.nodes.iter().filter(|node| node.axis == LEAF && node.count == 0).count();
+        println!("{} kd-tree: {} primitives ({} unbounded), {} references, {} nodes, \
+                  {} leaves ({} empty), depth {}, built in {:.2} ms",
+                 name, self.primitives, self.unbounded.len(), self.indices.len(),
+                 self.nodes.len(), leaves, empty, self.max_depth,
+                 self.build_time as f64 / 1e6);
+    }
+}
+
+/// The cheapest split of a node by the surface area heuristic, as its
+/// cost, axis and position. Sweeps the sorted start and end points of the
+/// primitives along each axis, keeping count of how many are on each side.
+fn find_split(bounds: &[BoundingBox], items: &[uint], node_bounds: &BoundingBox,
+              params: &BvhParams) -> Option<(f32, uint, f32)> {
+    let area = node_bounds.surface_area();
+    let mut best = None;
+    let mut best_cost = INFINITY;
+    for axis in range(0u, 3) {
+        let low = component(&node_bounds.min.to_vec(), axis);
+        let high = component(&node_bounds.max.to_vec(), axis);
+        if high <= low {
+            continue;
+        }
+
+        let mut events = Vec::with_capacity(items.len() * 2);
+        for &i in items.iter() {
+            let start = component(&bounds[i].min.to_vec(), axis);
+            let end = component(&bounds[i].max.to_vec(), axis);
+            if start == end {
+                events.push(Event { position: start, kind: PLANAR });
+            } else {
+                events.push(Event { position: start, kind: START });
+                events.push(Event { position: end, kind: END });
+            }
+        }
+        events.sort_by(|a, b| {
+            match a.position.partial_cmp(&b.position).unwrap() {
+                Equal => a.kind.cmp(&b.kind),
+                order => order
+            }
+        });
+
+        let mut left = 0u;
+        let mut right = items.len();
+        let mut i = 0;
+        while i < events.len() {
+            let position = events[i].position;
+            let (mut ending, mut planar, mut starting) = (0u, 0u, 0u);
+            while i < events.len() && events[i].position == position {
+                match events[i].kind {
+                    END => ending += 1,
+                    PLANAR => planar += 1,
+                    _ => starting += 1
+                }
+                i += 1;
+            }
+
+            right -= ending + planar;
+            if position > low && position < high {
+                let (left_bounds, right_bounds) = split_bounds(node_bounds, axis, position);
+                let below = left + planar;
+                let mut cost = params.traversal_cost + params.intersection_cost *
+                    (below as f32 * left_bounds.surface_area() +
+                     right as f32 * right_bounds.surface_area()) / area;
+                if below == 0 || right == 0 {
+                    cost *= 1.0 - EMPTY_BONUS;
+                }
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = Some((cost, axis, position));
+                }
+            }
+            left += planar + starting;
+        }
+    }
+    best
+}
+
+fn split_bounds(bounds: &BoundingBox, axis: uint, position: f32) -> (BoundingBox, BoundingBox) {
+    let mut below = bounds.clone();
+    let mut above = bounds.clone();
+    match axis {
+        0 => { below.max.x = position; above.min.x = position; },
+        1 => { below.max.y = position; above.min.y = position; },
+        _ => { below.max.z = position; above.min.z = position; }
+    }
+    (below, above)
+}
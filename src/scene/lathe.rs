@@ -0,0 +1,220 @@
+use std::f32::consts::PI;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox, Accelerator, Bvh, BvhParams};
+use scene::scene_objects::{around_axis, cone_bounds};
+use scene::polynomial::roots_in_range;
+
+/// A surface of revolution, like a vase or a glass, made by turning a
+/// profile of (radius, height) points around an axis. Each segment of the
+/// profile sweeps out a cone frustum, or a flat ring where it's level, and
+/// is intersected exactly. The surface faces to the right of the profile
+/// as it's followed, so a profile going up the outside of a vase faces
+/// out, and one that starts and ends on the axis, going around
+/// anticlockwise, encloses a solid.
+pub struct Lathe {
+    base: Point3<f32>,
+    /// Unit direction of increasing height
+    axis: Vector3<f32>,
+    profile: Vec<(f32, f32)>,
+    /// Normal at each profile point as (out, up), averaged from the
+    /// segments on either side, when the surface is smooth shaded
+    normals: Option<Vec<(f32, f32)>>,
+    /// Distance along the profile to each point, which v goes along
+    lengths: Vec<f32>,
+    bvh: Bvh,
+    bounds: BoundingBox
+}
+
+/// The (out, up) normal to the right of the profile going from `a` to `b`.
+fn segment_normal(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let ((r0, h0), (r1, h1)) = (a, b);
+    let (out, up) = (h1 - h0, r0 - r1);
+    let length = (out * out + up * up).sqrt();
+    if length > 0.0 { (out / length, up / length) } else { (0.0, 0.0) }
+}
+
+/// The point `t` of the way from `b` to `c` along a Catmull-Rom spline.
+fn catmull_rom_blend(a: f32, b: f32, c: f32, d: f32, t: f32) -> f32 {
+    0.5 * (2.0 * b + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t * t
+           + (3.0 * b - a - 3.0 * c + d) * t * t * t)
+}
+
+/// Samples a Catmull-Rom spline through `points` with `steps` segments
+/// between each pair, so the profile passes through every point given.
+pub fn catmull_rom(points: &[(f32, f32)], steps: uint) -> Vec<(f32, f32)> {
+    if points.len() < 2 || steps == 0 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let mut samples = Vec::with_capacity(last * steps + 1);
+    for i in range(0, last) {
+        // The ends continue in a straight line past the first and last points
+        let ((r1, h1), (r2, h2)) = (points[i], points[i + 1]);
+        let (r0, h0) = if i > 0 { points[i - 1] } else { (2.0 * r1 - r2, 2.0 * h1 - h2) };
+        let (r3, h3) = if i + 2 <= last { points[i + 2] } else { (2.0 * r2 - r1, 2.0 * h2 - h1) };
+        for step in range(0, steps) {
+            let t = step as f32 / steps as f32;
+            // The spline can overshoot past the axis, where the radius
+            // would be negative
+            samples.push((catmull_rom_blend(r0, r1, r2, r3, t).max(0.0),
+                          catmull_rom_blend(h0, h1, h2, h3, t)));
+        }
+    }
+    samples.push(points[last]);
+    samples
+}
+
+impl Lathe {
+    /// Turns `profile` around the axis from `base` along `axis`, with
+    /// heights measured along the axis from `base`. Smooth lathes shade
+    /// with normals interpolated along each segment.
+    pub fn new(base: Point3<f32>, axis: Vector3<f32>, profile: Vec<(f32, f32)>, smooth: bool,
+               params: &BvhParams) -> Lathe {
+        // Repeated points would make segments with no length
+        let mut profile = profile;
+        profile.dedup();
+        if profile.len() < 2 {
+            fail!("Lathe profile needs at least 2 different points, not {}", profile.len());
+        }
+        if profile.iter().any(|&(r, _)| r < 0.0) {
+            fail!("Lathe profile radii can't be negative");
+        }
+        if !(axis.length2() > 0.0) {
+            fail!("Lathe axis can't be zero");
+        }
+        let axis = axis.normalize();
+        let segments = profile.len() - 1;
+
+        let mut lengths = vec![0.0f32];
+        for pair in profile.as_slice().windows(2) {
+            let ((r0, h0), (r1, h1)) = (pair[0], pair[1]);
+            let length = ((r1 - r0) * (r1 - r0) + (h1 - h0) * (h1 - h0)).sqrt();
+            let previous = lengths[lengths.len() - 1];
+            lengths.push(previous + length);
+        }
+        let normals = if smooth {
+            Some(range(0, profile.len()).map(|i| {
+                let (b_out, b_up) = if i > 0 {
+                    segment_normal(profile[i - 1], profile[i])
+                } else {
+                    (0.0, 0.0)
+                };
+                let (a_out, a_up) = if i < segments {
+                    segment_normal(profile[i], profile[i + 1])
+                } else {
+                    (0.0, 0.0)
+                };
+                let (out, up) = (b_out + a_out, b_up + a_up);
+                let length = (out * out + up * up).sqrt();
+                if length > 0.0 { (out / length, up / length) } else { (b_out, b_up) }
+            }).collect())
+        } else {
+            None
+        };
+
+        let segment_bounds: Vec<BoundingBox> = profile.as_slice().windows(2).map(|pair| {
+            let ((r0, h0), (r1, h1)) = (pair[0], pair[1]);
+            cone_bounds(&base.add_v(&axis.mul_s(h0)), &axis, h1 - h0, r0, r1)
+        }).collect();
+        let bvh = Bvh::build(segment_bounds.as_slice(), params);
+        Lathe { base: base,
+                axis: axis,
+                profile: profile,
+                normals: normals,
+                lengths: lengths,
+                bvh: bvh,
+                bounds: segment_bounds.iter().fold(BoundingBox::empty(), |b, s| b.union(s)) }
+    }
+
+    /// The nearest distance from `tmin` to `tmax` where the ray crosses the
+    /// surface swept by segment `index`.
+    fn intersect(&self, ray: &Ray3<f32>, index: uint, tmin: f32, tmax: f32) -> Option<f32> {
+        let ((r0, h0), (r1, h1)) = (self.profile[index], self.profile[index + 1]);
+        let local = ray.origin.sub_p(&self.base);
+        let (oh, dh) = (dot(local, self.axis) as f64, dot(ray.direction, self.axis) as f64);
+        let op = local.sub_v(&self.axis.mul_s(oh as f32));
+        let dp = ray.direction.sub_v(&self.axis.mul_s(dh as f32));
+        let (r0, h0, r1, h1) = (r0 as f64, h0 as f64, r1 as f64, h1 as f64);
+
+        // A level segment is a flat ring
+        if h0 == h1 {
+            if dh == 0.0 {
+                return None;
+            }
+            let t = (h0 - oh) / dh;
+            if t < tmin as f64 || t > tmax as f64 {
+                return None;
+            }
+            let r = op.add_v(&dp.mul_s(t as f32)).length() as f64;
+            return if r >= r0.min(r1) && r <= r0.max(r1) { Some(t as f32) } else { None };
+        }
+
+        // |op + t*dp| = r0 + slope*(oh + t*dh - h0), on the side of the
+        // cone's apex where the radius is positive
+        let slope = (r1 - r0) / (h1 - h0);
+        let (c0, c1) = (r0 + slope * (oh - h0), slope * dh);
+        let coefficients = [dot(op, op) as f64 - c0 * c0,
+                            2.0 * (dot(op, dp) as f64 - c0 * c1),
+                            dot(dp, dp) as f64 - c1 * c1];
+        let (low, high) = (h0.min(h1), h0.max(h1));
+        roots_in_range(&coefficients, tmin as f64, tmax as f64).into_iter().find(|&t| {
+            let h = oh + t * dh;
+            h >= low && h <= high && c0 + c1 * t >= 0.0
+        }).map(|t| t as f32)
+    }
+
+    fn hit(&self, ray: &Ray3<f32>, distance: f32, index: uint) -> Hit {
+        let ((r0, h0), (r1, h1)) = (self.profile[index], self.profile[index + 1]);
+        let local = ray.origin.add_v(&ray.direction.mul_s(distance)).sub_p(&self.base);
+        let h = dot(local, self.axis);
+        let radial = local.sub_v(&self.axis.mul_s(h));
+        let r = radial.length();
+        let (out, around, u) = around_axis(&radial, &self.axis);
+
+        // How far along the segment the hit is
+        let along = if (h1 - h0).abs() >= (r1 - r0).abs() {
+            (h - h0) / (h1 - h0)
+        } else {
+            (r - r0) / (r1 - r0)
+        };
+        let along = along.max(0.0).min(1.0);
+        let (start, end) = (self.lengths[index], self.lengths[index + 1]);
+        let total = self.lengths[self.lengths.len() - 1];
+        let v = (start + (end - start) * along) / total;
+
+        let to_world = |(n_out, n_up): (f32, f32)| out.mul_s(n_out).add_v(&self.axis.mul_s(n_up));
+        let normal = to_world(segment_normal((r0, h0), (r1, h1)));
+        let dpdu = around.mul_s(2.0 * PI * r);
+        let length = end - start;
+        let dpdv = out.mul_s(r1 - r0).add_v(&self.axis.mul_s(h1 - h0)).mul_s(total / length);
+        let hit = Hit::new(ray, distance, normal, (u, v), dpdu, dpdv).with_primitive(index);
+        match self.normals {
+            Some(ref normals) => {
+                let ((a_out, a_up), (b_out, b_up)) = (normals[index], normals[index + 1]);
+                let shading = to_world((a_out + (b_out - a_out) * along,
+                                        a_up + (b_up - a_up) * along));
+                if shading.length2() > 0.0 {
+                    hit.with_shading_normal(shading.normalize())
+                } else {
+                    hit
+                }
+            },
+            None => hit
+        }
+    }
+}
+
+impl Intersectable for Lathe {
+    /// u goes around the axis, and v along the profile from its first
+    /// point to its last.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        self.bvh.closest(ray, tmax, |index, max| self.intersect(ray, index, tmin, max))
+                .map(|(index, distance)| self.hit(ray, distance, index))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
@@ -0,0 +1,353 @@
+use std::mem::swap;
+use std::f32::INFINITY;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3, Ray};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox, Accelerator, Bvh, BvhParams};
+use scene::util::{component, gamma, abs_vector};
+use image_types::Color;
+
+pub struct Triangle {
+    a: Point3<f32>,
+    b: Point3<f32>,
+    c: Point3<f32>
+}
+
+pub struct TriangleMesh {
+    vertices: Vec<Point3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    uvs: Vec<(f32, f32)>,
+    colors: Vec<Color>,
+    triangles: Vec<(uint, uint, uint)>,
+    bounds: BoundingBox,
+    bvh: Bvh
+}
+
+/// A ray prepared for the watertight ray-triangle test.
+/// See Woop, Benthin and Wald, "Watertight Ray/Triangle Intersection" (JCGT 2013)
+/// Rays that cross a shared edge always hit one of the two triangles, so
+/// meshes don't leak light through cracks between neighbouring faces.
+pub struct WatertightRay {
+    origin: Point3<f32>,
+    kx: uint,
+    ky: uint,
+    kz: uint,
+    sx: f32,
+    sy: f32,
+    sz: f32
+}
+
+struct TriangleHit {
+    distance: f32,
+    index: uint,
+    barycentric: (f32, f32, f32)
+}
+
+impl WatertightRay {
+    pub fn new(ray: &Ray3<f32>) -> WatertightRay {
+        let dir = ray.direction;
+        // Use the dimension where the direction is largest as the "z" axis
+        let kz = if dir.x.abs() > dir.y.abs() {
+            if dir.x.abs() > dir.z.abs() { 0 } else { 2 }
+        } else {
+            if dir.y.abs() > dir.z.abs() { 1 } else { 2 }
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        // Keep the winding of the triangles the same after the permutation
+        if component(&dir, kz) < 0.0 {
+            swap(&mut kx, &mut ky);
+        }
+        let dz = component(&dir, kz);
+        WatertightRay { origin: ray.origin,
+                        kx: kx,
+                        ky: ky,
+                        kz: kz,
+                        sx: component(&dir, kx) / dz,
+                        sy: component(&dir, ky) / dz,
+                        sz: 1.0 / dz }
+    }
+
+    /// Returns the distance and barycentric coordinates of the hit.
+    pub fn intersect(&self, a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>)
+                 -> Option<(f32, (f32, f32, f32))> {
+        let a = a.sub_p(&self.origin);
+        let b = b.sub_p(&self.origin);
+        let c = c.sub_p(&self.origin);
+
+        // Shear and scale the vertices into ray space
+        let ax = component(&a, self.kx) - self.sx * component(&a, self.kz);
+        let ay = component(&a, self.ky) - self.sy * component(&a, self.kz);
+        let bx = component(&b, self.kx) - self.sx * component(&b, self.kz);
+        let by = component(&b, self.ky) - self.sy * component(&b, self.kz);
+        let cx = component(&c, self.kx) - self.sx * component(&c, self.kz);
+        let cy = component(&c, self.ky) - self.sy * component(&c, self.kz);
+
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
+
+        // The ray passes exactly through an edge, recompute the edge
+        // functions in double precision so we pick a side consistently.
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            let (ax, ay) = (ax as f64, ay as f64);
+            let (bx, by) = (bx as f64, by as f64);
+            let (cx, cy) = (cx as f64, cy as f64);
+            u = (cx * by - cy * bx) as f32;
+            v = (ax * cy - ay * cx) as f32;
+            w = (bx * ay - by * ax) as f32;
+        }
+
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+
+        let az = self.sz * component(&a, self.kz);
+        let bz = self.sz * component(&b, self.kz);
+        let cz = self.sz * component(&c, self.kz);
+        let distance = (u * az + v * bz + w * cz) / det;
+        if distance > 0.0 {
+            Some((distance, (u / det, v / det, w / det)))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn face_normal(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> Vector3<f32> {
+    b.sub_p(a).cross(&c.sub_p(a)).normalize()
+}
+
+fn interpolate_uv(a: (f32, f32), b: (f32, f32), c: (f32, f32),
+                  barycentric: (f32, f32, f32)) -> (f32, f32) {
+    let (u, v, w) = barycentric;
+    let ((sa, ta), (sb, tb), (sc, tc)) = (a, b, c);
+    (sa * u + sb * v + sc * w, ta * u + tb * v + tc * w)
+}
+
+/// The point at barycentric coordinates on a triangle, with its error
+/// bounds, which are much tighter than for going along the ray.
+pub fn barycentric_point(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>,
+                         barycentric: (f32, f32, f32)) -> (Point3<f32>, Vector3<f32>) {
+    let (u, v, w) = barycentric;
+    let (pa, pb, pc) = (a.to_vec().mul_s(u), b.to_vec().mul_s(v), c.to_vec().mul_s(w));
+    let error = abs_vector(&pa).add_v(&abs_vector(&pb)).add_v(&abs_vector(&pc)).mul_s(gamma(7.0));
+    (Point3::from_vec(&pa.add_v(&pb).add_v(&pc)), error)
+}
+
+/// How a point on a triangle moves with its texture coordinates, or along
+/// its edges from `a` when the texture coordinates don't cover any area.
+pub fn triangle_tangents(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>,
+                         uv_a: (f32, f32), uv_b: (f32, f32), uv_c: (f32, f32))
+                         -> (Vector3<f32>, Vector3<f32>) {
+    let ((ua, va), (ub, vb), (uc, vc)) = (uv_a, uv_b, uv_c);
+    let (du02, dv02, du12, dv12) = (ua - uc, va - vc, ub - uc, vb - vc);
+    let determinant = du02 * dv12 - dv02 * du12;
+    if determinant.abs() < 1e-12 {
+        return (b.sub_p(a), c.sub_p(a));
+    }
+    let (dp02, dp12) = (a.sub_p(c), b.sub_p(c));
+    (dp02.mul_s(dv12).sub_v(&dp12.mul_s(dv02)).div_s(determinant),
+     dp12.mul_s(du02).sub_v(&dp02.mul_s(du12)).div_s(determinant))
+}
+
+impl Triangle {
+    pub fn new(a: (f32, f32, f32), b: (f32, f32, f32), c: (f32, f32, f32)) -> Triangle {
+        let (ax, ay, az) = a;
+        let (bx, by, bz) = b;
+        let (cx, cy, cz) = c;
+        Triangle { a: Point3::new(ax, ay, az),
+                   b: Point3::new(bx, by, bz),
+                   c: Point3::new(cx, cy, cz) }
+    }
+}
+
+impl Intersectable for Triangle {
+    /// u and v go along the edges from `a` to `b` and from `a` to `c`.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        match WatertightRay::new(ray).intersect(&self.a, &self.b, &self.c) {
+            Some((distance, (u, v, w))) if distance >= tmin && distance <= tmax => {
+                let (point, error) = barycentric_point(&self.a, &self.b, &self.c, (u, v, w));
+                Some(Hit::new(ray, distance, face_normal(&self.a, &self.b, &self.c), (v, w),
+                              self.b.sub_p(&self.a), self.c.sub_p(&self.a))
+                         .with_point(point, error))
+            },
+            _ => None
+        }
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::around([self.a, self.b, self.c].as_slice())
+    }
+}
+
+impl TriangleMesh {
+    /// Builds a mesh from a shared vertex buffer and a list of triangles
+    /// given as indices into it, along with a BVH over the triangles.
+    pub fn new(vertices: Vec<Point3<f32>>, triangles: Vec<(uint, uint, uint)>,
+               params: &BvhParams) -> TriangleMesh {
+        for &(a, b, c) in triangles.iter() {
+            if a >= vertices.len() || b >= vertices.len() || c >= vertices.len() {
+                fail!("Triangle ({}, {}, {}) indexes past the {} mesh vertices",
+                      a, b, c, vertices.len());
+            }
+        }
+        let triangle_bounds: Vec<BoundingBox> = triangles.iter().map(|&(a, b, c)| {
+            BoundingBox::around([vertices[a], vertices[b], vertices[c]].as_slice())
+        }).collect();
+        let bvh = Bvh::build(triangle_bounds.as_slice(), params);
+
+        TriangleMesh { bounds: BoundingBox::around(vertices.as_slice()),
+                       vertices: vertices,
+                       normals: Vec::new(),
+                       uvs: Vec::new(),
+                       colors: Vec::new(),
+                       triangles: triangles,
+                       bvh: bvh }
+    }
+
+    /// Adds per-vertex normals, which are interpolated across each face
+    /// for smooth shading. Without them the mesh is shaded flat.
+    pub fn with_normals(mut self, normals: Vec<Vector3<f32>>) -> TriangleMesh {
+        if normals.len() != self.vertices.len() {
+            fail!("Mesh has {} vertices but {} normals", self.vertices.len(), normals.len());
+        }
+        self.normals = normals.iter().map(|n| n.normalize()).collect();
+        self
+    }
+
+    /// Adds per-vertex texture coordinates.
+    pub fn with_uvs(mut self, uvs: Vec<(f32, f32)>) -> TriangleMesh {
+        if uvs.len() != self.vertices.len() {
+            fail!("Mesh has {} vertices but {} texture coordinates", self.vertices.len(), uvs.len());
+        }
+        self.uvs = uvs;
+        self
+    }
+
+    /// Adds per-vertex colors, which are interpolated across each face and
+    /// tint the color of the object's material.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> TriangleMesh {
+        if colors.len() != self.vertices.len() {
+            fail!("Mesh has {} vertices but {} colors", self.vertices.len(), colors.len());
+        }
+        self.colors = colors;
+        self
+    }
+
+    fn corners(&self, index: uint) -> (&Point3<f32>, &Point3<f32>, &Point3<f32>) {
+        let (a, b, c) = self.triangles[index];
+        (&self.vertices[a], &self.vertices[b], &self.vertices[c])
+    }
+
+    fn closest_hit(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<TriangleHit> {
+        let watertight = WatertightRay::new(ray);
+        let mut closest_barycentric = (0.0, 0.0, 0.0);
+        let closest = self.bvh.closest(ray, tmax, |index, max| {
+            let (a, b, c) = self.corners(index);
+            match watertight.intersect(a, b, c) {
+                Some((distance, barycentric)) if distance >= tmin && distance < max => {
+                    closest_barycentric = barycentric;
+                    Some(distance)
+                },
+                _ => None
+            }
+        });
+        closest.map(|(index, distance)| {
+            TriangleHit { distance: distance,
+                          index: index,
+                          barycentric: closest_barycentric }
+        })
+    }
+}
+
+impl Intersectable for TriangleMesh {
+    /// Without texture coordinates, u and v go along the edges of each
+    /// triangle like they do for a single `Triangle`.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let hit = match self.closest_hit(ray, tmin, tmax) {
+            Some(hit) => hit,
+            None => return None
+        };
+        let (a, b, c) = self.corners(hit.index);
+        let (i, j, k) = self.triangles[hit.index];
+        let (u, v, w) = hit.barycentric;
+        let (uv, (dpdu, dpdv)) = if self.uvs.is_empty() {
+            ((v, w), (b.sub_p(a), c.sub_p(a)))
+        } else {
+            (interpolate_uv(self.uvs[i], self.uvs[j], self.uvs[k], hit.barycentric),
+             triangle_tangents(a, b, c, self.uvs[i], self.uvs[j], self.uvs[k]))
+        };
+        let (point, error) = barycentric_point(a, b, c, hit.barycentric);
+        let mut result = Hit::new(ray, hit.distance, face_normal(a, b, c), uv, dpdu, dpdv)
+                             .with_point(point, error)
+                             .with_primitive(hit.index);
+        if !self.normals.is_empty() {
+            result = result.with_shading_normal(self.normals[i].mul_s(u)
+                                                    .add_v(&self.normals[j].mul_s(v))
+                                                    .add_v(&self.normals[k].mul_s(w))
+                                                    .normalize());
+        }
+        if !self.colors.is_empty() {
+            result = result.with_color(self.colors[i].mul_s(u)
+                                           .add_c(&self.colors[j].mul_s(v))
+                                           .add_c(&self.colors[k].mul_s(w)));
+        }
+        Some(result)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+
+    /// Only meaningful for closed meshes, where the line goes in and out
+    /// at alternate crossings.
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        // Start the line from outside the mesh, so crossings behind the
+        // ray's origin are found too.
+        let back = self.bounds.centroid().sub_p(&ray.origin).length() +
+                   self.bounds.extent().length();
+        let line = Ray::new(ray.origin.add_v(&ray.direction.mul_s(-back)), ray.direction);
+        let watertight = WatertightRay::new(&line);
+        let mut crossings = Vec::new();
+        self.bvh.all(&line, INFINITY, |index| {
+            let (a, b, c) = self.corners(index);
+            match watertight.intersect(a, b, c) {
+                Some((distance, _)) => {
+                    let facing = dot(b.sub_p(a).cross(&c.sub_p(a)), line.direction) < 0.0;
+                    crossings.push((distance, facing));
+                },
+                None => ()
+            }
+        });
+        crossings.sort_by(|&(a, _), &(b, _)| a.partial_cmp(&b).unwrap());
+
+        // A line through an edge or a vertex hits every triangle around it,
+        // at distances that only differ by rounding. Where it crosses the
+        // surface they all face the same way and only one is kept, where it
+        // just grazes the surface they face both ways and make an empty span.
+        let mut kept: Vec<(f32, bool)> = Vec::with_capacity(crossings.len());
+        for &(distance, facing) in crossings.iter() {
+            let repeated = match kept.last() {
+                Some(&(last, last_facing)) => {
+                    facing == last_facing && distance - last <= gamma(7.0) * distance
+                },
+                None => false
+            };
+            if !repeated {
+                kept.push((distance, facing));
+            }
+        }
+        Some(kept.as_slice().chunks(2)
+                 .filter(|pair| pair.len() == 2)
+                 .map(|pair| {
+                     let ((enter, _), (exit, _)) = (pair[0], pair[1]);
+                     (enter - back, exit - back)
+                 })
+                 .collect())
+    }
+}
@@ -0,0 +1,184 @@
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox, Accelerator, Bvh, BvhParams};
+use scene::polynomial::{multiply, roots_in_range};
+use scene::util::orthonormal_basis;
+
+/// One of the centres of a metaball field. It adds
+/// `weight * (1 - r^2/radius^2)^3` to the field within `radius` of its
+/// center, and nothing further out. A negative weight carves into the
+/// other balls.
+pub struct Metaball {
+    pub center: Point3<f32>,
+    pub radius: f32,
+    pub weight: f32
+}
+
+/// The surface where the summed field of a set of metaballs reaches
+/// `threshold`, for organic blobby shapes.
+///
+/// Each ball's falloff is a polynomial of the squared distance, so along a
+/// ray it's a degree 6 polynomial of the distance. Marching the ray from
+/// one ball's boundary to the next, the field between them is the sum of
+/// the balls it's inside, and the first crossing is found exactly with the
+/// polynomial root finder rather than by stepping.
+/// See Wyvill, McPheeters and Wyvill, "Data Structure for Soft Objects" (1986)
+pub struct Metaballs {
+    balls: Vec<Metaball>,
+    threshold: f32,
+    bvh: Bvh,
+    bounds: BoundingBox
+}
+
+/// Where the ray is inside one ball, and the ball's field along the ray
+/// there as a polynomial of the distance.
+struct Piece {
+    ball: uint,
+    enter: f64,
+    exit: f64,
+    field: Vec<f64>
+}
+
+impl Metaball {
+    fn bounds(&self) -> BoundingBox {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        BoundingBox::new(&self.center.add_v(&-r), &self.center.add_v(&r))
+    }
+
+    /// Where the ray is inside the ball past `tmin`, and the ball's field
+    /// along the ray there as a polynomial of the distance.
+    fn along(&self, ray: &Ray3<f32>, tmin: f64) -> Option<(f64, f64, Vec<f64>)> {
+        let delta = ray.origin.sub_p(&self.center);
+        let r2 = self.radius as f64 * self.radius as f64;
+        let a = ray.direction.length2() as f64;
+        let b = dot(ray.direction, delta) as f64;
+        let c = delta.length2() as f64;
+        let discriminant = b * b - a * (c - r2);
+        if discriminant <= 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (enter, exit) = ((-b - root) / a, (-b + root) / a);
+        if exit <= tmin {
+            return None;
+        }
+        // 1 - |origin + t*direction - center|^2 / radius^2
+        let falloff = [1.0 - c / r2, -2.0 * b / r2, -a / r2];
+        let cubed = multiply(multiply(&falloff, &falloff).as_slice(), &falloff);
+        let weight = self.weight as f64;
+        Some((enter.max(tmin), exit, cubed.into_iter().map(|c| c * weight).collect()))
+    }
+
+    /// The gradient of the ball's field at `point`.
+    fn gradient(&self, point: &Point3<f32>) -> Vector3<f32> {
+        let offset = point.sub_p(&self.center);
+        let r2 = self.radius * self.radius;
+        let falloff = 1.0 - offset.length2() / r2;
+        if falloff <= 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        offset.mul_s(-6.0 * self.weight * falloff * falloff / r2)
+    }
+}
+
+impl Metaballs {
+    pub fn new(balls: Vec<Metaball>, threshold: f32, params: &BvhParams) -> Metaballs {
+        // With a threshold of zero or less, all of space outside the balls
+        // would be inside the surface.
+        if !(threshold > 0.0) {
+            fail!("Metaball threshold has to be more than 0, not {}", threshold);
+        }
+        for ball in balls.iter() {
+            if !(ball.radius > 0.0) {
+                fail!("Metaball radius has to be more than 0, not {}", ball.radius);
+            }
+        }
+        let ball_bounds: Vec<BoundingBox> = balls.iter().map(|b| b.bounds()).collect();
+        let bvh = Bvh::build(ball_bounds.as_slice(), params);
+        // Balls with negative weights can't make any surface of their own
+        let bounds = balls.iter().zip(ball_bounds.iter())
+                          .filter(|&(ball, _)| ball.weight > 0.0)
+                          .fold(BoundingBox::empty(), |b, (_, bounds)| b.union(bounds));
+        Metaballs { balls: balls,
+                    threshold: threshold,
+                    bvh: bvh,
+                    bounds: bounds }
+    }
+
+    /// A hit at `distance`, which is inside some of the balls in `pieces`.
+    fn hit(&self, ray: &Ray3<f32>, distance: f32, pieces: &[Piece]) -> Hit {
+        let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+        // Only the balls the ray is inside there add to the field
+        let at = distance as f64;
+        let mut gradient = Vector3::new(0.0f32, 0.0, 0.0);
+        for piece in pieces.iter() {
+            if piece.enter <= at && at <= piece.exit {
+                gradient = gradient.add_v(&self.balls[piece.ball].gradient(&point));
+            }
+        }
+        // The field rises towards the inside, so the normal is against it
+        let normal = if gradient.length2() > 0.0 { -gradient.normalize() } else { -ray.direction };
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Hit::new(ray, distance, normal, (0.0, 0.0), tangent, bitangent)
+    }
+
+    /// The field along the ray from each ball it passes through.
+    fn pieces(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        self.bvh.all(ray, tmax, |index| {
+            match self.balls[index].along(ray, tmin as f64) {
+                Some((enter, exit, field)) => {
+                    pieces.push(Piece { ball: index, enter: enter, exit: exit, field: field })
+                },
+                None => ()
+            }
+        });
+        pieces
+    }
+}
+
+impl Intersectable for Metaballs {
+    /// Metaballs have no natural surface coordinates, so u and v are
+    /// always 0 and the tangents are just perpendicular to the normal.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let pieces = self.pieces(ray, tmin, tmax);
+        if pieces.is_empty() {
+            return None;
+        }
+        // The field only changes form where the ray enters or leaves a ball
+        let mut boundaries = Vec::with_capacity(2 * pieces.len());
+        for piece in pieces.iter() {
+            boundaries.push(piece.enter);
+            boundaries.push(piece.exit);
+        }
+        boundaries.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for span in boundaries.as_slice().windows(2) {
+            let (start, end) = (span[0], span[1].min(tmax as f64));
+            if end <= start {
+                continue;
+            }
+            let middle = 0.5 * (start + end);
+            let mut field = Vec::from_elem(7, 0.0f64);
+            field[0] = -self.threshold as f64;
+            for piece in pieces.iter() {
+                if piece.enter <= middle && middle <= piece.exit {
+                    for (sum, &c) in field.iter_mut().zip(piece.field.iter()) {
+                        *sum += c;
+                    }
+                }
+            }
+            let roots = roots_in_range(field.as_slice(), start, end);
+            match roots.into_iter().find(|&root| root >= tmin as f64) {
+                Some(root) => return Some(self.hit(ray, root as f32, pieces.as_slice())),
+                None => ()
+            }
+        }
+        None
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
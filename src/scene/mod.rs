@@ -1,35 +1,104 @@
 use parse_scene::parse_scene;
 use std::sync::Arc;
+use std::f32::INFINITY;
 use image_types::Color;
-use cgmath::{Point, Vector};
+use cgmath::{EuclideanVector, Vector};
 use cgmath::{Vector3, Point3, Ray3, Ray};
 use cgmath::dot;
-use self::util::random_cos_around;
+use self::util::{random_cos_around, random_unit_vector};
 pub use self::illuminator::Illuminator;
-pub use self::intersectable::Intersectable;
-pub use self::scene_objects::{SceneObject, Sphere};
+pub use self::intersectable::{Intersectable, Hit};
+pub use self::scene_objects::{SceneObject, Sphere, Plane, Disk, Rectangle};
+pub use self::scene_objects::{AxisAlignedBox, OrientedBox, Cylinder, Cone, Capsule, Torus};
+pub use self::mesh::{Triangle, TriangleMesh};
+pub use self::heightfield::Heightfield;
+pub use self::subdivision::{ControlCage, SubdivisionScheme, CatmullClarkScheme, LoopScheme};
+pub use self::displacement::DisplacementBase;
+pub use self::bezier::BezierPatches;
+pub use self::curves::{Curves, CurveShape, CurveRibbon, CurveTube};
+pub use self::particles::Particles;
+pub use self::metaballs::{Metaballs, Metaball};
+pub use self::voxels::Voxels;
+pub use self::lathe::{Lathe, catmull_rom};
+pub use self::transform::Transformed;
+pub use self::sdf::{DistanceField, SphereTraced, SphereField, RoundedBoxField, TorusField};
+pub use self::sdf::{CapsuleField, SmoothUnion, SmoothSubtraction, SmoothIntersection};
+pub use self::sdf::{Repetition, Mandelbulb};
+pub use self::csg::{Csg, CsgOperation, CsgUnion, CsgIntersection, CsgDifference};
+pub use self::bounding_box::BoundingBox;
+pub use self::accelerator::{Accelerator, AcceleratorType, BvhAccelerator, KdTreeAccelerator};
+pub use self::accelerator::{GridAccelerator, build_accelerator};
+pub use self::accelerator::{TraversalStats, take_traversal_stats, print_traversal_stats};
+pub use self::bvh::{Bvh, BvhParams};
+pub use self::kd_tree::KdTree;
+pub use self::grid::Grid;
+pub use self::util::rotate_euler;
 pub use self::scene_lights::{SceneLight, PointLight, DirectionalLight};
+pub use self::camera::Camera;
+pub use self::texture::{Texture, VOrigin, TopRow, BottomRow, bilinear_taps};
 
 mod util;
+mod polynomial;
+mod bounding_box;
+mod accelerator;
+mod bvh;
+mod kd_tree;
+mod grid;
 mod illuminator;
 mod intersectable;
 mod scene_objects;
+mod mesh;
+mod heightfield;
+mod subdivision;
+mod tessellation;
+mod displacement;
+mod bezier;
+mod curves;
+mod particles;
+mod metaballs;
+mod voxels;
+mod lathe;
+mod transform;
+mod csg;
+mod sdf;
 mod scene_lights;
+mod camera;
+mod texture;
 
 pub struct Scene {
+    pub camera: Camera,
     pub objects: Vec<SceneObject>,
     pub lights: Vec<SceneLight>,
     pub num_gi_samples: u32,
     pub num_shadow_samples: u32,
-    pub bounces: u32
+    pub bounces: u32,
+    pub accelerator_type: AcceleratorType,
+    pub bvh_params: BvhParams,
+    pub accelerator: Box<Accelerator+Send+Sync+'static>
 }
 
+#[deriving(Clone)]
 pub struct Material {
-    pub color: Color
+    pub color: Color,
+    /// From 0 for plastics and other dielectrics up to 1 for metals, which
+    /// reflect their surroundings tinted by their color instead of
+    /// scattering light.
+    pub metallic: f32,
+    /// How blurry reflections are, from 0 for a mirror
+    pub roughness: f32,
+    /// Multiplies the color, looked up at the texture coordinates of the
+    /// hit on surfaces that have them.
+    pub texture: Option<Arc<Texture>>
 }
 
 pub fn build_scene(filename: &str) -> Scene {
-    let scene = parse_scene(filename);
+    let mut scene = parse_scene(filename);
+    let bounds: Vec<BoundingBox> = scene.objects.iter().map(|o| o.bounds()).collect();
+    scene.accelerator = build_accelerator(scene.accelerator_type, bounds.as_slice(),
+                                          &scene.bvh_params);
+    // Only the scene's structure is printed. Assets can have thousands of
+    // meshes, each with its own BVH, which would flood the output.
+    scene.accelerator.print_build_stats("Scene", &scene.bvh_params);
     scene
 }
 
@@ -38,60 +107,66 @@ impl Scene {
         let intersect = self.find_intersection(ray);
         match intersect {
             Some(intersection) => {
-                let diff = self.light_diffuse(&intersection.point,
-                                              &intersection.normal,
-                                              depth);
-                
-                diff.mul_c(&intersection.material.color)
+                let material = intersection.material;
+                let mut light = Color { r: 0.0, g: 0.0, b: 0.0 };
+                if material.metallic < 1.0 {
+                    let diff = self.light_diffuse(&intersection.point,
+                                                  &intersection.normal,
+                                                  depth);
+                    light = light.add_c(&diff.mul_s(1.0 - material.metallic));
+                }
+                if material.metallic > 0.0 {
+                    let reflected = self.reflection(ray, &intersection, depth);
+                    light = light.add_c(&reflected.mul_s(material.metallic));
+                }
+                light.mul_c(&intersection.albedo)
             }
             None         => sky_color(&ray.direction)
         }
     }
 
-    fn find_intersection(&self, ray: &Ray3<f32>) -> Option<Intersection> {
-        let mut closest = None;
-        let mut closest_distance = 99999999999.0;
-        for object in self.objects.iter() {
-            let result = object.intersection(ray);
-            match result {
-                Some(distance) => {
-                    if distance < closest_distance {
-                        closest = Some(object);
-                        closest_distance = distance;
-                    }
+    fn find_intersection<'a>(&'a self, ray: &Ray3<f32>) -> Option<Intersection<'a>> {
+        let mut closest_hit = None;
+        let closest = self.accelerator.closest(ray, INFINITY, |index, max| {
+            match self.objects[index].intersection(ray, 0.0, max) {
+                Some(hit) if hit.distance < max => {
+                    closest_hit = Some(hit);
+                    Some(hit.distance)
                 },
-                None => ()
+                _ => None
             }
+        });
+
+        match (closest, closest_hit) {
+            (Some((index, _)), Some(hit)) => Some(self.objects[index].intersection_info(&hit)),
+            _ => None
         }
-        
-        match closest {
-            Some(object) => {
-                let point = ray.origin.add_v(&ray.direction.mul_s(closest_distance));
-                let intersection = object.intersection_info(&point);
-                Some(intersection)
-            },
-            None => None
+    }
+
+    /// Light reflected off a metal, with the reflection direction blurred
+    /// more the rougher the surface is.
+    fn reflection(&self, ray: &Ray3<f32>, intersection: &Intersection, depth: u32) -> Color {
+        let normal = &intersection.normal;
+        let mirror = ray.direction.sub_v(&normal.mul_s(2.0 * dot(ray.direction, *normal)));
+        let blurred = mirror.add_v(&random_unit_vector().mul_s(intersection.material.roughness))
+                            .normalize();
+        // Don't let the blur send the ray into the surface
+        let direction = if dot(blurred, *normal) > 0.0 { blurred } else { mirror };
+        if depth < self.bounces {
+            self.trace_ray(&Ray::new(intersection.point, direction), depth + 1)
+        } else {
+            sky_color(&direction)
         }
     }
 
     pub fn check_ray(&self, ray: &Ray3<f32>) -> bool {
-        for object in self.objects.iter() {
-            match object.intersection(ray) {
-                Some(_) => return true,
-                None    => ()
-            }
-        }
-        false
+        self.check_ray_distance(ray, INFINITY)
     }
 
     pub fn check_ray_distance(&self, ray: &Ray3<f32>, distance: f32) -> bool {
-        for object in self.objects.iter() {
-            match object.intersection(ray) {
-                Some(d) if d <= distance => return true,
-                _ => ()
-            }
-        }
-        false
+        self.accelerator.any(ray, distance, |index, max| {
+            self.objects[index].intersection(ray, 0.0, max).map(|hit| hit.distance)
+        })
     }
 
     pub fn light_diffuse(&self, point: &Point3<f32>, normal: &Vector3<f32>, depth: u32) -> Color {
@@ -130,8 +205,10 @@ fn sky_color(direction: &Vector3<f32>) -> Color {
     Color { r: 0.0, g: 0.0, b: fac }
 }
 
-struct Intersection {
+struct Intersection<'a> {
     point: Point3<f32>,
     normal: Vector3<f32>,
-    material: Arc<Material>
+    material: &'a Material,
+    /// The material's color at this point, after any texture and tint
+    albedo: Color
 }
@@ -0,0 +1,91 @@
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox, Accelerator, Bvh, BvhParams};
+use scene::scene_objects::sphere_hit;
+
+/// Lots of small spheres, like the output of a particle simulation. They're
+/// kept as separate lists of each coordinate rather than a `Sphere` object
+/// each, so tens of millions of them fit in memory, and share one material
+/// and one BVH.
+pub struct Particles {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    zs: Vec<f32>,
+    /// Either one radius for every particle, or a radius each
+    radii: Vec<f32>,
+    bvh: Bvh,
+    bounds: BoundingBox
+}
+
+impl Particles {
+    /// Particles at the given coordinates, with either a single radius
+    /// shared by all of them or one radius per particle.
+    pub fn new(xs: Vec<f32>, ys: Vec<f32>, zs: Vec<f32>, radii: Vec<f32>,
+               params: &BvhParams) -> Particles {
+        if xs.len() != ys.len() || xs.len() != zs.len() {
+            fail!("Particles have {} x, {} y and {} z coordinates", xs.len(), ys.len(), zs.len());
+        }
+        if radii.len() != 1 && radii.len() != xs.len() {
+            fail!("Particles need one radius or one for each of the {} particles, not {}",
+                  xs.len(), radii.len());
+        }
+        if radii.iter().any(|&r| !(r > 0.0)) {
+            fail!("Particle radii have to be more than 0");
+        }
+        let mut particles = Particles { xs: xs,
+                                        ys: ys,
+                                        zs: zs,
+                                        radii: radii,
+                                        bvh: Bvh::empty(),
+                                        bounds: BoundingBox::empty() };
+        // The boxes are only needed while building, the BVH just keeps
+        // indices into the coordinate lists.
+        let particle_bounds: Vec<BoundingBox> = range(0, particles.xs.len()).map(|i| {
+            let r = particles.radius(i);
+            let center = particles.center(i);
+            BoundingBox::new(&center.add_v(&Vector3::new(-r, -r, -r)),
+                             &center.add_v(&Vector3::new(r, r, r)))
+        }).collect();
+        particles.bvh = Bvh::build(particle_bounds.as_slice(), params);
+        particles.bounds = particle_bounds.iter().fold(BoundingBox::empty(), |b, p| b.union(p));
+        particles
+    }
+
+    fn center(&self, index: uint) -> Point3<f32> {
+        Point3::new(self.xs[index], self.ys[index], self.zs[index])
+    }
+
+    fn radius(&self, index: uint) -> f32 {
+        if self.radii.len() == 1 { self.radii[0] } else { self.radii[index] }
+    }
+
+    /// Same as `Sphere`, distance to where the ray enters particle
+    /// `index`, or leaves it if it starts inside.
+    fn intersect(&self, ray: &Ray3<f32>, index: uint, tmin: f32, tmax: f32) -> Option<f32> {
+        let delta = ray.origin.sub_p(&self.center(index));
+        let radius = self.radius(index);
+        let b = dot(ray.direction, delta);
+        let c = delta.length2() - radius * radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let distance = if -b - root >= tmin { -b - root } else { -b + root };
+        if distance >= tmin && distance <= tmax { Some(distance) } else { None }
+    }
+}
+
+impl Intersectable for Particles {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        self.bvh.closest(ray, tmax, |index, max| self.intersect(ray, index, tmin, max))
+                .map(|(index, distance)| {
+            sphere_hit(ray, distance, &self.center(index), self.radius(index)).with_primitive(index)
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
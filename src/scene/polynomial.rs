@@ -0,0 +1,131 @@
+//! Real root finding for the polynomials that come out of intersecting
+//! rays with implicit surfaces.
+//!
+//! Rather than the closed form cubic and quartic formulas, which lose most
+//! of their precision to cancellation exactly where surfaces are seen at
+//! grazing angles, roots are isolated recursively: the roots of the
+//! derivative split the range into pieces where the polynomial is
+//! monotonic, and each piece that changes sign holds exactly one root,
+//! which is then found with bracketed Newton iteration.
+
+/// Coefficients are given lowest degree first, so `[c, b, a]` is
+/// `a*x^2 + b*x + c`.
+pub fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |sum, &c| sum * x + c)
+}
+
+pub fn derivative(coefficients: &[f64]) -> Vec<f64> {
+    coefficients.iter().enumerate().skip(1).map(|(power, &c)| c * power as f64).collect()
+}
+
+/// The product of two polynomials.
+pub fn multiply(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut product = Vec::from_elem(a.len() + b.len() - 1, 0.0f64);
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
+
+/// All real roots of the polynomial inside `[min, max]`, in ascending order.
+pub fn roots_in_range(coefficients: &[f64], min: f64, max: f64) -> Vec<f64> {
+    // Drop leading coefficients that are negligible next to the rest, so a
+    // nearly degenerate quartic is solved as the cubic it really is.
+    let scale = coefficients.iter().fold(0.0f64, |m, &c| m.max(c.abs()));
+    if scale == 0.0 {
+        return Vec::new();
+    }
+    let mut degree = coefficients.len() - 1;
+    while degree > 0 && coefficients[degree].abs() <= scale * 1e-14 {
+        degree -= 1;
+    }
+    let coefficients = coefficients.slice_to(degree + 1);
+
+    match degree {
+        0 => Vec::new(),
+        1 => {
+            let root = -coefficients[0] / coefficients[1];
+            if root >= min && root <= max { vec![root] } else { Vec::new() }
+        },
+        2 => quadratic_roots(coefficients[2], coefficients[1], coefficients[0])
+                 .into_iter().filter(|&root| root >= min && root <= max).collect(),
+        _ => {
+            let mut bounds = vec![min];
+            bounds.extend(roots_in_range(derivative(coefficients).as_slice(), min, max).into_iter());
+            bounds.push(max);
+
+            let mut roots = Vec::new();
+            for pair in bounds.as_slice().windows(2) {
+                match bracketed_root(coefficients, pair[0], pair[1]) {
+                    Some(root) => roots.push(root),
+                    None => ()
+                }
+            }
+            roots
+        }
+    }
+}
+
+/// Roots of `a*x^2 + b*x + c`, avoiding the cancellation in the textbook formula.
+pub fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b*b - 4.0*a*c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let q = if b < 0.0 {
+        -0.5 * (b - discriminant.sqrt())
+    } else {
+        -0.5 * (b + discriminant.sqrt())
+    };
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    let (x0, x1) = (q / a, c / q);
+    if x0 < x1 { vec![x0, x1] } else { vec![x1, x0] }
+}
+
+/// Finds the root in `[min, max]` of a polynomial that is monotonic over
+/// that range, if its sign changes across it.
+fn bracketed_root(coefficients: &[f64], min: f64, max: f64) -> Option<f64> {
+    let f_min = evaluate(coefficients, min);
+    let f_max = evaluate(coefficients, max);
+    if f_min == 0.0 {
+        return Some(min);
+    }
+    if f_max == 0.0 {
+        return Some(max);
+    }
+    if (f_min < 0.0) == (f_max < 0.0) {
+        return None;
+    }
+
+    let slope = derivative(coefficients);
+    let (mut low, mut high) = if f_min < 0.0 { (min, max) } else { (max, min) };
+    let mut x = 0.5 * (min + max);
+    for _ in range(0u, 100) {
+        let f = evaluate(coefficients, x);
+        if f == 0.0 {
+            return Some(x);
+        }
+        if f < 0.0 { low = x } else { high = x }
+
+        // Take the Newton step when it stays inside the bracket, bisect otherwise
+        let df = evaluate(slope.as_slice(), x);
+        let newton = x - f / df;
+        let next = if df != 0.0 && (newton - low) * (newton - high) < 0.0 {
+            newton
+        } else {
+            0.5 * (low + high)
+        };
+        if (next - x).abs() <= 1e-12 * (1.0 + x.abs()) {
+            return Some(next);
+        }
+        x = next;
+    }
+    Some(x)
+}
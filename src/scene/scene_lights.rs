@@ -24,9 +24,10 @@ pub struct SceneLight {
 }
 
 impl Illuminator for SceneLight {
+    /// Points are already moved off the surface they're on by its error
+    /// bounds, so shadow rays from them won't hit it.
     fn illuminate(&self, scene: &Scene, point: &Point3<f32>, normal: &Vector3<f32>) -> Color {
-        let off_surface_point = &point.add_v(&normal.mul_s(0.0001));
-        self.illuminator.illuminate(scene, off_surface_point, normal)
+        self.illuminator.illuminate(scene, point, normal)
     }
 }
 
@@ -1,26 +1,131 @@
 use std::sync::Arc;
+use std::f32::INFINITY;
+use std::f32::consts::PI;
 use cgmath::{EuclideanVector, Point, Vector};
-use cgmath::{Point3, Ray3};
+use cgmath::{Point3, Vector3, Ray3};
 use cgmath::dot;
-use scene::{Intersectable, Material, Intersection};
+use scene::{Intersectable, Hit, Material, Intersection, BoundingBox};
+use scene::util::{component, rotate_euler, orthonormal_basis, gamma, abs_vector, offset_origin};
+use scene::polynomial::roots_in_range;
 
 pub struct Sphere {
     pos: Point3<f32>,
     radius: f32,
 }
 
+/// An infinite plane through `pos`.
+pub struct Plane {
+    pos: Point3<f32>,
+    normal: Vector3<f32>
+}
+
+pub struct Disk {
+    pos: Point3<f32>,
+    normal: Vector3<f32>,
+    radius: f32
+}
+
+/// A parallelogram spanned by two edges from a corner, usually a rectangle.
+pub struct Rectangle {
+    corner: Point3<f32>,
+    edge1: Vector3<f32>,
+    edge2: Vector3<f32>,
+    normal: Vector3<f32>
+}
+
+pub struct AxisAlignedBox {
+    min: Point3<f32>,
+    max: Point3<f32>
+}
+
+/// A box with its own local axes, centered on `pos`.
+pub struct OrientedBox {
+    pos: Point3<f32>,
+    half_size: Vector3<f32>,
+    axes: (Vector3<f32>, Vector3<f32>, Vector3<f32>)
+}
+
+/// A capped cylinder standing on `base`, extending `height` along `axis`.
+pub struct Cylinder {
+    base: Point3<f32>,
+    axis: Vector3<f32>,
+    height: f32,
+    radius: f32
+}
+
+/// A capped cone, or a truncated cone when `top_radius` isn't zero.
+pub struct Cone {
+    base: Point3<f32>,
+    axis: Vector3<f32>,
+    height: f32,
+    base_radius: f32,
+    top_radius: f32
+}
+
+/// A cylinder with hemispherical ends, the points within `radius` of
+/// the segment from `start` to `end`.
+pub struct Capsule {
+    start: Point3<f32>,
+    end: Point3<f32>,
+    radius: f32
+}
+
+/// A ring around `axis` through `pos`, `major_radius` from the center to
+/// the middle of the tube and `minor_radius` across the tube.
+pub struct Torus {
+    pos: Point3<f32>,
+    axis: Vector3<f32>,
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    major_radius: f32,
+    minor_radius: f32
+}
+
 pub struct SceneObject {
     pub material: Arc<Material>,
     pub geometry: Box<Intersectable+Send+Sync+'static>
 }
 
 impl SceneObject {
-    pub fn intersection(&self, ray: &Ray3<f32>) -> Option<f32> {
-        self.geometry.intersection(ray)
+    pub fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        self.geometry.intersection(ray, tmin, tmax)
+    }
+
+    /// What to shade at a hit: the point moved off the surface towards the
+    /// side the ray came from, the normal turned that way too, and the
+    /// material's color tinted by the hit's own color and the material's
+    /// texture. The material itself is shared, so shading doesn't allocate.
+    pub fn intersection_info<'a>(&'a self, hit: &Hit) -> Intersection<'a> {
+        let (geometric, normal) = if hit.front_face {
+            (hit.geometric_normal, hit.shading_normal)
+        } else {
+            (-hit.geometric_normal, -hit.shading_normal)
+        };
+        let material = match self.geometry.material(hit) {
+            Some(material) => material,
+            None => &*self.material
+        };
+        let mut albedo = material.color.clone();
+        match hit.color {
+            Some(ref tint) => albedo = tint.mul_c(&albedo),
+            None => ()
+        }
+        match material.texture {
+            Some(ref texture) => {
+                let (u, v) = hit.uv;
+                albedo = texture.sample(u, v).mul_c(&albedo);
+            },
+            None => ()
+        }
+
+        Intersection { point: offset_origin(&hit.point, &hit.error, &geometric),
+                       normal: normal,
+                       material: material,
+                       albedo: albedo }
     }
 
-    pub fn intersection_info(&self, point: &Point3<f32>) -> Intersection {
-        self.geometry.intersection_info(point, self)
+    pub fn bounds(&self) -> BoundingBox {
+        self.geometry.bounds()
     }
 }
 
@@ -33,7 +138,7 @@ impl Sphere {
 }
 
 impl Intersectable for Sphere {
-    fn intersection(&self, ray: &Ray3<f32>) -> Option<f32> {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
         // Optimized ray-sphere intersection
         // See http://en.wikipedia.org/wiki/Line%E2%80%93sphere_intersection
         let delta = ray.origin.sub_p(&self.pos);
@@ -43,21 +148,705 @@ impl Intersectable for Sphere {
         // Optimized discriminant, our b in normal b/2, which means that
         // we have normal (b^2)/4 as our b^2, so we can not multiply c by 4
         let discriminant = b*b - c;
-        if discriminant >= 0.0 {
-            // Our b is half the normal b, so we don't have to divide by 2
-            let distance = -b - discriminant.sqrt();
-            if distance > 0.0 {
-                return Some(distance);
-            }
+        if discriminant < 0.0 {
+            return None;
         }
+        // Our b is half the normal b, so we don't have to divide by 2.
+        // Rays that start inside leave through the far root.
+        let root = discriminant.sqrt();
+        let distance = if -b - root >= tmin { -b - root } else { -b + root };
+        if distance < tmin || distance > tmax {
+            return None;
+        }
+        Some(sphere_hit(ray, distance, &self.pos, self.radius))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(sphere_span(ray, &self.pos, self.radius))
+    }
+}
+
+/// A hit on a sphere, with u going around the z axis and v from the top
+/// of the sphere to the bottom.
+pub fn sphere_hit(ray: &Ray3<f32>, distance: f32, center: &Point3<f32>, radius: f32) -> Hit {
+    // Moving the point back onto the sphere makes it much more accurate
+    // than the distance along the ray it came from.
+    let local = ray.origin.add_v(&ray.direction.mul_s(distance)).sub_p(center);
+    let local = local.mul_s(radius / local.length());
+    let point = center.add_v(&local);
+    let error = abs_vector(&local).mul_s(gamma(5.0))
+                                  .add_v(&abs_vector(&point.to_vec()).mul_s(gamma(1.0)));
+    let phi = local.y.atan2(local.x);
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+    let theta = (local.z / radius).max(-1.0).min(1.0).acos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    Hit::new(ray, distance, local.normalize(), (phi / (2.0 * PI), theta / PI),
+             Vector3::new(-local.y, local.x, 0.0).mul_s(2.0 * PI),
+             Vector3::new(local.z * cos_phi, local.z * sin_phi, -radius * theta.sin()).mul_s(PI))
+        .with_point(point, error)
+}
+
+/// Distance along `ray` to the plane through `pos` with normal `normal`,
+/// if the ray isn't parallel to it and the plane is from `tmin` to `tmax`
+/// along the ray.
+fn plane_distance(ray: &Ray3<f32>, pos: &Point3<f32>, normal: &Vector3<f32>,
+                  tmin: f32, tmax: f32) -> Option<f32> {
+    let denominator = dot(*normal, ray.direction);
+    if denominator.abs() < 1e-9 {
+        return None;
+    }
+    let distance = dot(pos.sub_p(&ray.origin), *normal) / denominator;
+    if distance >= tmin && distance <= tmax {
+        Some(distance)
+    } else {
         None
     }
+}
 
-    fn intersection_info(&self, point: &Point3<f32>, object: &SceneObject) -> Intersection {
-        let normal = point.sub_p(&self.pos).normalize();
-        
-        Intersection { point: point.add_v(&normal.mul_s(0.000001)),
-                       normal: normal,
-                       material: object.material.clone() }
+/// A hit on a flat surface, with u and v measured along the perpendicular
+/// tangents `dpdu` and `dpdv` from `origin`.
+fn planar_hit(ray: &Ray3<f32>, distance: f32, normal: &Vector3<f32>, origin: &Point3<f32>,
+              dpdu: Vector3<f32>, dpdv: Vector3<f32>) -> Hit {
+    let local = ray.origin.add_v(&ray.direction.mul_s(distance)).sub_p(origin);
+    let uv = (dot(local, dpdu) / dpdu.length2(), dot(local, dpdv) / dpdv.length2());
+    Hit::new(ray, distance, *normal, uv, dpdu, dpdv)
+}
+
+impl Plane {
+    pub fn new(origin: (f32, f32, f32), normal: (f32, f32, f32)) -> Plane {
+        let (x, y, z) = origin;
+        let (nx, ny, nz) = normal;
+        Plane { pos: Point3::new(x, y, z),
+                normal: Vector3::new(nx, ny, nz).normalize() }
+    }
+}
+
+impl Intersectable for Plane {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        plane_distance(ray, &self.pos, &self.normal, tmin, tmax).map(|distance| {
+            let (tangent, bitangent) = orthonormal_basis(&self.normal);
+            planar_hit(ray, distance, &self.normal, &self.pos, tangent, bitangent)
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::infinite()
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        // The solid side is the one the normal points away from
+        let denominator = dot(self.normal, ray.direction);
+        let height = dot(ray.origin.sub_p(&self.pos), self.normal);
+        if denominator.abs() < 1e-9 {
+            return Some(if height <= 0.0 { vec![(-INFINITY, INFINITY)] } else { Vec::new() });
+        }
+        let distance = -height / denominator;
+        if denominator > 0.0 {
+            Some(vec![(-INFINITY, distance)])
+        } else {
+            Some(vec![(distance, INFINITY)])
+        }
+    }
+}
+
+impl Disk {
+    pub fn new(origin: (f32, f32, f32), normal: (f32, f32, f32), radius: f32) -> Disk {
+        let (x, y, z) = origin;
+        let (nx, ny, nz) = normal;
+        Disk { pos: Point3::new(x, y, z),
+               normal: Vector3::new(nx, ny, nz).normalize(),
+               radius: radius }
+    }
+}
+
+impl Intersectable for Disk {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        plane_distance(ray, &self.pos, &self.normal, tmin, tmax).and_then(|distance| {
+            let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+            if point.sub_p(&self.pos).length2() <= self.radius*self.radius {
+                // Map the square around the disk to the whole of u and v
+                let (tangent, bitangent) = orthonormal_basis(&self.normal);
+                let (dpdu, dpdv) = (tangent.mul_s(2.0 * self.radius),
+                                    bitangent.mul_s(2.0 * self.radius));
+                let corner = self.pos.add_v(&dpdu.add_v(&dpdv).mul_s(-0.5));
+                Some(planar_hit(ray, distance, &self.normal, &corner, dpdu, dpdv))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = disk_extent(&self.normal, self.radius);
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+}
+
+impl Rectangle {
+    pub fn new(corner: (f32, f32, f32), edge1: (f32, f32, f32), edge2: (f32, f32, f32)) -> Rectangle {
+        let (x, y, z) = corner;
+        let (ax, ay, az) = edge1;
+        let (bx, by, bz) = edge2;
+        let edge1 = Vector3::new(ax, ay, az);
+        let edge2 = Vector3::new(bx, by, bz);
+        Rectangle { corner: Point3::new(x, y, z),
+                    edge1: edge1,
+                    edge2: edge2,
+                    normal: edge1.cross(&edge2) }
+    }
+}
+
+impl Intersectable for Rectangle {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        plane_distance(ray, &self.corner, &self.normal, tmin, tmax).and_then(|distance| {
+            // Solve for the hit point as corner + u*edge1 + v*edge2, which
+            // also works when the edges aren't perpendicular.
+            let local = ray.origin.add_v(&ray.direction.mul_s(distance)).sub_p(&self.corner);
+            let area2 = self.normal.length2();
+            let u = dot(local.cross(&self.edge2), self.normal) / area2;
+            let v = dot(self.edge1.cross(&local), self.normal) / area2;
+            if u >= 0.0 && u <= 1.0 && v >= 0.0 && v <= 1.0 {
+                Some(Hit::new(ray, distance, self.normal.normalize(), (u, v), self.edge1, self.edge2))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::around([self.corner,
+                             self.corner.add_v(&self.edge1),
+                             self.corner.add_v(&self.edge2),
+                             self.corner.add_v(&self.edge1).add_v(&self.edge2)].as_slice())
+    }
+}
+
+/// The range of distances where a line with the given origin and direction
+/// components is between `min` and `max` along one axis.
+fn slab(origin: f32, direction: f32, min: f32, max: f32) -> (f32, f32) {
+    if direction == 0.0 {
+        return if origin >= min && origin <= max {
+            (-INFINITY, INFINITY)
+        } else {
+            (INFINITY, -INFINITY)
+        };
+    }
+    let t0 = (min - origin) / direction;
+    let t1 = (max - origin) / direction;
+    if t0 < t1 { (t0, t1) } else { (t1, t0) }
+}
+
+fn overlap(a: (f32, f32), b: (f32, f32)) -> Option<(f32, f32)> {
+    let (a0, a1) = a;
+    let (b0, b1) = b;
+    let enter = a0.max(b0);
+    let exit = a1.min(b1);
+    if enter <= exit { Some((enter, exit)) } else { None }
+}
+
+/// The nearest distance from `tmin` to `tmax` where a ray enters or
+/// leaves a convex solid, given the span of the solid along the ray's line.
+fn span_distance(span: Option<(f32, f32)>, tmin: f32, tmax: f32) -> Option<f32> {
+    match span {
+        Some((enter, _)) if enter >= tmin => if enter <= tmax { Some(enter) } else { None },
+        Some((_, exit)) if exit >= tmin && exit <= tmax => Some(exit),
+        _ => None
+    }
+}
+
+pub fn unit(axis: uint) -> Vector3<f32> {
+    match axis {
+        0 => Vector3::unit_x(),
+        1 => Vector3::unit_y(),
+        _ => Vector3::unit_z()
+    }
+}
+
+/// The spans of a convex solid, which has at most one.
+fn convex_spans(span: Option<(f32, f32)>) -> Option<Vec<(f32, f32)>> {
+    match span {
+        Some(span) => Some(vec![span]),
+        None => Some(Vec::new())
+    }
+}
+
+fn box_span(origin: &Vector3<f32>, direction: &Vector3<f32>,
+            min: &Vector3<f32>, max: &Vector3<f32>) -> Option<(f32, f32)> {
+    let mut span = (-INFINITY, INFINITY);
+    for axis in range(0, 3) {
+        let axis_span = slab(component(origin, axis), component(direction, axis),
+                             component(min, axis), component(max, axis));
+        match overlap(span, axis_span) {
+            Some(s) => span = s,
+            None    => return None
+        }
+    }
+    Some(span)
+}
+
+/// The normal, surface coordinates and tangents on the box face closest
+/// to `point`. Each face covers the whole of u and v.
+fn box_surface(point: &Vector3<f32>, min: &Vector3<f32>, max: &Vector3<f32>)
+               -> (Vector3<f32>, (f32, f32), Vector3<f32>, Vector3<f32>) {
+    let mut normal = Vector3::unit_x();
+    let mut face = 0;
+    let mut closest = INFINITY;
+    for axis in range(0, 3) {
+        let p = component(point, axis);
+        let to_min = (p - component(min, axis)).abs();
+        let to_max = (p - component(max, axis)).abs();
+        if to_min < closest {
+            closest = to_min;
+            normal = -unit(axis);
+            face = axis;
+        }
+        if to_max < closest {
+            closest = to_max;
+            normal = unit(axis);
+            face = axis;
+        }
+    }
+    let (a, b) = ((face + 1) % 3, (face + 2) % 3);
+    let size = max.sub_v(min);
+    let uv = ((component(point, a) - component(min, a)) / component(&size, a),
+              (component(point, b) - component(min, b)) / component(&size, b));
+    (normal, uv, unit(a).mul_s(component(&size, a)), unit(b).mul_s(component(&size, b)))
+}
+
+fn sphere_span(ray: &Ray3<f32>, center: &Point3<f32>, radius: f32) -> Option<(f32, f32)> {
+    let delta = ray.origin.sub_p(center);
+    let b = dot(ray.direction, delta);
+    let c = delta.length2() - radius*radius;
+    let discriminant = b*b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some((-b - root, -b + root))
+}
+
+/// Span of a line through a capped cone with radius `base_radius +
+/// slope * h` at height `h` along `axis`, which is a cylinder when the
+/// slope is zero.
+fn cone_span(ray: &Ray3<f32>, base: &Point3<f32>, axis: &Vector3<f32>, height: f32,
+             base_radius: f32, slope: f32) -> Option<(f32, f32)> {
+    let origin = ray.origin.sub_p(base);
+    let o_par = dot(origin, *axis);
+    let d_par = dot(ray.direction, *axis);
+    let o_perp = origin.sub_v(&axis.mul_s(o_par));
+    let d_perp = ray.direction.sub_v(&axis.mul_s(d_par));
+    let radius = base_radius + slope * o_par;
+
+    // The line is inside the (double) cone where a*t^2 + 2*b*t + c <= 0
+    let a = d_perp.length2() - slope * slope * d_par * d_par;
+    let b = dot(o_perp, d_perp) - slope * radius * d_par;
+    let c = o_perp.length2() - radius * radius;
+    let whole = (-INFINITY, INFINITY);
+    let inside = if a.abs() < 1e-9 {
+        if b.abs() < 1e-9 {
+            if c <= 0.0 { vec![whole] } else { vec![] }
+        } else if b > 0.0 {
+            vec![(-INFINITY, -c / (2.0 * b))]
+        } else {
+            vec![(-c / (2.0 * b), INFINITY)]
+        }
+    } else {
+        let discriminant = b*b - a*c;
+        if discriminant < 0.0 {
+            if a < 0.0 { vec![whole] } else { vec![] }
+        } else {
+            let root = discriminant.sqrt();
+            let t0 = (-b - root) / a;
+            let t1 = (-b + root) / a;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if a > 0.0 {
+                vec![(t0, t1)]
+            } else {
+                // Outside the roots is the other nappe of the double cone,
+                // which the caps cut away below.
+                vec![(-INFINITY, t0), (t1, INFINITY)]
+            }
+        }
+    };
+
+    let caps = slab(o_par, d_par, 0.0, height);
+    inside.iter().filter_map(|&part| overlap(part, caps)).next()
+}
+
+/// Which way is out from `axis` at a point `radial` away from it, and
+/// which way is around it, along with how far around it is from 0 to 1.
+pub fn around_axis(radial: &Vector3<f32>, axis: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>, f32) {
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let angle = dot(*radial, bitangent).atan2(dot(*radial, tangent));
+    let angle = if angle < 0.0 { angle + 2.0 * PI } else { angle };
+    let (sin, cos) = angle.sin_cos();
+    (tangent.mul_s(cos).add_v(&bitangent.mul_s(sin)),
+     bitangent.mul_s(cos).sub_v(&tangent.mul_s(sin)),
+     angle / (2.0 * PI))
+}
+
+/// A hit on a capped cone, picking the side or cap the point is closest
+/// to. u goes around the axis, v goes up the side and out across the caps.
+fn cone_hit(ray: &Ray3<f32>, distance: f32, base: &Point3<f32>, axis: &Vector3<f32>,
+            height: f32, base_radius: f32, top_radius: f32) -> Hit {
+    let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+    let slope = (top_radius - base_radius) / height;
+    let local = point.sub_p(base);
+    let h = dot(local, *axis);
+    let radial = local.sub_v(&axis.mul_s(h));
+    let distance_out = radial.length();
+    let (out, around, u) = around_axis(&radial, axis);
+    let dpdu = around.mul_s(2.0 * PI * distance_out);
+
+    let to_side = (distance_out - (base_radius + slope * h)).abs() / (1.0 + slope*slope).sqrt();
+    let to_bottom = h.abs();
+    let to_top = (h - height).abs();
+    if to_bottom < to_side && to_bottom <= to_top && base_radius > 0.0 {
+        Hit::new(ray, distance, -*axis, (u, distance_out / base_radius), dpdu,
+                 out.mul_s(base_radius))
+    } else if to_top < to_side && top_radius > 0.0 {
+        Hit::new(ray, distance, *axis, (u, distance_out / top_radius), dpdu,
+                 out.mul_s(top_radius))
+    } else {
+        let normal = out.sub_v(&axis.mul_s(slope)).normalize();
+        Hit::new(ray, distance, normal, (u, h / height),
+                 around.mul_s(2.0 * PI * (base_radius + slope * h)),
+                 axis.mul_s(height).add_v(&out.mul_s(top_radius - base_radius)))
+    }
+}
+
+/// Half the size of the box around a circle of the given radius facing
+/// along the unit vector `normal`.
+fn disk_extent(normal: &Vector3<f32>, radius: f32) -> Vector3<f32> {
+    Vector3::new(radius * (1.0 - normal.x*normal.x).max(0.0).sqrt(),
+                 radius * (1.0 - normal.y*normal.y).max(0.0).sqrt(),
+                 radius * (1.0 - normal.z*normal.z).max(0.0).sqrt())
+}
+
+pub fn cone_bounds(base: &Point3<f32>, axis: &Vector3<f32>, height: f32,
+               base_radius: f32, top_radius: f32) -> BoundingBox {
+    let top = base.add_v(&axis.mul_s(height));
+    let bottom_extent = disk_extent(axis, base_radius);
+    let top_extent = disk_extent(axis, top_radius);
+    BoundingBox::new(&base.add_v(&-bottom_extent), &base.add_v(&bottom_extent))
+        .union(&BoundingBox::new(&top.add_v(&-top_extent), &top.add_v(&top_extent)))
+}
+
+impl AxisAlignedBox {
+    pub fn new(min: (f32, f32, f32), max: (f32, f32, f32)) -> AxisAlignedBox {
+        let (x0, y0, z0) = min;
+        let (x1, y1, z1) = max;
+        AxisAlignedBox { min: Point3::new(x0.min(x1), y0.min(y1), z0.min(z1)),
+                         max: Point3::new(x0.max(x1), y0.max(y1), z0.max(z1)) }
+    }
+
+    fn span(&self, ray: &Ray3<f32>) -> Option<(f32, f32)> {
+        box_span(&ray.origin.to_vec(), &ray.direction, &self.min.to_vec(), &self.max.to_vec())
+    }
+}
+
+impl Intersectable for AxisAlignedBox {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        span_distance(self.span(ray), tmin, tmax).map(|distance| {
+            let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+            let (normal, uv, dpdu, dpdv) = box_surface(&point.to_vec(), &self.min.to_vec(),
+                                                       &self.max.to_vec());
+            Hit::new(ray, distance, normal, uv, dpdu, dpdv)
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(&self.min, &self.max)
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(self.span(ray))
+    }
+}
+
+impl OrientedBox {
+    /// A box of the given size, rotated by Euler angles in degrees.
+    pub fn new(origin: (f32, f32, f32), size: (f32, f32, f32), rotation: (f32, f32, f32)) -> OrientedBox {
+        let (x, y, z) = origin;
+        let (sx, sy, sz) = size;
+        let (rx, ry, rz) = rotation;
+        let angles = Vector3::new(rx, ry, rz);
+        OrientedBox { pos: Point3::new(x, y, z),
+                      half_size: Vector3::new(sx.abs(), sy.abs(), sz.abs()).mul_s(0.5),
+                      axes: (rotate_euler(&Vector3::unit_x(), &angles),
+                             rotate_euler(&Vector3::unit_y(), &angles),
+                             rotate_euler(&Vector3::unit_z(), &angles)) }
+    }
+
+    fn to_local(&self, vector: &Vector3<f32>) -> Vector3<f32> {
+        let (ref u, ref v, ref w) = self.axes;
+        Vector3::new(dot(*vector, *u), dot(*vector, *v), dot(*vector, *w))
+    }
+
+    fn to_world(&self, vector: &Vector3<f32>) -> Vector3<f32> {
+        let (u, v, w) = self.axes;
+        u.mul_s(vector.x).add_v(&v.mul_s(vector.y)).add_v(&w.mul_s(vector.z))
+    }
+
+    fn span(&self, ray: &Ray3<f32>) -> Option<(f32, f32)> {
+        let origin = self.to_local(&ray.origin.sub_p(&self.pos));
+        let direction = self.to_local(&ray.direction);
+        box_span(&origin, &direction, &(-self.half_size), &self.half_size)
+    }
+}
+
+impl Intersectable for OrientedBox {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        span_distance(self.span(ray), tmin, tmax).map(|distance| {
+            let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+            let local = self.to_local(&point.sub_p(&self.pos));
+            let (normal, uv, dpdu, dpdv) = box_surface(&local, &(-self.half_size), &self.half_size);
+            Hit::new(ray, distance, self.to_world(&normal), uv,
+                     self.to_world(&dpdu), self.to_world(&dpdv))
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let (u, v, w) = self.axes;
+        let h = self.half_size;
+        let extent = Vector3::new(u.x.abs()*h.x + v.x.abs()*h.y + w.x.abs()*h.z,
+                                  u.y.abs()*h.x + v.y.abs()*h.y + w.y.abs()*h.z,
+                                  u.z.abs()*h.x + v.z.abs()*h.y + w.z.abs()*h.z);
+        BoundingBox::new(&self.pos.add_v(&-extent), &self.pos.add_v(&extent))
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(self.span(ray))
+    }
+}
+
+impl Cylinder {
+    pub fn new(start: (f32, f32, f32), end: (f32, f32, f32), radius: f32) -> Cylinder {
+        let (x0, y0, z0) = start;
+        let (x1, y1, z1) = end;
+        let axis = Vector3::new(x1 - x0, y1 - y0, z1 - z0);
+        Cylinder { base: Point3::new(x0, y0, z0),
+                   axis: axis.normalize(),
+                   height: axis.length(),
+                   radius: radius }
+    }
+
+    fn span(&self, ray: &Ray3<f32>) -> Option<(f32, f32)> {
+        cone_span(ray, &self.base, &self.axis, self.height, self.radius, 0.0)
+    }
+}
+
+impl Intersectable for Cylinder {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        span_distance(self.span(ray), tmin, tmax).map(|distance| {
+            cone_hit(ray, distance, &self.base, &self.axis, self.height, self.radius, self.radius)
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        cone_bounds(&self.base, &self.axis, self.height, self.radius, self.radius)
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(self.span(ray))
+    }
+}
+
+impl Cone {
+    pub fn new(start: (f32, f32, f32), end: (f32, f32, f32),
+               base_radius: f32, top_radius: f32) -> Cone {
+        let (x0, y0, z0) = start;
+        let (x1, y1, z1) = end;
+        let axis = Vector3::new(x1 - x0, y1 - y0, z1 - z0);
+        Cone { base: Point3::new(x0, y0, z0),
+               axis: axis.normalize(),
+               height: axis.length(),
+               base_radius: base_radius,
+               top_radius: top_radius }
+    }
+
+    fn span(&self, ray: &Ray3<f32>) -> Option<(f32, f32)> {
+        let slope = (self.top_radius - self.base_radius) / self.height;
+        cone_span(ray, &self.base, &self.axis, self.height, self.base_radius, slope)
+    }
+}
+
+impl Intersectable for Cone {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        span_distance(self.span(ray), tmin, tmax).map(|distance| {
+            cone_hit(ray, distance, &self.base, &self.axis, self.height,
+                     self.base_radius, self.top_radius)
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        cone_bounds(&self.base, &self.axis, self.height, self.base_radius, self.top_radius)
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(self.span(ray))
+    }
+}
+
+impl Capsule {
+    pub fn new(start: (f32, f32, f32), end: (f32, f32, f32), radius: f32) -> Capsule {
+        let (x0, y0, z0) = start;
+        let (x1, y1, z1) = end;
+        Capsule { start: Point3::new(x0, y0, z0),
+                  end: Point3::new(x1, y1, z1),
+                  radius: radius }
+    }
+
+    fn span(&self, ray: &Ray3<f32>) -> Option<(f32, f32)> {
+        // The capsule is convex, so its span is the hull of the spans of
+        // the two end spheres and the cylinder between them.
+        let axis = self.end.sub_p(&self.start);
+        let height = axis.length();
+        let mut parts = vec![sphere_span(ray, &self.start, self.radius),
+                             sphere_span(ray, &self.end, self.radius)];
+        if height > 0.0 {
+            parts.push(cone_span(ray, &self.start, &axis.div_s(height), height, self.radius, 0.0));
+        }
+        parts.iter().filter_map(|&part| part).fold(None, |hull, (enter, exit)| {
+            match hull {
+                None => Some((enter, exit)),
+                Some((e0, e1)) => Some((e0.min(enter), e1.max(exit)))
+            }
+        })
+    }
+}
+
+impl Intersectable for Capsule {
+    /// u goes around the axis, and v from the bottom of the start cap to
+    /// the top of the end cap.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        span_distance(self.span(ray), tmin, tmax).map(|distance| {
+            let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+            let axis = self.end.sub_p(&self.start);
+            let along = dot(point.sub_p(&self.start), axis) / axis.length2().max(1e-12);
+            let closest = self.start.add_v(&axis.mul_s(along.max(0.0).min(1.0)));
+            let normal = point.sub_p(&closest).normalize();
+
+            let length = axis.length();
+            let direction = if length > 0.0 { axis.div_s(length) } else { Vector3::unit_z() };
+            let local = point.sub_p(&self.start);
+            let h = dot(local, direction);
+            let radial = local.sub_v(&direction.mul_s(h));
+            let (_, around, u) = around_axis(&radial, &direction);
+            let total = length + 2.0 * self.radius;
+            let up = direction.mul_s(total);
+            Hit::new(ray, distance, normal, (u, (h + self.radius) / total),
+                     around.mul_s(2.0 * PI * radial.length()),
+                     up.sub_v(&normal.mul_s(dot(up, normal))))
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        BoundingBox::new(&self.start.add_v(&-r), &self.start.add_v(&r))
+            .union(&BoundingBox::new(&self.end.add_v(&-r), &self.end.add_v(&r)))
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        convex_spans(self.span(ray))
+    }
+}
+
+impl Torus {
+    pub fn new(origin: (f32, f32, f32), axis: (f32, f32, f32),
+               major_radius: f32, minor_radius: f32) -> Torus {
+        let (x, y, z) = origin;
+        let (ax, ay, az) = axis;
+        let axis = Vector3::new(ax, ay, az).normalize();
+        let (tangent, bitangent) = orthonormal_basis(&axis);
+        Torus { pos: Point3::new(x, y, z),
+                axis: axis,
+                tangent: tangent,
+                bitangent: bitangent,
+                major_radius: major_radius,
+                minor_radius: minor_radius }
+    }
+
+    fn to_local(&self, vector: &Vector3<f32>) -> (f64, f64, f64) {
+        (dot(*vector, self.tangent) as f64,
+         dot(*vector, self.bitangent) as f64,
+         dot(*vector, self.axis) as f64)
+    }
+
+    /// Distances along the ray's line where it crosses the torus, ascending.
+    fn crossings(&self, ray: &Ray3<f32>) -> Vec<f32> {
+        // Only solve over the part of the line inside the bounding sphere,
+        // and do it in coordinates where the bounding sphere has radius 1
+        // starting from where the line enters it, which keeps the
+        // coefficients of the quartic small and well conditioned.
+        let outer = self.major_radius + self.minor_radius;
+        let (enter, exit) = match sphere_span(ray, &self.pos, outer) {
+            Some(span) => span,
+            None => return Vec::new()
+        };
+        let start = ray.origin.add_v(&ray.direction.mul_s(enter));
+        let scale = outer as f64;
+        let (ox, oy, oz) = self.to_local(&start.sub_p(&self.pos));
+        let (ox, oy, oz) = (ox / scale, oy / scale, oz / scale);
+        let (dx, dy, dz) = self.to_local(&ray.direction);
+        let big = self.major_radius as f64 / scale;
+        let small = self.minor_radius as f64 / scale;
+
+        let d_dot_d = dx*dx + dy*dy + dz*dz;
+        let o_dot_d = ox*dx + oy*dy + oz*dz;
+        let e = ox*ox + oy*oy + oz*oz - big*big - small*small;
+        let coefficients = [e*e + 4.0*big*big*(oz*oz - small*small),
+                            4.0*o_dot_d*e + 8.0*big*big*oz*dz,
+                            2.0*d_dot_d*e + 4.0*o_dot_d*o_dot_d + 4.0*big*big*dz*dz,
+                            4.0*d_dot_d*o_dot_d,
+                            d_dot_d*d_dot_d];
+        let length = (exit - enter) as f64 / scale;
+        roots_in_range(coefficients.as_slice(), 0.0, length).iter()
+            .map(|&t| enter + (t * scale) as f32)
+            .collect()
+    }
+}
+
+impl Intersectable for Torus {
+    /// u goes around the ring and v around the tube.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let distance = match self.crossings(ray).into_iter().find(|&distance| distance >= tmin) {
+            Some(distance) if distance <= tmax => distance,
+            _ => return None
+        };
+        let point = ray.origin.add_v(&ray.direction.mul_s(distance));
+        let local = point.sub_p(&self.pos);
+        // The normal points away from the closest point on the ring
+        // running through the middle of the tube.
+        let height = dot(local, self.axis);
+        let in_plane = local.sub_v(&self.axis.mul_s(height));
+        let (out, around, u) = around_axis(&in_plane, &self.axis);
+        let normal = local.sub_v(&out.mul_s(self.major_radius)).normalize();
+        let tube = dot(local, out) - self.major_radius;
+        let v = height.atan2(tube);
+        let v = if v < 0.0 { v + 2.0 * PI } else { v };
+        Some(Hit::new(ray, distance, normal, (u, v / (2.0 * PI)),
+                      around.mul_s(2.0 * PI * in_plane.length()),
+                      normal.cross(&around).mul_s(2.0 * PI * self.minor_radius)))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let ring = disk_extent(&self.axis, self.major_radius);
+        let r = ring.add_v(&Vector3::new(self.minor_radius, self.minor_radius, self.minor_radius));
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        // The crossings cover the whole line through the torus, and go
+        // in and out in turn.
+        Some(self.crossings(ray).as_slice().chunks(2)
+                 .filter(|pair| pair.len() == 2)
+                 .map(|pair| (pair[0], pair[1]))
+                 .collect())
     }
 }
@@ -0,0 +1,444 @@
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Point3, Vector3, Ray3};
+use cgmath::dot;
+use scene::{Intersectable, Hit, BoundingBox};
+use scene::util::{orthonormal_basis, gamma};
+
+/// How close sphere tracing has to get to the surface to call it a hit,
+/// near the origin. Further out it's relative to how far out the point
+/// is, since positions there are only so precise.
+const EPSILON: f32 = 0.0001;
+const MAX_STEPS: uint = 512;
+
+/// A signed distance field: for any point, a distance it's safe to move
+/// without crossing the surface. Exact fields give the distance to the
+/// surface itself, but anything that never overestimates it works.
+pub trait DistanceField {
+    /// Negative inside the surface.
+    fn distance(&self, point: &Point3<f32>) -> f32;
+    fn bounds(&self) -> BoundingBox;
+}
+
+/// Renders a distance field by sphere tracing: stepping along the ray by
+/// the distance to the surface, which can never step through it.
+/// See Hart, "Sphere tracing: a geometric method for the antialiased ray
+/// tracing of implicit surfaces" (1996)
+pub struct SphereTraced {
+    field: Box<DistanceField+Send+Sync+'static>,
+    bounds: BoundingBox
+}
+
+pub struct SphereField {
+    pos: Point3<f32>,
+    radius: f32
+}
+
+/// A box with its edges rounded off by `radius`, still `half_size`
+/// across on each side of `pos`.
+pub struct RoundedBoxField {
+    pos: Point3<f32>,
+    half_size: Vector3<f32>,
+    radius: f32
+}
+
+pub struct TorusField {
+    pos: Point3<f32>,
+    axis: Vector3<f32>,
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    major_radius: f32,
+    minor_radius: f32
+}
+
+pub struct CapsuleField {
+    start: Point3<f32>,
+    end: Point3<f32>,
+    radius: f32
+}
+
+/// The union of two fields, blended together over `smoothness`.
+pub struct SmoothUnion {
+    a: Box<DistanceField+Send+Sync+'static>,
+    b: Box<DistanceField+Send+Sync+'static>,
+    smoothness: f32
+}
+
+/// The first field with the second carved out of it, blended over
+/// `smoothness`.
+pub struct SmoothSubtraction {
+    a: Box<DistanceField+Send+Sync+'static>,
+    b: Box<DistanceField+Send+Sync+'static>,
+    smoothness: f32
+}
+
+pub struct SmoothIntersection {
+    a: Box<DistanceField+Send+Sync+'static>,
+    b: Box<DistanceField+Send+Sync+'static>,
+    smoothness: f32
+}
+
+/// Copies of a field repeated every `period` along each axis, for the axes
+/// where it isn't zero. The field should fit within half a period of the
+/// origin. With a `limit`, there are only that many copies on each side
+/// of the original, otherwise they go on forever.
+pub struct Repetition {
+    field: Box<DistanceField+Send+Sync+'static>,
+    period: Vector3<f32>,
+    limit: Option<Vector3<f32>>
+}
+
+/// The power 8 Mandelbulb fractal, or another `power`, with radius about
+/// `scale` around `pos`.
+pub struct Mandelbulb {
+    pos: Point3<f32>,
+    scale: f32,
+    power: f32,
+    iterations: uint
+}
+
+/// A smooth minimum that blends `a` and `b` where they're within `k` of
+/// each other. See Quilez, "Smooth minimum" (2013)
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+fn smooth_max(a: f32, b: f32, k: f32) -> f32 {
+    -smooth_min(-a, -b, k)
+}
+
+/// How close to the surface counts as on it at `point`, `t` along the ray.
+/// It grows with both, so steps of at least this much always move the ray
+/// on, however far out it is.
+fn surface_tolerance(point: &Point3<f32>, t: f32) -> f32 {
+    let scale = t.abs().max(point.x.abs()).max(point.y.abs()).max(point.z.abs());
+    EPSILON.max(scale * gamma(16.0))
+}
+
+impl SphereTraced {
+    pub fn new(field: Box<DistanceField+Send+Sync+'static>) -> SphereTraced {
+        let bounds = field.bounds();
+        SphereTraced { field: field,
+                       bounds: bounds }
+    }
+
+    /// The field's gradient by central differences, which is the surface
+    /// normal on the surface.
+    fn normal(&self, point: &Point3<f32>, tolerance: f32) -> Vector3<f32> {
+        let h = tolerance * 0.5;
+        let difference = |offset: Vector3<f32>| {
+            self.field.distance(&point.add_v(&offset)) - self.field.distance(&point.add_v(&-offset))
+        };
+        Vector3::new(difference(Vector3::new(h, 0.0, 0.0)),
+                     difference(Vector3::new(0.0, h, 0.0)),
+                     difference(Vector3::new(0.0, 0.0, h))).normalize()
+    }
+}
+
+impl Intersectable for SphereTraced {
+    /// Distance fields have no natural surface coordinates, so u and v are
+    /// always 0 and the tangents are just perpendicular to the normal.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let (start, end) = if self.bounds.is_finite() {
+            let inverse = Vector3::new(1.0 / ray.direction.x,
+                                       1.0 / ray.direction.y,
+                                       1.0 / ray.direction.z);
+            match self.bounds.hit(&ray.origin, &inverse, tmax) {
+                Some(span) => span,
+                None => return None
+            }
+        } else {
+            // Fields that go on forever are marched until they're hit, the
+            // ray runs out or the steps do.
+            (0.0, tmax)
+        };
+        let mut t = start.max(tmin);
+        // Rays starting on the surface, like the ones leaving it, have to
+        // get out of the shell they'd count as hitting it in first.
+        let mut leaving = start <= tmin;
+
+        for _ in range(0, MAX_STEPS) {
+            // Inside the surface the distance is negative, and rays step
+            // towards where they leave it instead.
+            let point = ray.origin.add_v(&ray.direction.mul_s(t));
+            let distance = self.field.distance(&point).abs();
+            let tolerance = surface_tolerance(&point, t);
+            if distance < tolerance && !leaving {
+                let normal = self.normal(&point, tolerance);
+                let (tangent, bitangent) = orthonormal_basis(&normal);
+                let hit = Hit::new(ray, t, normal, (0.0, 0.0), tangent, bitangent);
+                // The hit is anywhere within the tolerance of the surface
+                let error = hit.error.add_v(&Vector3::new(tolerance, tolerance, tolerance));
+                return Some(hit.with_point(point, error));
+            }
+            if distance >= tolerance {
+                leaving = false;
+            }
+            // Never less than the tolerance, which is always enough to
+            // move t on
+            t += distance.max(tolerance);
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
+
+impl SphereField {
+    pub fn new(origin: (f32, f32, f32), radius: f32) -> SphereField {
+        let (x, y, z) = origin;
+        SphereField { pos: Point3::new(x, y, z),
+                      radius: radius }
+    }
+}
+
+impl DistanceField for SphereField {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        point.sub_p(&self.pos).length() - self.radius
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+}
+
+impl RoundedBoxField {
+    pub fn new(origin: (f32, f32, f32), size: (f32, f32, f32), radius: f32) -> RoundedBoxField {
+        let (x, y, z) = origin;
+        let (sx, sy, sz) = size;
+        RoundedBoxField { pos: Point3::new(x, y, z),
+                          half_size: Vector3::new(sx * 0.5, sy * 0.5, sz * 0.5),
+                          radius: radius }
+    }
+}
+
+impl DistanceField for RoundedBoxField {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        let p = point.sub_p(&self.pos);
+        let r = self.radius;
+        let q = Vector3::new(p.x.abs() - self.half_size.x + r,
+                             p.y.abs() - self.half_size.y + r,
+                             p.z.abs() - self.half_size.z + r);
+        let outside = Vector3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - r
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(&self.pos.add_v(&-self.half_size), &self.pos.add_v(&self.half_size))
+    }
+}
+
+impl TorusField {
+    pub fn new(origin: (f32, f32, f32), axis: (f32, f32, f32),
+               major_radius: f32, minor_radius: f32) -> TorusField {
+        let (x, y, z) = origin;
+        let (ax, ay, az) = axis;
+        let axis = Vector3::new(ax, ay, az).normalize();
+        let (tangent, bitangent) = orthonormal_basis(&axis);
+        TorusField { pos: Point3::new(x, y, z),
+                     axis: axis,
+                     tangent: tangent,
+                     bitangent: bitangent,
+                     major_radius: major_radius,
+                     minor_radius: minor_radius }
+    }
+}
+
+impl DistanceField for TorusField {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        let p = point.sub_p(&self.pos);
+        let x = dot(p, self.tangent);
+        let y = dot(p, self.bitangent);
+        let ring = (x*x + y*y).sqrt() - self.major_radius;
+        let height = dot(p, self.axis);
+        (ring*ring + height*height).sqrt() - self.minor_radius
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = self.major_radius + self.minor_radius;
+        let r = Vector3::new(r, r, r);
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+}
+
+impl CapsuleField {
+    pub fn new(start: (f32, f32, f32), end: (f32, f32, f32), radius: f32) -> CapsuleField {
+        let (x0, y0, z0) = start;
+        let (x1, y1, z1) = end;
+        CapsuleField { start: Point3::new(x0, y0, z0),
+                       end: Point3::new(x1, y1, z1),
+                       radius: radius }
+    }
+}
+
+impl DistanceField for CapsuleField {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        let axis = self.end.sub_p(&self.start);
+        let p = point.sub_p(&self.start);
+        let along = (dot(p, axis) / axis.length2().max(1e-12)).max(0.0).min(1.0);
+        p.sub_v(&axis.mul_s(along)).length() - self.radius
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        BoundingBox::new(&self.start.add_v(&-r), &self.start.add_v(&r))
+            .union(&BoundingBox::new(&self.end.add_v(&-r), &self.end.add_v(&r)))
+    }
+}
+
+impl SmoothUnion {
+    pub fn new(a: Box<DistanceField+Send+Sync+'static>, b: Box<DistanceField+Send+Sync+'static>,
+               smoothness: f32) -> SmoothUnion {
+        SmoothUnion { a: a, b: b, smoothness: smoothness }
+    }
+}
+
+impl DistanceField for SmoothUnion {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        smooth_min(self.a.distance(point), self.b.distance(point), self.smoothness)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        // Blending can bulge out by up to a quarter of the smoothness
+        self.a.bounds().union(&self.b.bounds()).expand(self.smoothness * 0.25)
+    }
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Box<DistanceField+Send+Sync+'static>, b: Box<DistanceField+Send+Sync+'static>,
+               smoothness: f32) -> SmoothSubtraction {
+        SmoothSubtraction { a: a, b: b, smoothness: smoothness }
+    }
+}
+
+impl DistanceField for SmoothSubtraction {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        smooth_max(self.a.distance(point), -self.b.distance(point), self.smoothness)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.a.bounds()
+    }
+}
+
+impl SmoothIntersection {
+    pub fn new(a: Box<DistanceField+Send+Sync+'static>, b: Box<DistanceField+Send+Sync+'static>,
+               smoothness: f32) -> SmoothIntersection {
+        SmoothIntersection { a: a, b: b, smoothness: smoothness }
+    }
+}
+
+impl DistanceField for SmoothIntersection {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        smooth_max(self.a.distance(point), self.b.distance(point), self.smoothness)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.a.bounds().intersection(&self.b.bounds())
+    }
+}
+
+impl Repetition {
+    pub fn new(field: Box<DistanceField+Send+Sync+'static>, period: (f32, f32, f32),
+               limit: Option<(f32, f32, f32)>) -> Repetition {
+        let (px, py, pz) = period;
+        Repetition { field: field,
+                     period: Vector3::new(px, py, pz),
+                     limit: limit.map(|(lx, ly, lz)| Vector3::new(lx, ly, lz)) }
+    }
+
+    /// Moves a coordinate into the copy of the field nearest to it.
+    fn fold(&self, x: f32, period: f32, limit: Option<f32>) -> f32 {
+        if period == 0.0 {
+            return x;
+        }
+        let copy = (x / period).round();
+        let copy = match limit {
+            Some(limit) => copy.max(-limit).min(limit),
+            None => copy
+        };
+        x - period * copy
+    }
+}
+
+impl DistanceField for Repetition {
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        let (lx, ly, lz) = match self.limit {
+            Some(limit) => (Some(limit.x), Some(limit.y), Some(limit.z)),
+            None => (None, None, None)
+        };
+        let folded = Point3::new(self.fold(point.x, self.period.x, lx),
+                                 self.fold(point.y, self.period.y, ly),
+                                 self.fold(point.z, self.period.z, lz));
+        self.field.distance(&folded)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        match self.limit {
+            Some(limit) => {
+                let reach = Vector3::new((self.period.x * limit.x).abs(),
+                                         (self.period.y * limit.y).abs(),
+                                         (self.period.z * limit.z).abs());
+                let bounds = self.field.bounds();
+                BoundingBox::new(&bounds.min.add_v(&-reach), &bounds.max.add_v(&reach))
+            },
+            None => BoundingBox::infinite()
+        }
+    }
+}
+
+impl Mandelbulb {
+    pub fn new(origin: (f32, f32, f32), scale: f32, power: f32, iterations: uint) -> Mandelbulb {
+        let (x, y, z) = origin;
+        Mandelbulb { pos: Point3::new(x, y, z),
+                     scale: scale,
+                     power: power,
+                     iterations: iterations }
+    }
+}
+
+impl DistanceField for Mandelbulb {
+    /// The usual distance estimate from the running derivative of the
+    /// iteration, see Hart, Sandin and Kauffman, "Ray tracing deterministic
+    /// 3-D fractals" (1989)
+    fn distance(&self, point: &Point3<f32>) -> f32 {
+        let c = point.sub_p(&self.pos).div_s(self.scale);
+        let mut z = c;
+        let mut dr = 1.0f32;
+        let mut r = z.length();
+        for _ in range(0, self.iterations) {
+            if r > 2.0 || r == 0.0 {
+                break;
+            }
+            // Raise z to the power in spherical coordinates
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            let zr = r.powf(self.power);
+            dr = self.power * r.powf(self.power - 1.0) * dr + 1.0;
+            z = Vector3::new(theta.sin() * phi.cos(),
+                             theta.sin() * phi.sin(),
+                             theta.cos()).mul_s(zr).add_v(&c);
+            r = z.length();
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = 1.2 * self.scale;
+        let r = Vector3::new(r, r, r);
+        BoundingBox::new(&self.pos.add_v(&-r), &self.pos.add_v(&r))
+    }
+}
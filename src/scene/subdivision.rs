@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::f32::INFINITY;
+use std::f32::consts::PI;
+use cgmath::{EuclideanVector, Point, Vector};
+use cgmath::{Vector3, Point3};
+use scene::{TriangleMesh, BvhParams};
+
+/// Which rules refine the cage.
+#[deriving(Clone, Show, PartialEq)]
+pub enum SubdivisionScheme {
+    /// Catmull-Clark, for quads. Any polygons work, and are all quads
+    /// after the first level.
+    CatmullClarkScheme,
+    /// Loop, for cages made only of triangles
+    LoopScheme
+}
+
+/// A polygon mesh to be refined into a smooth surface. Edges can be given
+/// a crease sharpness, which keeps them sharp for that many levels of
+/// subdivision, so fractional values give softened creases. Edges on the
+/// boundary of the cage are always sharp.
+/// See DeRose, Kass and Truong, "Subdivision Surfaces in Character
+/// Animation" (1998)
+#[deriving(Clone)]
+pub struct ControlCage {
+    vertices: Vec<Point3<f32>>,
+    faces: Vec<Vec<uint>>,
+    creases: Vec<(uint, uint, f32)>
+}
+
+struct Edge {
+    a: uint,
+    b: uint,
+    faces: Vec<uint>,
+    sharpness: f32
+}
+
+/// How the faces, edges and vertices of a cage connect to each other.
+struct Topology {
+    edges: Vec<Edge>,
+    /// Edge i of a face joins its corners i and i + 1
+    face_edges: Vec<Vec<uint>>,
+    vertex_edges: Vec<Vec<uint>>,
+    vertex_faces: Vec<Vec<uint>>
+}
+
+fn edge_key(a: uint, b: uint) -> (uint, uint) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+impl Topology {
+    fn new(cage: &ControlCage) -> Topology {
+        let mut lookup = HashMap::new();
+        let mut topology = Topology { edges: Vec::new(),
+                                      face_edges: Vec::with_capacity(cage.faces.len()),
+                                      vertex_edges: Vec::from_elem(cage.vertices.len(), Vec::new()),
+                                      vertex_faces: Vec::from_elem(cage.vertices.len(), Vec::new()) };
+        for (f, face) in cage.faces.iter().enumerate() {
+            let mut edges = Vec::with_capacity(face.len());
+            for i in range(0, face.len()) {
+                let (a, b) = (face[i], face[(i + 1) % face.len()]);
+                let key = edge_key(a, b);
+                let edge = match lookup.find(&key) {
+                    Some(&edge) => edge,
+                    None => {
+                        lookup.insert(key, topology.edges.len());
+                        topology.edges.push(Edge { a: a, b: b, faces: Vec::new(), sharpness: 0.0 });
+                        topology.vertex_edges[a].push(topology.edges.len() - 1);
+                        topology.vertex_edges[b].push(topology.edges.len() - 1);
+                        topology.edges.len() - 1
+                    }
+                };
+                topology.edges[edge].faces.push(f);
+                topology.vertex_faces[face[i]].push(f);
+                edges.push(edge);
+            }
+            topology.face_edges.push(edges);
+        }
+        for &(a, b, sharpness) in cage.creases.iter() {
+            match lookup.find(&edge_key(a, b)) {
+                Some(&edge) => topology.edges[edge].sharpness = sharpness,
+                None => fail!("Crease between vertices {} and {} isn't an edge of the cage", a, b)
+            }
+        }
+        // Boundaries, and edges with more than two faces that have no
+        // sensible smooth rule, stay sharp.
+        for edge in topology.edges.iter_mut() {
+            if edge.faces.len() != 2 {
+                edge.sharpness = INFINITY;
+            }
+        }
+        topology
+    }
+
+    fn other_end(&self, edge: uint, vertex: uint) -> uint {
+        let edge = &self.edges[edge];
+        if edge.a == vertex { edge.b } else { edge.a }
+    }
+
+    /// The sharp edges at a vertex, and their average sharpness.
+    fn sharp_edges(&self, vertex: uint) -> (Vec<uint>, f32) {
+        let sharp: Vec<uint> = self.vertex_edges[vertex].iter()
+                                                        .map(|&e| e)
+                                                        .filter(|&e| self.edges[e].sharpness > 0.0)
+                                                        .collect();
+        let total = sharp.iter().fold(0.0, |sum, &e| sum + self.edges[e].sharpness);
+        let average = if sharp.is_empty() { 0.0 } else { total / sharp.len() as f32 };
+        (sharp, average)
+    }
+}
+
+/// The root of `item`'s set in a union-find forest.
+fn find_root(parents: &[uint], item: uint) -> uint {
+    let mut root = item;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    root
+}
+
+/// Moves from the smooth rule to the sharp one as sharpness goes from 0 to 1.
+fn blend(smooth: Vector3<f32>, sharp: Vector3<f32>, sharpness: f32) -> Vector3<f32> {
+    if sharpness >= 1.0 {
+        sharp
+    } else if sharpness <= 0.0 {
+        smooth
+    } else {
+        smooth.mul_s(1.0 - sharpness).add_v(&sharp.mul_s(sharpness))
+    }
+}
+
+impl ControlCage {
+    pub fn new(vertices: Vec<Point3<f32>>, faces: Vec<Vec<uint>>) -> ControlCage {
+        for face in faces.iter() {
+            if face.len() < 3 {
+                fail!("Subdivision cage face has {} vertices, it needs at least 3", face.len());
+            }
+            for &index in face.iter() {
+                if index >= vertices.len() {
+                    fail!("Subdivision cage face indexes past the {} vertices", vertices.len());
+                }
+            }
+        }
+        ControlCage { vertices: vertices,
+                      faces: faces,
+                      creases: Vec::new() }
+    }
+
+    /// Adds creases, each the two vertices at the ends of an edge and how
+    /// many levels it stays sharp for.
+    pub fn with_creases(mut self, creases: Vec<(uint, uint, f32)>) -> ControlCage {
+        self.creases = creases;
+        self
+    }
+
+    pub fn is_triangles(&self) -> bool {
+        self.faces.iter().all(|face| face.len() == 3)
+    }
+
+    pub fn subdivide(&self, scheme: SubdivisionScheme, levels: uint) -> ControlCage {
+        if scheme == LoopScheme && !self.is_triangles() {
+            fail!("Loop subdivision only works on cages made of triangles");
+        }
+        let mut cage = self.clone();
+        for _ in range(0, levels) {
+            cage = match scheme {
+                CatmullClarkScheme => cage.catmull_clark(),
+                LoopScheme => cage.loop_subdivide()
+            };
+        }
+        cage
+    }
+
+    /// The creases of the next level. Each crease is split in two at its
+    /// edge point, with one level less of sharpness.
+    fn child_creases(&self, topology: &Topology) -> Vec<(uint, uint, f32)> {
+        let mut creases = Vec::new();
+        let mut lookup = HashMap::new();
+        for (e, edge) in topology.edges.iter().enumerate() {
+            lookup.insert(edge_key(edge.a, edge.b), e);
+        }
+        for &(a, b, sharpness) in self.creases.iter() {
+            if sharpness <= 1.0 {
+                continue;
+            }
+            let edge_point = self.vertices.len() + *lookup.find(&edge_key(a, b)).unwrap();
+            creases.push((a, edge_point, sharpness - 1.0));
+            creases.push((edge_point, b, sharpness - 1.0));
+        }
+        creases
+    }
+
+    /// One level of Catmull-Clark. The new vertices are the moved old
+    /// vertices, then a point for each edge, then one for each face, and
+    /// every face becomes a quad for each of its corners.
+    fn catmull_clark(&self) -> ControlCage {
+        let topology = Topology::new(self);
+        let v: Vec<Vector3<f32>> = self.vertices.iter().map(|p| p.to_vec()).collect();
+
+        let face_points: Vec<Vector3<f32>> = self.faces.iter().map(|face| {
+            face.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &i| sum.add_v(&v[i]))
+                       .div_s(face.len() as f32)
+        }).collect();
+
+        let edge_points: Vec<Vector3<f32>> = topology.edges.iter().map(|edge| {
+            let midpoint = v[edge.a].add_v(&v[edge.b]).mul_s(0.5);
+            if edge.faces.len() != 2 {
+                return midpoint;
+            }
+            let smooth = v[edge.a].add_v(&v[edge.b])
+                                  .add_v(&face_points[edge.faces[0]])
+                                  .add_v(&face_points[edge.faces[1]])
+                                  .mul_s(0.25);
+            blend(smooth, midpoint, edge.sharpness)
+        }).collect();
+
+        let mut vertices = Vec::with_capacity(v.len() + edge_points.len() + face_points.len());
+        for i in range(0, v.len()) {
+            let edges = &topology.vertex_edges[i];
+            let faces = &topology.vertex_faces[i];
+            if edges.is_empty() {
+                vertices.push(Point3::from_vec(&v[i]));
+                continue;
+            }
+            let n = edges.len() as f32;
+            let smooth = if faces.len() == edges.len() {
+                let q = faces.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &f| sum.add_v(&face_points[f]))
+                             .div_s(faces.len() as f32);
+                let r = edges.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &e| {
+                    sum.add_v(&v[i].add_v(&v[topology.other_end(e, i)]).mul_s(0.5))
+                }).div_s(n);
+                q.add_v(&r.mul_s(2.0)).add_v(&v[i].mul_s(n - 3.0)).div_s(n)
+            } else {
+                // Only on vertices with sharp edges, which use the rules below
+                v[i]
+            };
+            let (sharp, sharpness) = topology.sharp_edges(i);
+            let point = match sharp.len() {
+                0 | 1 => smooth,
+                2 => {
+                    let crease = v[i].mul_s(6.0)
+                                     .add_v(&v[topology.other_end(sharp[0], i)])
+                                     .add_v(&v[topology.other_end(sharp[1], i)])
+                                     .div_s(8.0);
+                    blend(smooth, crease, sharpness)
+                },
+                _ => blend(smooth, v[i], sharpness)
+            };
+            vertices.push(Point3::from_vec(&point));
+        }
+        vertices.extend(edge_points.iter().map(|p| Point3::from_vec(p)));
+        vertices.extend(face_points.iter().map(|p| Point3::from_vec(p)));
+
+        let edge_base = v.len();
+        let face_base = v.len() + edge_points.len();
+        let mut faces = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let edges = &topology.face_edges[f];
+            for i in range(0, face.len()) {
+                let previous = (i + face.len() - 1) % face.len();
+                faces.push(vec![face[i],
+                                edge_base + edges[i],
+                                face_base + f,
+                                edge_base + edges[previous]]);
+            }
+        }
+
+        ControlCage { vertices: vertices,
+                      faces: faces,
+                      creases: self.child_creases(&topology) }
+    }
+
+    /// One level of Loop subdivision. The new vertices are the moved old
+    /// vertices then a point for each edge, and every triangle becomes four.
+    /// See Loop, "Smooth Subdivision Surfaces Based on Triangles" (1987)
+    fn loop_subdivide(&self) -> ControlCage {
+        let topology = Topology::new(self);
+        let v: Vec<Vector3<f32>> = self.vertices.iter().map(|p| p.to_vec()).collect();
+
+        let edge_points: Vec<Vector3<f32>> = topology.edges.iter().map(|edge| {
+            let midpoint = v[edge.a].add_v(&v[edge.b]).mul_s(0.5);
+            if edge.faces.len() != 2 {
+                return midpoint;
+            }
+            let smooth = v[edge.a].add_v(&v[edge.b]).mul_s(3.0 / 8.0)
+                                  .add_v(&v[self.opposite(edge.faces[0], edge)].mul_s(1.0 / 8.0))
+                                  .add_v(&v[self.opposite(edge.faces[1], edge)].mul_s(1.0 / 8.0));
+            blend(smooth, midpoint, edge.sharpness)
+        }).collect();
+
+        let mut vertices = Vec::with_capacity(v.len() + edge_points.len());
+        for i in range(0, v.len()) {
+            let edges = &topology.vertex_edges[i];
+            if edges.is_empty() {
+                vertices.push(Point3::from_vec(&v[i]));
+                continue;
+            }
+            let n = edges.len() as f32;
+            let spread = 3.0 / 8.0 + (2.0 * PI / n).cos() / 4.0;
+            let beta = (5.0 / 8.0 - spread * spread) / n;
+            let neighbours = edges.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &e| {
+                sum.add_v(&v[topology.other_end(e, i)])
+            });
+            let smooth = v[i].mul_s(1.0 - n * beta).add_v(&neighbours.mul_s(beta));
+            let (sharp, sharpness) = topology.sharp_edges(i);
+            let point = match sharp.len() {
+                0 | 1 => smooth,
+                2 => {
+                    let crease = v[i].mul_s(3.0 / 4.0)
+                                     .add_v(&v[topology.other_end(sharp[0], i)].mul_s(1.0 / 8.0))
+                                     .add_v(&v[topology.other_end(sharp[1], i)].mul_s(1.0 / 8.0));
+                    blend(smooth, crease, sharpness)
+                },
+                _ => blend(smooth, v[i], sharpness)
+            };
+            vertices.push(Point3::from_vec(&point));
+        }
+        vertices.extend(edge_points.iter().map(|p| Point3::from_vec(p)));
+
+        let edge_base = v.len();
+        let mut faces = Vec::with_capacity(4 * self.faces.len());
+        for (f, face) in self.faces.iter().enumerate() {
+            let edges = &topology.face_edges[f];
+            let (ab, bc, ca) = (edge_base + edges[0], edge_base + edges[1], edge_base + edges[2]);
+            faces.push(vec![face[0], ab, ca]);
+            faces.push(vec![face[1], bc, ab]);
+            faces.push(vec![face[2], ca, bc]);
+            faces.push(vec![ab, bc, ca]);
+        }
+
+        ControlCage { vertices: vertices,
+                      faces: faces,
+                      creases: self.child_creases(&topology) }
+    }
+
+    /// The corner of a triangle that isn't on the given edge.
+    fn opposite(&self, face: uint, edge: &Edge) -> uint {
+        *self.faces[face].iter().find(|&&i| i != edge.a && i != edge.b).unwrap()
+    }
+
+    /// Splits the faces into triangles, with normals averaged from the
+    /// faces around each vertex. Vertices on edges that are still sharp are
+    /// split, so the faces on each side of a crease get their own normal
+    /// and it shades as a hard edge.
+    pub fn to_mesh(&self, bvh_params: &BvhParams) -> TriangleMesh {
+        let topology = Topology::new(self);
+        let mut face_normals = Vec::with_capacity(self.faces.len());
+        for face in self.faces.iter() {
+            // Unnormalized, so bigger faces count for more
+            let mut normal = Vector3::new(0.0f32, 0.0, 0.0);
+            let a = self.vertices[face[0]];
+            for i in range(1, face.len() - 1) {
+                let (b, c) = (self.vertices[face[i]], self.vertices[face[i + 1]]);
+                normal = normal.add_v(&b.sub_p(&a).cross(&c.sub_p(&a)));
+            }
+            face_normals.push(normal);
+        }
+
+        let mut vertices = self.vertices.clone();
+        let mut normals = Vec::from_elem(self.vertices.len(), Vector3::new(0.0f32, 0.0, 0.0));
+        // The mesh vertex at each corner of each face
+        let mut corners = self.faces.clone();
+        for i in range(0, self.vertices.len()) {
+            // The faces around the vertex that are joined by smooth edges
+            // make up a sector sharing one normal.
+            let faces = &topology.vertex_faces[i];
+            let mut parents: Vec<uint> = range(0, faces.len()).collect();
+            for &e in topology.vertex_edges[i].iter() {
+                let edge = &topology.edges[e];
+                if edge.sharpness > 0.0 {
+                    continue;
+                }
+                // Smooth edges always have two faces
+                let a = faces.iter().position(|&f| f == edge.faces[0]).unwrap();
+                let b = faces.iter().position(|&f| f == edge.faces[1]).unwrap();
+                let (a, b) = (find_root(parents.as_slice(), a), find_root(parents.as_slice(), b));
+                parents[a] = b;
+            }
+
+            // The first sector keeps the original vertex, the rest get copies
+            let mut sector_vertices = HashMap::new();
+            for (k, &f) in faces.iter().enumerate() {
+                let sector = find_root(parents.as_slice(), k);
+                let vertex = match sector_vertices.find(&sector) {
+                    Some(&vertex) => vertex,
+                    None => {
+                        let vertex = if sector_vertices.is_empty() {
+                            i
+                        } else {
+                            vertices.push(self.vertices[i]);
+                            normals.push(Vector3::new(0.0, 0.0, 0.0));
+                            vertices.len() - 1
+                        };
+                        sector_vertices.insert(sector, vertex);
+                        vertex
+                    }
+                };
+                normals[vertex] = normals[vertex].add_v(&face_normals[f]);
+                for (corner, &index) in corners[f].iter_mut().zip(self.faces[f].iter()) {
+                    if index == i {
+                        *corner = vertex;
+                    }
+                }
+            }
+        }
+
+        let mut triangles = Vec::new();
+        for face in corners.iter() {
+            for i in range(1, face.len() - 1) {
+                triangles.push((face[0], face[i], face[i + 1]));
+            }
+        }
+        // Vertices no face uses still need some normal
+        let normals = normals.into_iter().map(|n| {
+            if n.length2() > 0.0 { n } else { Vector3::unit_z() }
+        }).collect();
+        TriangleMesh::new(vertices, triangles, bvh_params).with_normals(normals)
+    }
+}
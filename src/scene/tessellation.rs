@@ -0,0 +1,69 @@
+/// A mesh being split into smaller triangles by halving edges, for meshes
+/// that are refined as they're loaded. As long as midpoints are shared
+/// between the triangles on either side of an edge, and whether an edge is
+/// split only depends on the edge itself, neighbouring triangles always
+/// agree and no cracks open up between them.
+pub trait EdgeSplitter {
+    /// Whether the edge between two vertices has to be split.
+    fn needs_split(&self, a: uint, b: uint) -> bool;
+
+    /// The vertex halfway along the edge between two vertices, which
+    /// should be the same one every time it's asked for.
+    fn midpoint(&mut self, a: uint, b: uint) -> uint;
+
+    /// Adds a finished triangle.
+    fn add_triangle(&mut self, a: uint, b: uint, c: uint);
+}
+
+/// Splits triangle abc until none of its edges need splitting, or its
+/// edges have been halved `max_depth` times over, which is only reached by
+/// broken input like infinite coordinates.
+pub fn split_triangle<S: EdgeSplitter>(splitter: &mut S, a: uint, b: uint, c: uint,
+                                       max_depth: uint) {
+    split(splitter, a, b, c, max_depth, 0);
+}
+
+fn split<S: EdgeSplitter>(splitter: &mut S, a: uint, b: uint, c: uint,
+                          max_depth: uint, depth: uint) {
+    if depth >= max_depth {
+        splitter.add_triangle(a, b, c);
+        return;
+    }
+    match (splitter.needs_split(a, b), splitter.needs_split(b, c), splitter.needs_split(c, a)) {
+        (false, false, false) => splitter.add_triangle(a, b, c),
+        (true, true, true) => {
+            let ab = splitter.midpoint(a, b);
+            let bc = splitter.midpoint(b, c);
+            let ca = splitter.midpoint(c, a);
+            split(splitter, a, ab, ca, max_depth, depth + 1);
+            split(splitter, ab, b, bc, max_depth, depth + 1);
+            split(splitter, ca, bc, c, max_depth, depth + 1);
+            split(splitter, ab, bc, ca, max_depth, depth + 1);
+        },
+        // Turn the triangle around so the split edges come first
+        (true, false, false) => split_one(splitter, a, b, c, max_depth, depth),
+        (false, true, false) => split_one(splitter, b, c, a, max_depth, depth),
+        (false, false, true) => split_one(splitter, c, a, b, max_depth, depth),
+        (true, true, false) => split_two(splitter, a, b, c, max_depth, depth),
+        (false, true, true) => split_two(splitter, b, c, a, max_depth, depth),
+        (true, false, true) => split_two(splitter, c, a, b, max_depth, depth)
+    }
+}
+
+/// Splits edge ab in two.
+fn split_one<S: EdgeSplitter>(splitter: &mut S, a: uint, b: uint, c: uint,
+                              max_depth: uint, depth: uint) {
+    let ab = splitter.midpoint(a, b);
+    split(splitter, a, ab, c, max_depth, depth + 1);
+    split(splitter, ab, b, c, max_depth, depth + 1);
+}
+
+/// Splits edges ab and bc in two.
+fn split_two<S: EdgeSplitter>(splitter: &mut S, a: uint, b: uint, c: uint,
+                              max_depth: uint, depth: uint) {
+    let ab = splitter.midpoint(a, b);
+    let bc = splitter.midpoint(b, c);
+    split(splitter, ab, b, bc, max_depth, depth + 1);
+    split(splitter, a, ab, bc, max_depth, depth + 1);
+    split(splitter, a, bc, c, max_depth, depth + 1);
+}
@@ -0,0 +1,65 @@
+use image_types::Color;
+
+/// Which row of an image v = 0 is at.
+#[deriving(Clone, Show, PartialEq)]
+pub enum VOrigin {
+    /// Like glTF textures
+    TopRow,
+    /// Like height maps, where v goes up like y does
+    BottomRow
+}
+
+/// An RGB image looked up by texture coordinates, repeating outside 0 to 1.
+/// The first row of pixels is at the top, at v = 0, like glTF expects.
+pub struct Texture {
+    width: uint,
+    height: uint,
+    pixels: Vec<Color>
+}
+
+impl Texture {
+    pub fn new(width: uint, height: uint, pixels: Vec<Color>) -> Texture {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            fail!("Texture has {} pixels instead of {}x{}", pixels.len(), width, height);
+        }
+        Texture { width: width,
+                  height: height,
+                  pixels: pixels }
+    }
+
+    /// The color at the given texture coordinates, blended between the four
+    /// nearest pixels.
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let taps = bilinear_taps(self.width, self.height, u, v, TopRow);
+        taps.iter().fold(Color { r: 0.0, g: 0.0, b: 0.0 }, |sum, &(index, weight)| {
+            sum.add_c(&self.pixels[index].mul_s(weight))
+        })
+    }
+}
+
+/// The four pixels nearest to the given texture coordinates in an image
+/// that repeats outside 0 to 1, as their indices and the weight to blend
+/// each by. Shared by every image lookup, so they all filter alike.
+pub fn bilinear_taps(width: uint, height: uint, u: f32, v: f32,
+                     origin: VOrigin) -> [(uint, f32), ..4] {
+    let v = match origin {
+        TopRow => v,
+        BottomRow => 1.0 - v
+    };
+    // Pixel centers are half a pixel in from their edges
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as int, y0 as int);
+    let index = |x: int, y: int| wrap(y, height) * width + wrap(x, width);
+    [(index(x0, y0), (1.0 - fx) * (1.0 - fy)),
+     (index(x0 + 1, y0), fx * (1.0 - fy)),
+     (index(x0, y0 + 1), (1.0 - fx) * fy),
+     (index(x0 + 1, y0 + 1), fx * fy)]
+}
+
+fn wrap(i: int, size: uint) -> uint {
+    let size = size as int;
+    (((i % size) + size) % size) as uint
+}
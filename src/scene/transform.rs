@@ -0,0 +1,97 @@
+use cgmath::{EuclideanVector, Matrix, Vector};
+use cgmath::{Matrix4, Vector3, Vector4, Point3, Ray3, Ray};
+use scene::{Intersectable, Hit, BoundingBox, Material};
+use scene::util::gamma;
+
+/// Places any geometry in the scene with an affine transform, by
+/// intersecting it with rays moved into its own space.
+pub struct Transformed {
+    object: Box<Intersectable+Send+Sync+'static>,
+    to_world: Matrix4<f32>,
+    to_object: Matrix4<f32>
+}
+
+fn transform_point(matrix: &Matrix4<f32>, point: &Point3<f32>) -> Point3<f32> {
+    let v = matrix.mul_v(&Vector4::new(point.x, point.y, point.z, 1.0));
+    Point3::new(v.x, v.y, v.z)
+}
+
+fn transform_vector(matrix: &Matrix4<f32>, vector: &Vector3<f32>) -> Vector3<f32> {
+    let v = matrix.mul_v(&Vector4::new(vector.x, vector.y, vector.z, 0.0));
+    Vector3::new(v.x, v.y, v.z)
+}
+
+fn abs_vector4(v: &Vector4<f32>) -> Vector4<f32> {
+    Vector4::new(v.x.abs(), v.y.abs(), v.z.abs(), v.w.abs())
+}
+
+/// A point moved by `matrix`, with error bounds covering both the error
+/// the point already had and the rounding from transforming it.
+fn transform_point_error(matrix: &Matrix4<f32>, point: &Point3<f32>,
+                         error: &Vector3<f32>) -> (Point3<f32>, Vector3<f32>) {
+    let abs = Matrix4 { x: abs_vector4(&matrix.x),
+                        y: abs_vector4(&matrix.y),
+                        z: abs_vector4(&matrix.z),
+                        w: abs_vector4(&matrix.w) };
+    let rounding = abs.mul_v(&Vector4::new(point.x.abs(), point.y.abs(), point.z.abs(), 1.0))
+                      .mul_s(gamma(3.0));
+    let carried = abs.mul_v(&Vector4::new(error.x, error.y, error.z, 0.0))
+                     .mul_s(gamma(3.0) + 1.0);
+    let total = rounding.add_v(&carried);
+    (transform_point(matrix, point), Vector3::new(total.x, total.y, total.z))
+}
+
+impl Transformed {
+    pub fn new(object: Box<Intersectable+Send+Sync+'static>, to_world: Matrix4<f32>) -> Transformed {
+        let to_object = to_world.invert()
+                                .expect("Object transform can't be inverted");
+        Transformed { object: object,
+                      to_world: to_world,
+                      to_object: to_object }
+    }
+
+    /// The ray in object space, with its direction normalized again, and
+    /// how many object space units one world space unit along it covers.
+    fn object_ray(&self, ray: &Ray3<f32>) -> (Ray3<f32>, f32) {
+        let origin = transform_point(&self.to_object, &ray.origin);
+        let direction = transform_vector(&self.to_object, &ray.direction);
+        let scale = direction.length();
+        (Ray::new(origin, direction.div_s(scale)), scale)
+    }
+}
+
+impl Intersectable for Transformed {
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let (object_ray, scale) = self.object_ray(ray);
+        self.object.intersection(&object_ray, tmin * scale, tmax * scale).map(|hit| {
+            // Normals transform by the inverse transpose to stay
+            // perpendicular to the surface under non-uniform scaling,
+            // which also keeps them on the same side of it.
+            let normal_matrix = self.to_object.transpose();
+            let (point, error) = transform_point_error(&self.to_world, &hit.point, &hit.error);
+            Hit { distance: hit.distance / scale,
+                  point: point,
+                  error: error,
+                  geometric_normal: transform_vector(&normal_matrix, &hit.geometric_normal).normalize(),
+                  shading_normal: transform_vector(&normal_matrix, &hit.shading_normal).normalize(),
+                  dpdu: transform_vector(&self.to_world, &hit.dpdu),
+                  dpdv: transform_vector(&self.to_world, &hit.dpdv),
+                  ..hit }
+        })
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.object.bounds().transform(&self.to_world)
+    }
+
+    fn spans(&self, ray: &Ray3<f32>) -> Option<Vec<(f32, f32)>> {
+        let (object_ray, scale) = self.object_ray(ray);
+        self.object.spans(&object_ray).map(|spans| {
+            spans.into_iter().map(|(enter, exit)| (enter / scale, exit / scale)).collect()
+        })
+    }
+
+    fn material<'a>(&'a self, hit: &Hit) -> Option<&'a Material> {
+        self.object.material(hit)
+    }
+}
@@ -1,4 +1,6 @@
-use cgmath::{EuclideanVector, Vector, Vector3};
+use cgmath::{EuclideanVector, Point, Vector, Vector3, Point3};
+use cgmath::dot;
+use std::mem::transmute;
 use std::rand;
 use std::rand::Rng;
 use std::rand::distributions::{Normal, IndependentSample};
@@ -37,3 +39,92 @@ pub fn saturate(x: f32) -> f32 {
         x            => x
     }
 }
+
+pub fn component(vector: &Vector3<f32>, axis: uint) -> f32 {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z
+    }
+}
+
+/// Rotates `vector` by Euler angles in degrees, around the x axis first,
+/// then y, then z.
+pub fn rotate_euler(vector: &Vector3<f32>, angles: &Vector3<f32>) -> Vector3<f32> {
+    let (sx, cx) = (angles.x*PI/180.0).sin_cos();
+    let (sy, cy) = (angles.y*PI/180.0).sin_cos();
+    let (sz, cz) = (angles.z*PI/180.0).sin_cos();
+    let v = Vector3::new(vector.x,
+                         vector.y*cx - vector.z*sx,
+                         vector.y*sx + vector.z*cx);
+    let v = Vector3::new(v.x*cy + v.z*sy,
+                         v.y,
+                         -v.x*sy + v.z*cy);
+    Vector3::new(v.x*cz - v.y*sz,
+                 v.x*sz + v.y*cz,
+                 v.z)
+}
+
+/// Two unit vectors that form an orthonormal basis with the unit vector `n`.
+/// See Duff et al., "Building an Orthonormal Basis, Revisited" (JCGT 2017)
+pub fn orthonormal_basis(n: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let sign = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (Vector3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+     Vector3::new(b, sign + n.y * n.y * a, -n.y))
+}
+
+/// The most a single rounded float operation can be off by, relative to
+/// the exact result.
+const MACHINE_EPSILON: f32 = ::std::f32::EPSILON * 0.5;
+
+/// A conservative bound on the relative error after `n` rounded float
+/// operations in a row.
+/// See Pharr, Jakob and Humphreys, "Physically Based Rendering" (3rd
+/// edition, 2016), section 3.9
+pub fn gamma(n: f32) -> f32 {
+    (n * MACHINE_EPSILON) / (1.0 - n * MACHINE_EPSILON)
+}
+
+pub fn abs_vector(v: &Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(v.x.abs(), v.y.abs(), v.z.abs())
+}
+
+/// The next float after `x` towards positive infinity.
+pub fn next_float_up(x: f32) -> f32 {
+    if x.is_infinite() && x > 0.0 {
+        return x;
+    }
+    // -0 and 0 have different bits, but both step up to the same float
+    let x = if x == 0.0 { 0.0f32 } else { x };
+    let bits = unsafe { transmute::<f32, u32>(x) };
+    let bits = if x >= 0.0 { bits + 1 } else { bits - 1 };
+    unsafe { transmute::<u32, f32>(bits) }
+}
+
+pub fn next_float_down(x: f32) -> f32 {
+    -next_float_up(-x)
+}
+
+/// Moves a point on a surface off it to the side `normal` faces, past
+/// where the rounding `error` in its coordinates could have put it on
+/// the other side, so rays leaving from it can't hit the surface again.
+/// The distance scales with the coordinates, so it works as well for
+/// scenes in millimetres as in kilometres.
+pub fn offset_origin(point: &Point3<f32>, error: &Vector3<f32>, normal: &Vector3<f32>) -> Point3<f32> {
+    let distance = dot(abs_vector(normal), *error);
+    let offset = normal.mul_s(distance);
+    let moved = point.add_v(&offset);
+    // Round away from the point too, so the offset can't round to nothing
+    let away = |coordinate: f32, offset: f32| {
+        if offset > 0.0 {
+            next_float_up(coordinate)
+        } else if offset < 0.0 {
+            next_float_down(coordinate)
+        } else {
+            coordinate
+        }
+    };
+    Point3::new(away(moved.x, offset.x), away(moved.y, offset.y), away(moved.z, offset.z))
+}
@@ -0,0 +1,177 @@
+use std::cmp::min;
+use std::f32::INFINITY;
+use std::sync::Arc;
+use cgmath::{Point, Vector};
+use cgmath::{Vector3, Point3, Ray3};
+use scene::{Intersectable, Hit, BoundingBox, Material};
+use scene::accelerator::inverse_direction;
+use scene::scene_objects::unit;
+use scene::util::component;
+use image_types::Color;
+
+/// A dense grid of solid cubes, like voxel art, hit by walking the cells
+/// along the ray one at a time. Each voxel is an index into a palette of
+/// up to 255 colors, with 0 for empty, so a voxel only takes a byte. Each
+/// palette color becomes a copy of the object's material tinted by it, so
+/// a white material shows the palette colors as they are.
+/// See Amanatides and Woo, "A Fast Voxel Traversal Algorithm for Ray
+/// Tracing" (1987)
+pub struct Voxels {
+    /// Palette indices, x fastest, then y, then z
+    voxels: Vec<u8>,
+    size: [uint, ..3],
+    /// The material for each palette index, where index 0 is never used
+    materials: Vec<Arc<Material>>,
+    origin: Point3<f32>,
+    voxel_size: f32,
+    bounds: BoundingBox
+}
+
+impl Voxels {
+    /// `size` voxels along x, y and z, each `voxel_size` wide, with the
+    /// corner of the first at `origin`. `material` is tinted by each color
+    /// in `palette`.
+    pub fn new(voxels: Vec<u8>, size: (uint, uint, uint), palette: Vec<Color>,
+               material: &Material, origin: Point3<f32>, voxel_size: f32) -> Voxels {
+        let (x, y, z) = size;
+        if x == 0 || y == 0 || z == 0 {
+            fail!("Voxels need at least one voxel along each axis, not {}x{}x{}", x, y, z);
+        }
+        if voxels.len() != x * y * z {
+            fail!("Voxels have {} voxels instead of {}x{}x{}", voxels.len(), x, y, z);
+        }
+        if palette.len() != 256 {
+            fail!("Voxel palette has {} colors instead of 256", palette.len());
+        }
+        if !(voxel_size > 0.0) {
+            fail!("Voxel size has to be more than 0, not {}", voxel_size);
+        }
+        let materials = palette.iter().map(|color| {
+            Arc::new(Material { color: color.mul_c(&material.color), ..material.clone() })
+        }).collect();
+        let far = origin.add_v(&Vector3::new(x as f32, y as f32, z as f32).mul_s(voxel_size));
+        Voxels { voxels: voxels,
+                 size: [x, y, z],
+                 materials: materials,
+                 origin: origin,
+                 voxel_size: voxel_size,
+                 bounds: BoundingBox::new(&origin, &far) }
+    }
+
+    fn index(&self, cell: &[int, ..3]) -> uint {
+        (cell[2] as uint * self.size[1] + cell[1] as uint) * self.size[0] + cell[0] as uint
+    }
+
+    /// A hit on the face of voxel `index` facing `normal`.
+    fn hit(&self, ray: &Ray3<f32>, distance: f32, index: uint, axis: uint,
+           normal: Vector3<f32>) -> Hit {
+        // u and v go across the face, along the next two axes around
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let local = ray.origin.add_v(&ray.direction.mul_s(distance))
+                       .sub_p(&self.origin).div_s(self.voxel_size);
+        let (u, v) = (component(&local, u_axis), component(&local, v_axis));
+        Hit::new(ray, distance, normal, (u - u.floor(), v - v.floor()),
+                 unit(u_axis).mul_s(self.voxel_size), unit(v_axis).mul_s(self.voxel_size))
+            .with_primitive(index)
+    }
+}
+
+impl Intersectable for Voxels {
+    /// u and v go from 0 to 1 across each face of a voxel.
+    fn intersection(&self, ray: &Ray3<f32>, tmin: f32, tmax: f32) -> Option<Hit> {
+        let inverse = inverse_direction(ray);
+        let exit = match self.bounds.hit(&ray.origin, &inverse, tmax) {
+            Some((_, exit)) => exit,
+            None => return None
+        };
+
+        // Where the ray enters the grid, and through which side
+        let mut enter = -INFINITY;
+        let mut axis = 0;
+        for a in range(0u, 3) {
+            let (o, inverse) = (component(&ray.origin.to_vec(), a), component(&inverse, a));
+            let t0 = (component(&self.bounds.min.to_vec(), a) - o) * inverse;
+            let t1 = (component(&self.bounds.max.to_vec(), a) - o) * inverse;
+            let near = t0.min(t1);
+            if near > enter {
+                enter = near;
+                axis = a;
+            }
+        }
+        let start = enter.max(tmin);
+        if start > exit {
+            return None;
+        }
+
+        let point = ray.origin.add_v(&ray.direction.mul_s(start)).sub_p(&self.origin);
+        let mut cell = [0i, 0, 0];
+        let mut step = [0i, 0, 0];
+        let mut next_crossing = [INFINITY, INFINITY, INFINITY];
+        let mut delta = [INFINITY, INFINITY, INFINITY];
+        for a in range(0u, 3) {
+            let position = component(&point, a) / self.voxel_size;
+            let c = min(position.floor().max(0.0) as uint, self.size[a] - 1);
+            let direction = component(&ray.direction, a);
+            let inverse = component(&inverse, a);
+            cell[a] = c as int;
+            if direction > 0.0 {
+                step[a] = 1;
+                next_crossing[a] = start + ((c + 1) as f32 - position) * self.voxel_size * inverse;
+                delta[a] = self.voxel_size * inverse;
+            } else if direction < 0.0 {
+                step[a] = -1;
+                next_crossing[a] = start + (c as f32 - position) * self.voxel_size * inverse;
+                delta[a] = -self.voxel_size * inverse;
+            }
+        }
+
+        // A ray starting inside a solid voxel hits where it leaves the
+        // solid, through an empty voxel or the side of the grid.
+        let first = self.index(&cell);
+        let inside = self.voxels[first] != 0 && start > enter;
+        if self.voxels[first] != 0 && !inside {
+            let normal = unit(axis).mul_s(-step[axis] as f32);
+            return Some(self.hit(ray, start, first, axis, normal));
+        }
+
+        let mut previous = first;
+        loop {
+            let axis = if next_crossing[0] < next_crossing[1] {
+                if next_crossing[0] < next_crossing[2] { 0 } else { 2 }
+            } else {
+                if next_crossing[1] < next_crossing[2] { 1 } else { 2 }
+            };
+            let distance = next_crossing[axis];
+            if distance > tmax {
+                return None;
+            }
+            let out = unit(axis).mul_s(step[axis] as f32);
+            cell[axis] += step[axis];
+            next_crossing[axis] += delta[axis];
+            if cell[axis] < 0 || cell[axis] >= self.size[axis] as int {
+                if inside {
+                    return Some(self.hit(ray, distance, previous, axis, out));
+                }
+                return None;
+            }
+            let index = self.index(&cell);
+            let solid = self.voxels[index] != 0;
+            if solid && !inside {
+                return Some(self.hit(ray, distance, index, axis, -out));
+            }
+            if !solid && inside {
+                return Some(self.hit(ray, distance, previous, axis, out));
+            }
+            previous = index;
+        }
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+
+    /// The primitive of a hit is the voxel it's on.
+    fn material<'a>(&'a self, hit: &Hit) -> Option<&'a Material> {
+        Some(&*self.materials[self.voxels[hit.primitive] as uint])
+    }
+}